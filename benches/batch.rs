@@ -0,0 +1,93 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::Rng;
+
+fn bench_batch(c: &mut Criterion) {
+    let mut rng = rand::rng();
+    for num_keys in [100_000, 1_000_000, 10_000_000] {
+        let num_targets = 10_000;
+
+        // Generate random keys.
+        let keys: Vec<u64> = (0..num_keys)
+            .map(|_| rng.random_range(0..=u64::MAX))
+            .collect();
+
+        let mut tree = veb_tree::VebTreeMap::<u64, u64>::new();
+        for k in &keys {
+            tree.insert(*k, *k);
+        }
+
+        // Look up a mix of keys that are in the tree and keys that aren't.
+        let target_keys: Vec<u64> = (0..num_targets)
+            .map(|i| {
+                if i % 2 == 0 {
+                    keys[rng.random_range(0..keys.len())]
+                } else {
+                    rng.random_range(0..=u64::MAX)
+                }
+            })
+            .collect();
+
+        let mut group = c.benchmark_group("get_batch");
+        group.bench_with_input(
+            BenchmarkId::new("get", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    for target in &target_keys {
+                        black_box(tree.get(target));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("get_many", num_keys),
+            &num_keys,
+            |b, _i| b.iter(|| black_box(tree.get_many(&target_keys))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("get_many_without_prefetch", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    black_box(tree.get_many_without_prefetch(&target_keys))
+                })
+            },
+        );
+        group.finish();
+
+        let mut group = c.benchmark_group("successor_batch");
+        group.bench_with_input(
+            BenchmarkId::new("successor", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    for target in &target_keys {
+                        black_box(tree.successor(target));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("successor_many", num_keys),
+            &num_keys,
+            |b, _i| b.iter(|| black_box(tree.successor_many(&target_keys))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("successor_many_without_prefetch", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    black_box(
+                        tree.successor_many_without_prefetch(&target_keys),
+                    )
+                })
+            },
+        );
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_batch);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+use std::sync::Mutex;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::Rng;
+use veb_tree::{ConcurrentVebTreeMap, VebTreeMap};
+
+const THREADS: usize = 4;
+
+fn bench_concurrent(c: &mut Criterion) {
+    let mut rng = rand::rng();
+    for num_keys in [10_000, 100_000] {
+        let keys: Vec<Vec<u64>> = (0..THREADS)
+            .map(|_| {
+                (0..num_keys / THREADS)
+                    .map(|_| rng.random_range(0..u64::MAX))
+                    .collect()
+            })
+            .collect();
+
+        let mut group = c.benchmark_group("concurrent_insert");
+        group.bench_with_input(
+            BenchmarkId::new("mutex", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    let tree = Mutex::new(VebTreeMap::<u64, u64>::new());
+                    std::thread::scope(|s| {
+                        for keys in &keys {
+                            let tree = &tree;
+                            s.spawn(move || {
+                                for k in keys {
+                                    tree.lock().unwrap().insert(*k, *k);
+                                }
+                            });
+                        }
+                    });
+                    tree
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("sharded", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    let tree = ConcurrentVebTreeMap::<u64, u64>::new();
+                    std::thread::scope(|s| {
+                        for keys in &keys {
+                            let tree = &tree;
+                            s.spawn(move || {
+                                for k in keys {
+                                    tree.insert(*k, *k);
+                                }
+                            });
+                        }
+                    });
+                    tree
+                })
+            },
+        );
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_concurrent);
+criterion_main!(benches);
@@ -0,0 +1,85 @@
+use std::hint::black_box;
+
+use criterion::{
+    BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main,
+};
+use rand::Rng;
+use veb_tree::{Finger, VebTreeMap};
+
+fn bench_nearly_sorted(c: &mut Criterion) {
+    let mut rng = rand::rng();
+    for num_keys in [100_000, 1_000_000] {
+        // Timestamps in milliseconds that arrive slightly out of order.
+        let keys: Vec<u64> = (0..num_keys)
+            .map(|i| 1_700_000_000_000 + i * 1000 + rng.random_range(0..3000))
+            .collect();
+
+        let mut group = c.benchmark_group("insert_nearly_sorted");
+        group.bench_with_input(
+            BenchmarkId::new("insert", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter_batched_ref(
+                    VebTreeMap::<u64, u64>::new,
+                    |tree| {
+                        for k in &keys {
+                            tree.insert(*k, *k);
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("insert_near", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter_batched_ref(
+                    VebTreeMap::<u64, u64>::new,
+                    |tree| {
+                        let mut finger = Finger::new();
+                        for k in &keys {
+                            tree.insert_near(&mut finger, *k, *k);
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        group.finish();
+
+        let mut tree = VebTreeMap::<u64, u64>::new();
+        for k in &keys {
+            tree.insert(*k, *k);
+        }
+
+        let mut group = c.benchmark_group("successor_nearly_sorted");
+        group.bench_with_input(
+            BenchmarkId::new("successor", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    for k in &keys {
+                        black_box(tree.successor(k));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("successor_near", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    let mut finger = Finger::new();
+                    for k in &keys {
+                        black_box(tree.successor_near(&mut finger, k));
+                    }
+                })
+            },
+        );
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_nearly_sorted);
+criterion_main!(benches);
@@ -0,0 +1,167 @@
+use std::{collections::BTreeMap, hint::black_box};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::Rng;
+
+macro_rules! bench_fixed_depth_key {
+    ($fn_name: ident, $name: expr, $key_ty: ty, $max_key: expr) => {
+        fn $fn_name(c: &mut Criterion) {
+            let mut rng = rand::rng();
+
+            for num_keys in [10_000, 100_000, 1_000_000] {
+                let num_targets = 10_000;
+
+                // Generate random keys.
+                let keys: Vec<$key_ty> = (0..num_keys)
+                    .map(|_| rng.random_range(0..=$max_key))
+                    .collect();
+
+                // Insert the same keys into each implementation.
+                let mut tree = veb_tree::VebTreeMap::<$key_ty, u64>::new();
+                for k in &keys {
+                    tree.insert(*k, *k as u64);
+                }
+
+                let mut b_tree: BTreeMap<$key_ty, u64> = BTreeMap::new();
+                for k in &keys {
+                    b_tree.insert(*k, *k as u64);
+                }
+
+                // Generate random keys to search for.
+                let target_keys: Vec<$key_ty> = (0..num_targets)
+                    .map(|_| rng.random_range(0..=$max_key))
+                    .collect();
+
+                let mut group = c.benchmark_group(format!("get_{}", $name));
+                group.bench_with_input(
+                    BenchmarkId::new("VebTreeMap generic", num_keys),
+                    &num_keys,
+                    |b, _i| {
+                        b.iter(|| {
+                            for target in &target_keys {
+                                black_box(tree.get(target));
+                            }
+                        })
+                    },
+                );
+                group.bench_with_input(
+                    BenchmarkId::new("VebTreeMap fixed", num_keys),
+                    &num_keys,
+                    |b, _i| {
+                        b.iter(|| {
+                            for target in &target_keys {
+                                black_box(tree.get_fixed(target));
+                            }
+                        })
+                    },
+                );
+                group.bench_with_input(
+                    BenchmarkId::new("BTreeMap", num_keys),
+                    &num_keys,
+                    |b, _i| {
+                        b.iter(|| {
+                            for target in &target_keys {
+                                black_box(b_tree.get(target));
+                            }
+                        })
+                    },
+                );
+                group.finish();
+
+                let mut group =
+                    c.benchmark_group(format!("successor_{}", $name));
+                group.bench_with_input(
+                    BenchmarkId::new("VebTreeMap generic", num_keys),
+                    &num_keys,
+                    |b, _i| {
+                        b.iter(|| {
+                            for target in &target_keys {
+                                black_box(tree.successor(target));
+                            }
+                        })
+                    },
+                );
+                group.bench_with_input(
+                    BenchmarkId::new("VebTreeMap fixed", num_keys),
+                    &num_keys,
+                    |b, _i| {
+                        b.iter(|| {
+                            for target in &target_keys {
+                                black_box(tree.successor_fixed(target));
+                            }
+                        })
+                    },
+                );
+                group.bench_with_input(
+                    BenchmarkId::new("BTreeMap", num_keys),
+                    &num_keys,
+                    |b, _i| {
+                        b.iter(|| {
+                            for target in &target_keys {
+                                black_box(b_tree.range(target..).next());
+                            }
+                        })
+                    },
+                );
+                group.finish();
+
+                // Inserting starts from a copy of the tree each time, so
+                // every insert has the same amount of work to do.
+                let mut group = c.benchmark_group(format!("insert_{}", $name));
+                group.bench_with_input(
+                    BenchmarkId::new("VebTreeMap generic", num_keys),
+                    &num_keys,
+                    |b, _i| {
+                        b.iter_batched_ref(
+                            || tree.clone(),
+                            |tree| {
+                                for target in &target_keys {
+                                    tree.insert(*target, 0);
+                                }
+                            },
+                            criterion::BatchSize::LargeInput,
+                        )
+                    },
+                );
+                group.bench_with_input(
+                    BenchmarkId::new("VebTreeMap fixed", num_keys),
+                    &num_keys,
+                    |b, _i| {
+                        b.iter_batched_ref(
+                            || tree.clone(),
+                            |tree| {
+                                for target in &target_keys {
+                                    tree.insert_fixed(*target, 0);
+                                }
+                            },
+                            criterion::BatchSize::LargeInput,
+                        )
+                    },
+                );
+                group.bench_with_input(
+                    BenchmarkId::new("BTreeMap", num_keys),
+                    &num_keys,
+                    |b, _i| {
+                        b.iter_batched_ref(
+                            || b_tree.clone(),
+                            |b_tree| {
+                                for target in &target_keys {
+                                    b_tree.insert(*target, 0);
+                                }
+                            },
+                            criterion::BatchSize::LargeInput,
+                        )
+                    },
+                );
+                group.finish();
+            }
+        }
+    };
+}
+
+bench_fixed_depth_key!(bench_fixed_depth_u32, "u32", u32, u32::MAX);
+
+bench_fixed_depth_key!(bench_fixed_depth_u64, "u64", u64, u64::MAX);
+
+criterion_group!(benches, bench_fixed_depth_u32, bench_fixed_depth_u64);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::Rng;
+use veb_tree::{FrozenVebTree, VebTreeMap};
+
+fn bench_frozen(c: &mut Criterion) {
+    let mut rng = rand::rng();
+    for num_keys in [10_000, 1_000_000] {
+        let mut tree = VebTreeMap::<u64, u64>::new();
+        for _ in 0..num_keys {
+            let k = rng.random_range(0..u64::MAX);
+            tree.insert(k, k);
+        }
+        let frozen = FrozenVebTree::from_map(&tree);
+        let keys: Vec<u64> = (0..100_000)
+            .map(|_| rng.random_range(0..u64::MAX))
+            .collect();
+
+        let mut group = c.benchmark_group("frozen_successor");
+        group.bench_with_input(
+            BenchmarkId::new("map", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    for k in &keys {
+                        black_box(tree.successor(k));
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("frozen", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    for k in &keys {
+                        black_box(frozen.successor(k));
+                    }
+                })
+            },
+        );
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_frozen);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::Rng;
+use rayon::prelude::*;
+use veb_tree::VebTreeMap;
+
+fn bench_parallel(c: &mut Criterion) {
+    let mut rng = rand::rng();
+    for num_keys in [100_000, 1_000_000] {
+        let mut keys: Vec<u64> = (0..num_keys)
+            .map(|_| rng.random_range(0..u64::MAX))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        let entries: Vec<(u64, u64)> = keys.iter().map(|k| (*k, *k)).collect();
+
+        let mut group = c.benchmark_group("build_sorted");
+        group.bench_with_input(
+            BenchmarkId::new("sequential", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| VebTreeMap::from_sorted_iter(entries.iter().copied()))
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("parallel", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| VebTreeMap::from_sorted_par_iter(entries.clone()))
+            },
+        );
+        group.finish();
+
+        let tree = VebTreeMap::from_sorted_iter(entries.iter().copied());
+        c.bench_with_input(
+            BenchmarkId::new("par_iter_max", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    black_box(
+                        tree.par_iter().map(|(_, v)| *v).reduce(|| 0, u64::max),
+                    )
+                })
+            },
+        );
+    }
+}
+
+criterion_group!(benches, bench_parallel);
+criterion_main!(benches);
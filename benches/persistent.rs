@@ -0,0 +1,53 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::Rng;
+use veb_tree::{PersistentVebTreeMap, VebTreeMap};
+
+fn bench_persistent(c: &mut Criterion) {
+    let mut rng = rand::rng();
+    for num_keys in [10_000, 100_000] {
+        let mut tree = VebTreeMap::<u64, u64>::new();
+        let mut persistent = PersistentVebTreeMap::<u64, u64>::new();
+        for _ in 0..num_keys {
+            let k = rng.random_range(0..u64::MAX);
+            tree.insert(k, k);
+            persistent.insert(k, k);
+        }
+        let keys: Vec<u64> =
+            (0..100).map(|_| rng.random_range(0..u64::MAX)).collect();
+
+        // Take a snapshot for readers after each insert.
+        let mut group = c.benchmark_group("snapshot_insert");
+        group.bench_with_input(
+            BenchmarkId::new("map", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    let mut tree = tree.clone();
+                    for k in &keys {
+                        tree.insert(*k, *k);
+                        black_box(tree.clone());
+                    }
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("persistent", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    let mut persistent = persistent.clone();
+                    for k in &keys {
+                        persistent.insert(*k, *k);
+                        black_box(persistent.clone());
+                    }
+                })
+            },
+        );
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_persistent);
+criterion_main!(benches);
@@ -0,0 +1,50 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use rand::Rng;
+use veb_tree::{LittleEndianCodec, VebTreeMap};
+
+fn bench_load(c: &mut Criterion) {
+    let mut rng = rand::rng();
+    for num_keys in [10_000, 100_000, 1_000_000] {
+        let keys: Vec<u64> = (0..num_keys)
+            .map(|_| rng.random_range(0..u64::MAX))
+            .collect();
+        let mut tree = VebTreeMap::<u64, u64>::new();
+        for k in &keys {
+            tree.insert(*k, *k);
+        }
+        let mut bytes = Vec::new();
+        tree.write_to(&mut bytes, &LittleEndianCodec).unwrap();
+
+        let mut group = c.benchmark_group("load");
+        group.bench_with_input(
+            BenchmarkId::new("insert", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    let mut tree = VebTreeMap::<u64, u64>::new();
+                    for k in &keys {
+                        tree.insert(*k, *k);
+                    }
+                    tree
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("read_from", num_keys),
+            &num_keys,
+            |b, _i| {
+                b.iter(|| {
+                    VebTreeMap::<u64, u64>::read_from(
+                        bytes.as_slice(),
+                        &LittleEndianCodec,
+                    )
+                    .unwrap()
+                })
+            },
+        );
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_load);
+criterion_main!(benches);
@@ -0,0 +1,1626 @@
+//! Arena storage for the nodes of a tree.
+//!
+//! Every node lives in a `Vec` and refers to other nodes by index instead of
+//! owning them.  The clusters of all the nodes in an arena are found through a
+//! single table keyed by the parent node and the cluster number, so a node
+//! doesn't own any heap memory of its own.  The table is a hash map, or an
+//! ordered map without the `std` feature.  Cloning an arena copies a few
+//! flat buffers, and clearing it frees everything at once.
+//!
+//! The values of a summary are the clusters it summarizes, so they live in a
+//! second arena that's created lazily the first time a node needs a summary.
+//! Summaries of summaries share that second arena's own summary arena, and so
+//! on, which bounds the chain of arenas by the depth of the tree.
+//!
+//! Within a node, neither the min nor the max is stored in a cluster, and
+//! clusters are released as soon as they become empty.  A node with a single
+//! entry only has a min.
+
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::hash::Hash;
+use core::mem::{replace, swap};
+use core::ops::Add;
+use core::sync::atomic;
+
+use alloc::collections::{BTreeMap, TryReserveError};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[cfg(feature = "rayon")]
+use ::rayon::prelude::*;
+
+use crate::instrument::Counter;
+#[cfg(feature = "stats")]
+use crate::instrument::{Counters, Counts};
+use crate::{ArenaStats, InvariantViolation, VebKey};
+
+/// Index of a node within an [`Arena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct NodeId(u32);
+
+impl NodeId {
+    #[inline]
+    fn index(self) -> usize {
+        usize::try_from(self.0).expect("node index must fit in usize")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Node<K, V>
+where
+    K: VebKey,
+{
+    /// The minimum entry.
+    pub(crate) min: Option<(K, V)>,
+    /// The maximum entry, only when it differs from the min.
+    pub(crate) max: Option<(K, V)>,
+    /// The summary of non-empty clusters, in the summary arena.  It maps each
+    /// cluster number to the cluster's node.
+    pub(crate) summary: Option<NodeId>,
+    pub(crate) cluster_size: K::Size,
+    #[cfg(any(test, feature = "safety_checks"))]
+    pub(crate) max_size: K::Size,
+}
+
+impl<K, V> Node<K, V>
+where
+    K: VebKey,
+{
+    fn with_max_size(max_size: K::Size) -> Node<K, V> {
+        Node {
+            min: None,
+            max: None,
+            summary: None,
+            cluster_size: K::cluster_size(&max_size),
+            #[cfg(any(test, feature = "safety_checks"))]
+            max_size,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.min.is_none()
+    }
+
+    /// The minimum entry.
+    #[inline]
+    pub(crate) fn first(&self) -> Option<&(K, V)> {
+        self.min.as_ref()
+    }
+
+    /// The maximum entry, which is the min when there's only one.
+    #[inline]
+    pub(crate) fn last(&self) -> Option<&(K, V)> {
+        self.max.as_ref().or(self.min.as_ref())
+    }
+}
+
+impl<K, V> Node<K, V>
+where
+    K: VebKey + Ord + Debug,
+{
+    #[inline]
+    pub(crate) fn assert_in_universe(&self, _key: &K) {
+        #[cfg(any(test, feature = "safety_checks"))]
+        assert!(
+            *_key <= K::size_to_key(&self.max_size),
+            "key must be representable by cluster's maximum size: key={:?}, max_size={:?}, size_to_key={:?}",
+            _key,
+            self.max_size,
+            K::size_to_key(&self.max_size)
+        );
+    }
+}
+
+/// Where an entry goes after inserting it into a node's min and max.
+pub(crate) enum Placement<K, V> {
+    /// The entry was stored in the min or max, possibly replacing the value.
+    Done(Option<V>),
+    /// This entry, which may have been swapped out of the min or max, belongs
+    /// in a cluster.
+    Cluster(K, V),
+}
+
+impl<K, V> Node<K, V>
+where
+    K: VebKey + Ord,
+{
+    /// Insert an entry into the min or max, or swap it with them so that the
+    /// entry left over can be inserted into a cluster.
+    pub(crate) fn insert_min_max(
+        &mut self,
+        mut key: K,
+        mut value: V,
+    ) -> Placement<K, V> {
+        let Some((min_key, min_value)) = self.min.as_mut() else {
+            // When currently empty, be lazy to prevent recursive calls.
+            self.min = Some((key, value));
+            return Placement::Done(None);
+        };
+        // If it's less than the min, swap it with the min.
+        match key.cmp(min_key) {
+            Ordering::Less => {
+                swap(min_key, &mut key);
+                swap(min_value, &mut value);
+            }
+            Ordering::Equal => {
+                return Placement::Done(Some(replace(min_value, value)));
+            }
+            Ordering::Greater => {}
+        }
+        let Some((max_key, max_value)) = self.max.as_mut() else {
+            // With a single entry, whichever key is larger becomes the max.
+            self.max = Some((key, value));
+            return Placement::Done(None);
+        };
+        // If it's greater than the max, swap it with the max.
+        match key.cmp(max_key) {
+            Ordering::Greater => {
+                swap(max_key, &mut key);
+                swap(max_value, &mut value);
+            }
+            Ordering::Equal => {
+                return Placement::Done(Some(replace(max_value, value)));
+            }
+            Ordering::Less => {}
+        }
+        Placement::Cluster(key, value)
+    }
+}
+
+/// The result of looking for a key in a single node.
+pub(crate) enum Probe<'a, K, V> {
+    Found(&'a V),
+    Absent,
+    /// The key can only be in this cluster, at this index.
+    Cluster(NodeId, K),
+}
+
+/// The result of looking for a neighbor of a key in a single node.
+pub(crate) enum Step<'a, K, V> {
+    /// The neighbor, with its key relative to the node.
+    Found(K, &'a V),
+    Absent,
+    /// The neighbor is in the cluster with number `h`, next to index `l`.
+    Cluster {
+        h: K,
+        cluster: NodeId,
+        l: K,
+    },
+}
+
+/// The high bits of a key that were consumed while descending the tree.
+#[derive(Clone)]
+pub(crate) struct Prefix<K>
+where
+    K: VebKey,
+{
+    /// The bits, and the size of the cluster they were consumed from.
+    high: Option<(K, K::Size)>,
+}
+
+impl<K> Default for Prefix<K>
+where
+    K: VebKey,
+{
+    fn default() -> Self {
+        Prefix { high: None }
+    }
+}
+
+impl<K> Prefix<K>
+where
+    K: VebKey,
+{
+    /// Descend into cluster `h` of a node with the given cluster size.
+    pub(crate) fn push(&mut self, h: K, cluster_size: &K::Size) {
+        let high = match self.high.take() {
+            None => h,
+            // The node's universe was the previous cluster size, which is
+            // twice its own cluster size, so `h` has `cluster_size` bits.
+            Some((high, _)) => high.index(h, cluster_size),
+        };
+        self.high = Some((high, cluster_size.clone()));
+    }
+
+    /// The full key from a key relative to the current node.
+    pub(crate) fn join(&self, low: K) -> K {
+        match &self.high {
+            None => low,
+            Some((high, cluster_size)) => high.index(low, cluster_size),
+        }
+    }
+}
+
+/// Source of arena versions.  Taking every version from one counter means
+/// that a version identifies an arena as well as the state of its links.
+#[cfg(target_has_atomic = "64")]
+static NEXT_VERSION: atomic::AtomicU64 = atomic::AtomicU64::new(0);
+/// Without 64-bit atomics, versions wrap after 2^32 new arenas and releases,
+/// so a finger kept unused across that many could wrongly look current.
+#[cfg(not(target_has_atomic = "64"))]
+static NEXT_VERSION: atomic::AtomicU32 = atomic::AtomicU32::new(0);
+
+#[cfg(target_has_atomic = "64")]
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, atomic::Ordering::Relaxed)
+}
+
+#[cfg(not(target_has_atomic = "64"))]
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, atomic::Ordering::Relaxed).into()
+}
+
+/// The arena of the summaries, creating it when it doesn't exist.
+fn summaries_or_insert<K>(
+    summaries: &mut Vec<Arena<K, NodeId>>,
+) -> &mut Arena<K, NodeId>
+where
+    K: VebKey,
+{
+    if summaries.is_empty() {
+        summaries.reserve_exact(1);
+        summaries.push(Arena::new());
+    }
+    &mut summaries[0]
+}
+
+/// Maps a parent node and a cluster number to the cluster's node.
+#[cfg(feature = "std")]
+type ClusterTable<K> = std::collections::HashMap<(NodeId, K), NodeId>;
+#[cfg(not(feature = "std"))]
+type ClusterTable<K> = alloc::collections::BTreeMap<(NodeId, K), NodeId>;
+
+#[derive(Debug)]
+pub(crate) struct Arena<K, V>
+where
+    K: VebKey,
+{
+    nodes: Vec<Node<K, V>>,
+    /// Maps a parent node and a cluster number to the cluster's node.
+    clusters: ClusterTable<K>,
+    /// Released nodes that can be reused.
+    free: Vec<NodeId>,
+    /// The arena of the summaries, created lazily.  It's in a `Vec` of at
+    /// most one instead of a `Box`, so that it can be allocated fallibly.
+    summaries: Vec<Arena<K, NodeId>>,
+    /// Changes whenever a node is released, which is the only time that a
+    /// link from a node to one of its clusters can stop being valid.
+    version: u64,
+    #[cfg(feature = "stats")]
+    counters: Counters,
+}
+
+impl<K, V> Clone for Arena<K, V>
+where
+    K: VebKey + Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Arena {
+            nodes: self.nodes.clone(),
+            clusters: self.clusters.clone(),
+            free: self.free.clone(),
+            summaries: self.summaries.clone(),
+            // The copy gets its own version since the two will diverge.
+            version: next_version(),
+            // The copy's tree keeps the totals, so the arena's counts, which
+            // are only compared before and after an operation, start over.
+            #[cfg(feature = "stats")]
+            counters: Counters::default(),
+        }
+    }
+}
+
+impl<K, V> Arena<K, V>
+where
+    K: VebKey,
+{
+    pub(crate) fn new() -> Arena<K, V> {
+        Arena {
+            nodes: Vec::new(),
+            clusters: ClusterTable::new(),
+            free: Vec::new(),
+            summaries: Vec::new(),
+            version: next_version(),
+            #[cfg(feature = "stats")]
+            counters: Counters::default(),
+        }
+    }
+
+    /// The version of the arena's links.  Nodes and links between them that
+    /// were seen at one version are still valid as long as the version is
+    /// the same.
+    #[inline]
+    pub(crate) fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Releases every node at once, keeping the allocated capacity.
+    pub(crate) fn clear(&mut self) {
+        self.nodes.clear();
+        self.clusters.clear();
+        self.free.clear();
+        self.version = next_version();
+        if let Some(summaries) = self.summaries.first_mut() {
+            summaries.clear();
+        }
+    }
+
+    #[inline]
+    pub(crate) fn node(&self, id: NodeId) -> &Node<K, V> {
+        &self.nodes[id.index()]
+    }
+
+    #[inline]
+    pub(crate) fn node_mut(&mut self, id: NodeId) -> &mut Node<K, V> {
+        &mut self.nodes[id.index()]
+    }
+
+    /// Count a unit of work in this arena.
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub(crate) fn count(&self, counter: Counter) {
+        self.counters.add(counter);
+    }
+
+    /// Count a unit of work in this arena, which only does anything with the
+    /// `stats` feature.
+    #[cfg(not(feature = "stats"))]
+    #[inline]
+    pub(crate) fn count(&self, _counter: Counter) {}
+
+    /// The work counted by this arena and the summary arenas below it.
+    #[cfg(feature = "stats")]
+    pub(crate) fn counts(&self) -> Counts {
+        let mut counts = self.counters.counts();
+        if let Some(summaries) = self.summaries.first() {
+            counts.add_below(summaries.counts());
+        }
+        counts
+    }
+
+    /// Ask the processor to start loading a node into the cache, so that the
+    /// next access to it doesn't wait on memory.  Only x86-64 has a prefetch
+    /// instruction here; elsewhere this does nothing.
+    #[inline]
+    pub(crate) fn prefetch(&self, id: NodeId) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use core::arch::x86_64::{_MM_HINT_T0, _mm_prefetch};
+            let node: *const Node<K, V> = &raw const self.nodes[id.index()];
+            // SAFETY: SSE is part of the x86-64 baseline, and a prefetch is
+            // only a hint that never reads or faults, whatever the address.
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(node.cast()) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = id;
+    }
+
+    /// Allocate an empty node for a universe of the given size.
+    pub(crate) fn alloc(&mut self, max_size: K::Size) -> NodeId {
+        let node = Node::with_max_size(max_size);
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id.index()] = node;
+                id
+            }
+            None => {
+                let id = NodeId(
+                    u32::try_from(self.nodes.len())
+                        .expect("number of nodes must fit in u32"),
+                );
+                self.nodes.push(node);
+                id
+            }
+        }
+    }
+
+    /// Release a node so that it can be reused.  The node must not have any
+    /// clusters or summary.
+    fn release(&mut self, id: NodeId) {
+        let node = self.node_mut(id);
+        node.min = None;
+        node.max = None;
+        node.summary = None;
+        self.free.push(id);
+        self.version = next_version();
+    }
+}
+
+impl<K, V> Arena<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+{
+    #[inline]
+    pub(crate) fn cluster(&self, id: NodeId, h: &K) -> Option<NodeId> {
+        self.count(Counter::Probes);
+        self.count(Counter::Clones);
+        self.clusters.get(&(id, h.clone())).copied()
+    }
+
+    /// Look for a key among a node's min and max, and find the cluster it
+    /// would be in otherwise.
+    #[inline]
+    pub(crate) fn probe(
+        &self,
+        id: NodeId,
+        key: &K,
+        cluster_size: &K::Size,
+        find_cluster: impl FnOnce(&K) -> Option<NodeId>,
+    ) -> Probe<'_, K, V> {
+        let node = self.node(id);
+        node.assert_in_universe(key);
+
+        // Check the min.
+        let Some((min_key, min_value)) = node.first() else {
+            return Probe::Absent;
+        };
+        match key.cmp(min_key) {
+            Ordering::Less => return Probe::Absent,
+            Ordering::Equal => return Probe::Found(min_value),
+            Ordering::Greater => {}
+        }
+        // Check the max.
+        let Some((max_key, max_value)) = node.max.as_ref() else {
+            return Probe::Absent;
+        };
+        match key.cmp(max_key) {
+            Ordering::Greater => return Probe::Absent,
+            Ordering::Equal => return Probe::Found(max_value),
+            Ordering::Less => {}
+        }
+
+        // Get the cluster.
+        match find_cluster(&key.high(cluster_size)) {
+            Some(cluster) => Probe::Cluster(cluster, key.low(cluster_size)),
+            None => Probe::Absent,
+        }
+    }
+
+    /// Lookup a key in the subtree rooted at a node.
+    pub(crate) fn get(&self, id: NodeId, key: &K) -> Option<&V> {
+        self.count(Counter::Visits);
+        let cluster_size = &self.node(id).cluster_size;
+        match self.probe(id, key, cluster_size, |h| self.cluster(id, h)) {
+            Probe::Found(value) => Some(value),
+            Probe::Absent => None,
+            Probe::Cluster(cluster, l) => self.get(cluster, &l),
+        }
+    }
+
+    /// Lookup a key in the subtree rooted at a node and get its value mutably.
+    pub(crate) fn get_mut(&mut self, id: NodeId, key: &K) -> Option<&mut V> {
+        let cluster_size = &self.node(id).cluster_size;
+        let next =
+            match self.probe(id, key, cluster_size, |h| self.cluster(id, h)) {
+                Probe::Found(_) => None,
+                Probe::Absent => return None,
+                Probe::Cluster(cluster, l) => Some((cluster, l)),
+            };
+        match next {
+            Some((cluster, l)) => self.get_mut(cluster, &l),
+            None => {
+                let node = self.node_mut(id);
+                [node.min.as_mut(), node.max.as_mut()]
+                    .into_iter()
+                    .flatten()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v)
+            }
+        }
+    }
+
+    /// Insert an entry into the subtree rooted at a node.
+    pub(crate) fn insert(&mut self, id: NodeId, key: K, value: V) -> Option<V> {
+        self.count(Counter::Visits);
+        let node = self.node_mut(id);
+        node.assert_in_universe(&key);
+
+        let (key, value) = match node.insert_min_max(key, value) {
+            Placement::Done(old_value) => return old_value,
+            Placement::Cluster(key, value) => (key, value),
+        };
+
+        let cluster_size = node.cluster_size.clone();
+        let h = key.high(&cluster_size);
+        let l = key.low(&cluster_size);
+        let cluster = self.cluster_or_insert(id, h, cluster_size);
+        // When the cluster was just created, this recursive call will trigger
+        // the lazy case and run in constant time.
+        self.insert(cluster, l, value)
+    }
+
+    /// Get a node's cluster, creating it when it doesn't exist.
+    pub(crate) fn cluster_or_insert(
+        &mut self,
+        id: NodeId,
+        h: K,
+        cluster_size: K::Size,
+    ) -> NodeId {
+        match self.cluster(id, &h) {
+            Some(cluster) => cluster,
+            None => {
+                // Only recurse on the summary when a cluster is created.  This
+                // prevents unneeded recursive calls on the summary.
+                let cluster = self.alloc(cluster_size);
+                self.count(Counter::Probes);
+                self.count(Counter::Clones);
+                self.clusters.insert((id, h.clone()), cluster);
+                self.insert_summary(id, h, cluster);
+                cluster
+            }
+        }
+    }
+
+    /// Reserve memory for `additional` more inserts into nodes of at most
+    /// `max_size`, in this arena and each summary arena below it.
+    ///
+    /// An insert adds at most two nodes and one cluster to each arena: a
+    /// cluster, or the root or a summary node and then the summary's cluster.
+    /// Without the `std` feature, the ordered cluster map allocates as it
+    /// grows, so only the nodes and summary arenas are reserved.
+    pub(crate) fn try_reserve(
+        &mut self,
+        max_size: &K::Size,
+        additional: usize,
+    ) -> Result<(), TryReserveError> {
+        let nodes = additional.saturating_mul(2);
+        self.nodes
+            .try_reserve(nodes.saturating_sub(self.free.len()))?;
+        // Nodes of a single bit hold both keys in their min and max, so they
+        // never have clusters or a summary.
+        let cluster_size = K::cluster_size(max_size);
+        if K::size_to_key(&cluster_size)
+            == K::size_to_key(&K::cluster_size(&cluster_size))
+        {
+            return Ok(());
+        }
+        #[cfg(feature = "std")]
+        self.clusters.try_reserve(additional)?;
+        if self.summaries.is_empty() {
+            self.summaries.try_reserve_exact(1)?;
+        }
+        summaries_or_insert(&mut self.summaries)
+            .try_reserve(&cluster_size, additional)
+    }
+
+    /// The capacities of the nodes and the cluster table of this arena and
+    /// each summary arena below it.
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn capacities(&self) -> Vec<(usize, usize)> {
+        let mut capacities =
+            Vec::from([(self.nodes.capacity(), self.clusters.capacity())]);
+        if let Some(summaries) = self.summaries.first() {
+            capacities.extend(summaries.capacities());
+        }
+        capacities
+    }
+
+    /// Remove a key from the subtree rooted at a node and return its value.
+    pub(crate) fn remove(&mut self, id: NodeId, key: &K) -> Option<V> {
+        self.count(Counter::Visits);
+        let node = self.node(id);
+        node.assert_in_universe(key);
+
+        let (min_key, _) = node.first()?;
+        let min_ordering = key.cmp(min_key);
+        let max_ordering =
+            node.max.as_ref().map(|(max_key, _)| key.cmp(max_key));
+        let cluster_size = node.cluster_size.clone();
+
+        match (min_ordering, max_ordering) {
+            // There's a single entry.
+            (Ordering::Equal, None) => {
+                self.node_mut(id).min.take().map(|(_, v)| v)
+            }
+            (_, None) => None,
+            (Ordering::Equal, Some(_)) => {
+                // Pull the smallest clustered entry out to become the new min.
+                let new_min = match self.take_first(id) {
+                    Some(entry) => Some(entry),
+                    None => self.node_mut(id).max.take(),
+                };
+                replace(&mut self.node_mut(id).min, new_min).map(|(_, v)| v)
+            }
+            (_, Some(Ordering::Equal)) => {
+                // Pull the largest clustered entry out to become the new max.
+                let new_max = self.take_last(id);
+                replace(&mut self.node_mut(id).max, new_max).map(|(_, v)| v)
+            }
+            (Ordering::Less, _) | (_, Some(Ordering::Greater)) => None,
+            (Ordering::Greater, Some(Ordering::Less)) => {
+                let h = key.high(&cluster_size);
+                let cluster = self.cluster(id, &h)?;
+                self.remove_from_cluster(
+                    id,
+                    h,
+                    cluster,
+                    &key.low(&cluster_size),
+                )
+            }
+        }
+    }
+
+    /// Remove the smallest entry stored in the node's clusters.
+    fn take_first(&mut self, id: NodeId) -> Option<(K, V)> {
+        let (h, cluster) = self.summary_first(id)?;
+        let (l, _) = self
+            .node(cluster)
+            .first()
+            .expect("cluster for summary min should have a min element");
+        let l = l.clone();
+        let key = h.index(l.clone(), &self.node(id).cluster_size);
+        let value = self
+            .remove_from_cluster(id, h, cluster, &l)
+            .expect("cluster min should be removed");
+        Some((key, value))
+    }
+
+    /// Remove the largest entry stored in the node's clusters.
+    fn take_last(&mut self, id: NodeId) -> Option<(K, V)> {
+        let (h, cluster) = self.summary_last(id)?;
+        let (l, _) = self
+            .node(cluster)
+            .last()
+            .expect("cluster for summary max should have a max element");
+        let l = l.clone();
+        let key = h.index(l.clone(), &self.node(id).cluster_size);
+        let value = self
+            .remove_from_cluster(id, h, cluster, &l)
+            .expect("cluster max should be removed");
+        Some((key, value))
+    }
+
+    /// Remove a key from one of a node's clusters, releasing the cluster when
+    /// it becomes empty.
+    fn remove_from_cluster(
+        &mut self,
+        id: NodeId,
+        h: K,
+        cluster: NodeId,
+        l: &K,
+    ) -> Option<V> {
+        let value = self.remove(cluster, l);
+        // Only recurse on the summary when the cluster became empty, in which
+        // case the recursive call above ran in constant time.
+        if self.node(cluster).is_empty() {
+            self.count(Counter::Probes);
+            self.count(Counter::Clones);
+            self.clusters.remove(&(id, h.clone()));
+            self.release(cluster);
+            self.remove_summary(id, &h);
+        }
+        value
+    }
+
+    fn insert_summary(&mut self, id: NodeId, h: K, cluster: NodeId) {
+        self.count(Counter::SummaryDescents);
+        let summaries = summaries_or_insert(&mut self.summaries);
+        let node = &mut self.nodes[id.index()];
+        let summary = *node
+            .summary
+            .get_or_insert_with(|| summaries.alloc(node.cluster_size.clone()));
+        summaries.insert(summary, h, cluster);
+    }
+
+    fn remove_summary(&mut self, id: NodeId, h: &K) {
+        let node = &mut self.nodes[id.index()];
+        let (Some(summary), Some(summaries)) =
+            (node.summary, self.summaries.first_mut())
+        else {
+            return;
+        };
+        #[cfg(feature = "stats")]
+        self.counters.add(Counter::SummaryDescents);
+        summaries.remove(summary, h);
+        if summaries.node(summary).is_empty() {
+            summaries.release(summary);
+            node.summary = None;
+        }
+    }
+
+    /// The summary node of a node, along with the arena it lives in.
+    #[inline]
+    pub(crate) fn summary(
+        &self,
+        id: NodeId,
+    ) -> Option<(&Arena<K, NodeId>, NodeId)> {
+        let summary = self.node(id).summary?;
+        Some((self.summaries.first()?, summary))
+    }
+
+    /// The first non-empty cluster and its number.
+    fn summary_first(&self, id: NodeId) -> Option<(K, NodeId)> {
+        let (summaries, summary) = self.summary(id)?;
+        summaries.node(summary).first().cloned()
+    }
+
+    /// The last non-empty cluster and its number.
+    fn summary_last(&self, id: NodeId) -> Option<(K, NodeId)> {
+        let (summaries, summary) = self.summary(id)?;
+        summaries.node(summary).last().cloned()
+    }
+
+    /// Find the successor of a key among a node's min, max and the mins of
+    /// its clusters, or the cluster to descend into to find it.
+    #[inline]
+    pub(crate) fn successor_step(
+        &self,
+        id: NodeId,
+        key: &K,
+        cluster_size: &K::Size,
+        find_cluster: impl FnOnce(&K) -> Option<NodeId>,
+    ) -> Step<'_, K, V> {
+        self.after_step(id, key, false, cluster_size, find_cluster)
+    }
+
+    /// Find the first key after a key, or at it when `inclusive`, among a
+    /// node's min, max and the mins of its clusters, or the cluster to descend
+    /// into to find it.
+    #[inline]
+    fn after_step(
+        &self,
+        id: NodeId,
+        key: &K,
+        inclusive: bool,
+        cluster_size: &K::Size,
+        find_cluster: impl FnOnce(&K) -> Option<NodeId>,
+    ) -> Step<'_, K, V> {
+        let node = self.node(id);
+        node.assert_in_universe(key);
+        // Whether `other` is a candidate for a key after `key`.
+        let after = |key: &K, other: &K| {
+            if inclusive { key <= other } else { key < other }
+        };
+
+        // If the key is less than the min, then the successor is the min.
+        let Some((min_key, min_value)) = node.first() else {
+            return Step::Absent;
+        };
+        if after(key, min_key) {
+            return Step::Found(min_key.clone(), min_value);
+        }
+        // Nothing is greater than the max.
+        let Some((max_key, max_value)) = node.max.as_ref() else {
+            return Step::Absent;
+        };
+        if !after(key, max_key) {
+            return Step::Absent;
+        }
+        // Without any clusters, the successor is the max.
+        let Some((summaries, summary)) = self.summary(id) else {
+            return Step::Found(max_key.clone(), max_value);
+        };
+
+        // If the key is less than its cluster's max, then the successor is in
+        // that cluster.
+        let h = key.high(cluster_size);
+        let l = key.low(cluster_size);
+        if let Some(cluster) = find_cluster(&h)
+            && let Some((cluster_max, _)) = self.node(cluster).last()
+            && after(&l, cluster_max)
+        {
+            return Step::Cluster { h, cluster, l };
+        }
+
+        // Recurse on the summary to find the next cluster.  The successor is
+        // the min in that cluster.
+        self.count(Counter::SummaryDescents);
+        if let Some((next_h, next_cluster)) = summaries.successor(summary, &h) {
+            let (next_l, v) = self
+                .node(*next_cluster)
+                .first()
+                .expect("cluster for summary successor should be non-empty");
+            return Step::Found(next_h.index(next_l.clone(), cluster_size), v);
+        }
+
+        // Otherwise, the successor is the max.
+        Step::Found(max_key.clone(), max_value)
+    }
+
+    /// Get the successor of a key in the subtree rooted at a node.
+    pub(crate) fn successor(&self, id: NodeId, key: &K) -> Option<(K, &V)> {
+        self.after(id, key, false)
+    }
+
+    /// Get the first key at or after a key in the subtree rooted at a node.
+    pub(crate) fn ceiling(&self, id: NodeId, key: &K) -> Option<(K, &V)> {
+        self.after(id, key, true)
+    }
+
+    fn after(&self, id: NodeId, key: &K, inclusive: bool) -> Option<(K, &V)> {
+        self.count(Counter::Visits);
+        let cluster_size = &self.node(id).cluster_size;
+        match self.after_step(id, key, inclusive, cluster_size, |h| {
+            self.cluster(id, h)
+        }) {
+            Step::Found(k, v) => Some((k, v)),
+            Step::Absent => None,
+            Step::Cluster { h, cluster, l } => {
+                // Recurse.
+                let (next_l, v) = self.after(cluster, &l, inclusive).unwrap_or_else(|| {
+                    // This should never happen since the step checked that the
+                    // key is less than the cluster max.
+                    panic!(
+                        "key is less than cluster max, but successor wasn't found; key={key:?}, h={h:?}, l={l:?}"
+                    )
+                });
+                Some((h.index(next_l, cluster_size), v))
+            }
+        }
+    }
+
+    /// Find the predecessor of a key among a node's min, max and the maxes of
+    /// its clusters, or the cluster to descend into to find it.
+    #[inline]
+    pub(crate) fn predecessor_step(
+        &self,
+        id: NodeId,
+        key: &K,
+        cluster_size: &K::Size,
+        find_cluster: impl FnOnce(&K) -> Option<NodeId>,
+    ) -> Step<'_, K, V> {
+        self.before_step(id, key, false, cluster_size, find_cluster)
+    }
+
+    /// Find the last key before a key, or at it when `inclusive`, among a
+    /// node's min, max and the maxes of its clusters, or the cluster to
+    /// descend into to find it.
+    #[inline]
+    fn before_step(
+        &self,
+        id: NodeId,
+        key: &K,
+        inclusive: bool,
+        cluster_size: &K::Size,
+        find_cluster: impl FnOnce(&K) -> Option<NodeId>,
+    ) -> Step<'_, K, V> {
+        let node = self.node(id);
+        node.assert_in_universe(key);
+        // Whether `other` is a candidate for a key before `key`.
+        let before = |key: &K, other: &K| {
+            if inclusive { key >= other } else { key > other }
+        };
+
+        // If the key is greater than the max, then the predecessor is the max.
+        let Some((max_key, max_value)) = node.last() else {
+            return Step::Absent;
+        };
+        if before(key, max_key) {
+            return Step::Found(max_key.clone(), max_value);
+        }
+        // Nothing is less than the min.
+        let Some((min_key, min_value)) = node.first() else {
+            return Step::Absent;
+        };
+        if !before(key, min_key) {
+            return Step::Absent;
+        }
+        // Without any clusters, the predecessor is the min.
+        let Some((summaries, summary)) = self.summary(id) else {
+            return Step::Found(min_key.clone(), min_value);
+        };
+
+        // If the key is greater than its cluster's min, then the predecessor is
+        // in that cluster.
+        let h = key.high(cluster_size);
+        let l = key.low(cluster_size);
+        if let Some(cluster) = find_cluster(&h)
+            && let Some((cluster_min, _)) = self.node(cluster).first()
+            && before(&l, cluster_min)
+        {
+            return Step::Cluster { h, cluster, l };
+        }
+
+        // Recurse on the summary to find the previous cluster.  The
+        // predecessor is the max in that cluster.
+        self.count(Counter::SummaryDescents);
+        if let Some((prev_h, prev_cluster)) = summaries.predecessor(summary, &h)
+        {
+            let (prev_l, v) = self
+                .node(*prev_cluster)
+                .last()
+                .expect("cluster for summary predecessor should be non-empty");
+            return Step::Found(prev_h.index(prev_l.clone(), cluster_size), v);
+        }
+
+        // Otherwise, the predecessor is the min.
+        Step::Found(min_key.clone(), min_value)
+    }
+
+    /// Get the predecessor of a key in the subtree rooted at a node.
+    pub(crate) fn predecessor(&self, id: NodeId, key: &K) -> Option<(K, &V)> {
+        self.before(id, key, false)
+    }
+
+    /// Get the last key at or before a key in the subtree rooted at a node.
+    pub(crate) fn floor(&self, id: NodeId, key: &K) -> Option<(K, &V)> {
+        self.before(id, key, true)
+    }
+
+    fn before(&self, id: NodeId, key: &K, inclusive: bool) -> Option<(K, &V)> {
+        self.count(Counter::Visits);
+        let cluster_size = &self.node(id).cluster_size;
+        match self.before_step(id, key, inclusive, cluster_size, |h| {
+            self.cluster(id, h)
+        }) {
+            Step::Found(k, v) => Some((k, v)),
+            Step::Absent => None,
+            Step::Cluster { h, cluster, l } => {
+                // Recurse.
+                let (prev_l, v) = self.before(cluster, &l, inclusive).unwrap_or_else(|| {
+                    // This should never happen since the step checked that the
+                    // key is greater than the cluster min.
+                    panic!(
+                        "key is greater than cluster min, but predecessor wasn't found; key={key:?}, h={h:?}, l={l:?}"
+                    )
+                });
+                Some((h.index(prev_l, cluster_size), v))
+            }
+        }
+    }
+
+    /// Get the last key at or before a key and the first key at or after it
+    /// in the subtree rooted at a node.  Both are found in the same descent:
+    /// it only goes into a cluster when both are in that cluster.
+    pub(crate) fn bracket(&self, id: NodeId, key: &K) -> Bracket<'_, K, V> {
+        self.count(Counter::Visits);
+        let node = self.node(id);
+        node.assert_in_universe(key);
+
+        let Some((min_key, min_value)) = node.first() else {
+            return (None, None);
+        };
+        let min = || Some((min_key.clone(), min_value));
+        if *key <= *min_key {
+            return (min().filter(|_| key == min_key), min());
+        }
+        let (max_key, max_value) = node.last().expect("node is non-empty");
+        let max = || Some((max_key.clone(), max_value));
+        if *key >= *max_key {
+            return (max(), max().filter(|_| key == max_key));
+        }
+        // The key is strictly between the min and the max.
+        let cluster_size = &node.cluster_size;
+        let Some((summaries, summary)) = self.summary(id) else {
+            return (min(), max());
+        };
+
+        let h = key.high(cluster_size);
+        let l = key.low(cluster_size);
+        let cluster = self.cluster(id, &h);
+        if let Some(cluster) = cluster {
+            let cluster_node = self.node(cluster);
+            let (cluster_min, _) = cluster_node.first().expect("non-empty");
+            let (cluster_max, _) = cluster_node.last().expect("non-empty");
+            if *cluster_min <= l && l <= *cluster_max {
+                // Recurse.
+                let (floor, ceiling) = self.bracket(cluster, &l);
+                let join = |(k, v)| (h.index(k, cluster_size), v);
+                return (floor.map(join), ceiling.map(join));
+            }
+        }
+
+        // The key is outside of its cluster's range, so each side is either
+        // in its cluster or in the next cluster that way.
+        let floor = match cluster.and_then(|c| self.node(c).last()) {
+            Some((cluster_max, v)) if *cluster_max < l => {
+                Some((h.index(cluster_max.clone(), cluster_size), v))
+            }
+            _ => {
+                self.count(Counter::SummaryDescents);
+                summaries.predecessor(summary, &h).map(|(prev_h, prev)| {
+                    let (prev_l, v) = self.node(*prev).last().expect(
+                        "cluster for summary predecessor should be non-empty",
+                    );
+                    (prev_h.index(prev_l.clone(), cluster_size), v)
+                })
+            }
+        };
+        let ceiling = match cluster.and_then(|c| self.node(c).first()) {
+            Some((cluster_min, v)) if l < *cluster_min => {
+                Some((h.index(cluster_min.clone(), cluster_size), v))
+            }
+            _ => {
+                self.count(Counter::SummaryDescents);
+                summaries.successor(summary, &h).map(|(next_h, next)| {
+                    let (next_l, v) = self.node(*next).first().expect(
+                        "cluster for summary successor should be non-empty",
+                    );
+                    (next_h.index(next_l.clone(), cluster_size), v)
+                })
+            }
+        };
+        (floor.or_else(min), ceiling.or_else(max))
+    }
+}
+
+/// The last entry at or before a key and the first entry at or after it.
+pub(crate) type Bracket<'a, K, V> = (Option<(K, &'a V)>, Option<(K, &'a V)>);
+
+/// Iterative versions of the operations for trees where the cluster size at
+/// each depth is known ahead of time.  Instead of recursing into clusters,
+/// these loop over `cluster_sizes`, which lets the compiler unroll the loop
+/// when the sizes are constants.
+impl<K, V> Arena<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug + Add<Output = K> + Default,
+{
+    /// Lookup a key starting from the root.
+    #[inline]
+    pub(crate) fn get_iterative(
+        &self,
+        root: NodeId,
+        key: &K,
+        cluster_sizes: &[K::Size],
+    ) -> Option<&V> {
+        let mut id = root;
+        let mut key = key.clone();
+        for cluster_size in cluster_sizes {
+            self.count(Counter::Visits);
+            match self.probe(id, &key, cluster_size, |h| self.cluster(id, h)) {
+                Probe::Found(value) => return Some(value),
+                Probe::Absent => return None,
+                Probe::Cluster(cluster, l) => {
+                    id = cluster;
+                    key = l;
+                }
+            }
+        }
+        None
+    }
+
+    /// Insert an entry starting from the root.
+    #[inline]
+    pub(crate) fn insert_iterative(
+        &mut self,
+        root: NodeId,
+        mut key: K,
+        mut value: V,
+        cluster_sizes: &[K::Size],
+    ) -> Option<V> {
+        let mut id = root;
+        for cluster_size in cluster_sizes {
+            self.count(Counter::Visits);
+            (key, value) = match self.node_mut(id).insert_min_max(key, value) {
+                Placement::Done(old_value) => return old_value,
+                Placement::Cluster(key, value) => (key, value),
+            };
+            let h = key.high(cluster_size);
+            key = key.low(cluster_size);
+            id = self.cluster_or_insert(id, h, cluster_size.clone());
+        }
+        panic!(
+            "insert went deeper than the number of cluster sizes; key={key:?}"
+        )
+    }
+
+    /// Get the successor of a key starting from the root.
+    #[inline]
+    pub(crate) fn successor_iterative(
+        &self,
+        root: NodeId,
+        key: &K,
+        cluster_sizes: &[K::Size],
+    ) -> Option<(K, &V)> {
+        let mut id = root;
+        let mut key = key.clone();
+        // The high bits of the key that were consumed by descending.
+        let mut base = K::default();
+        for cluster_size in cluster_sizes {
+            self.count(Counter::Visits);
+            match self
+                .successor_step(id, &key, cluster_size, |h| self.cluster(id, h))
+            {
+                Step::Found(k, v) => return Some((base + k, v)),
+                Step::Absent => return None,
+                Step::Cluster { h, cluster, l } => {
+                    base = base + h.index(K::default(), cluster_size);
+                    id = cluster;
+                    key = l;
+                }
+            }
+        }
+        panic!(
+            "successor went deeper than the number of cluster sizes; key={key:?}"
+        )
+    }
+}
+
+/// Measuring an arena and the summary arenas below it.
+impl<K, V> Arena<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+{
+    /// Append the statistics of this arena and each summary arena below it.
+    pub(crate) fn stats(&self, arenas: &mut Vec<ArenaStats>) {
+        let entries = self
+            .nodes
+            .iter()
+            .map(|node| {
+                usize::from(node.min.is_some())
+                    + usize::from(node.max.is_some())
+            })
+            .sum();
+        #[cfg(feature = "std")]
+        let cluster_table_capacity = self.clusters.capacity();
+        #[cfg(not(feature = "std"))]
+        let cluster_table_capacity = self.clusters.len();
+        // A hash table also has a control byte for each slot.
+        let cluster_bytes = size_of::<((NodeId, K), NodeId)>()
+            + usize::from(cfg!(feature = "std"));
+        arenas.push(ArenaStats {
+            nodes: self.nodes.len() - self.free.len(),
+            free_nodes: self.free.len(),
+            node_capacity: self.nodes.capacity(),
+            entries,
+            cluster_table_len: self.clusters.len(),
+            cluster_table_capacity,
+            heap_bytes: self.nodes.capacity() * size_of::<Node<K, V>>()
+                + self.free.capacity() * size_of::<NodeId>()
+                + cluster_table_capacity * cluster_bytes
+                + self.summaries.capacity() * size_of::<Arena<K, NodeId>>(),
+        });
+        if let Some(summaries) = self.summaries.first() {
+            summaries.stats(arenas);
+        }
+    }
+
+    /// The number of nodes at each depth of the subtree rooted at a node.
+    pub(crate) fn nodes_per_level(&self, root: NodeId) -> Vec<usize> {
+        let mut children: BTreeMap<NodeId, Vec<NodeId>> = BTreeMap::new();
+        for ((id, _), cluster) in &self.clusters {
+            children.entry(*id).or_default().push(*cluster);
+        }
+        let mut counts = Vec::new();
+        let mut level = vec![root];
+        while !level.is_empty() {
+            counts.push(level.len());
+            level = level
+                .iter()
+                .filter_map(|id| children.get(id))
+                .flatten()
+                .copied()
+                .collect();
+        }
+        counts
+    }
+}
+
+/// Drawing an arena and the summary arenas below it.
+impl<K, V> Arena<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+{
+    /// The non-empty clusters of a node and their numbers, in order.
+    pub(crate) fn clusters_of(
+        &self,
+        id: NodeId,
+    ) -> impl Iterator<Item = (K, NodeId)> + '_ {
+        let summary = self.summary(id);
+        let mut next = summary.and_then(|(summaries, summary)| {
+            summaries.node(summary).first().cloned()
+        });
+        core::iter::from_fn(move || {
+            let (h, cluster) = next.take()?;
+            let (summaries, summary) = summary?;
+            next = summaries
+                .successor(summary, &h)
+                .map(|(h, cluster)| (h, *cluster));
+            Some((h, cluster))
+        })
+    }
+
+    /// Write the subtree rooted at a node as Graphviz statements, down to
+    /// `depth` more levels of clusters, or all of them when it's `None`.
+    /// The nodes of arena number `level` are named `a{level}n{index}`, and
+    /// their values are only drawn when `values` is set.
+    pub(crate) fn write_dot(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        level: usize,
+        id: NodeId,
+        depth: Option<usize>,
+        values: bool,
+    ) -> fmt::Result
+    where
+        V: Debug,
+    {
+        let name = format!("a{level}n{}", id.index());
+        let node = self.node(id);
+        let mut label =
+            format!("{name}\\ncluster bits: {:?}", node.cluster_size);
+        for (which, entry) in [("min", &node.min), ("max", &node.max)] {
+            let Some((key, value)) = entry else { continue };
+            label.push_str(&format!("\\n{which}: {}", escape(key)));
+            if values {
+                label.push_str(&format!(" => {}", escape(value)));
+            }
+        }
+        writeln!(f, "    {name} [label=\"{label}\"];")?;
+
+        if let Some(summary) = node.summary
+            && let Some(summaries) = self.summaries.first()
+        {
+            let summary_name = format!("a{}n{}", level + 1, summary.index());
+            writeln!(
+                f,
+                "    {name} -> {summary_name} [style=dashed, label=\"summary\"];"
+            )?;
+            summaries.write_dot(f, level + 1, summary, depth, false)?;
+        }
+        match depth.map(|depth| depth.checked_sub(1)) {
+            Some(None) => {
+                if node.summary.is_some() {
+                    writeln!(
+                        f,
+                        "    {name}_more [label=\"...\", shape=plaintext];"
+                    )?;
+                    writeln!(f, "    {name} -> {name}_more;")?;
+                }
+            }
+            depth => {
+                for (h, cluster) in self.clusters_of(id) {
+                    writeln!(
+                        f,
+                        "    {name} -> a{level}n{} [label=\"{}\"];",
+                        cluster.index(),
+                        escape(&h)
+                    )?;
+                    self.write_dot(f, level, cluster, depth.flatten(), values)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Format a value with `Debug`, escaped for a quoted Graphviz string.
+fn escape<T>(value: &T) -> String
+where
+    T: Debug + ?Sized,
+{
+    format!("{value:?}")
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// What a walk over the nodes of an arena has found so far.
+struct Walk<K>
+where
+    K: VebKey,
+{
+    /// The clusters of each node from the cluster table, in order.
+    children: BTreeMap<NodeId, Vec<(K, NodeId)>>,
+    /// Whether each node has been reached or is free.
+    seen: Vec<bool>,
+    /// Each summary node reached, with its size and the node it summarizes.
+    summaries: Vec<(NodeId, K::Size, NodeId)>,
+    /// The number of clusters reached.
+    clusters: usize,
+}
+
+/// Checking the invariants of an arena and the summary arenas below it.
+impl<K, V> Arena<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+{
+    /// Check the subtrees rooted at the given nodes, each with the size of
+    /// its universe, and get the number of entries in each.  Every node in
+    /// the arena must be in exactly one subtree or be free.
+    pub(crate) fn validate(
+        &self,
+        roots: &[(NodeId, K::Size)],
+    ) -> Result<Vec<usize>, InvariantViolation> {
+        let mut children: BTreeMap<NodeId, Vec<(K, NodeId)>> = BTreeMap::new();
+        for ((id, h), cluster) in &self.clusters {
+            children.entry(*id).or_default().push((h.clone(), *cluster));
+        }
+        for clusters in children.values_mut() {
+            clusters.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        }
+        let mut walk = Walk {
+            children,
+            seen: vec![false; self.nodes.len()],
+            summaries: Vec::new(),
+            clusters: 0,
+        };
+        for id in &self.free {
+            mark(&mut walk.seen, *id)?;
+        }
+        let counts = roots
+            .iter()
+            .map(|(root, max_size)| {
+                Ok(self.validate_node(*root, max_size, &mut walk)?.0)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if walk.clusters != self.clusters.len() || walk.seen.contains(&false) {
+            return Err(InvariantViolation::Links);
+        }
+
+        let summary_roots: Vec<_> = walk
+            .summaries
+            .iter()
+            .map(|(summary, size, _)| (*summary, size.clone()))
+            .collect();
+        let Some(summaries) = self.summaries.first() else {
+            if summary_roots.is_empty() {
+                return Ok(counts);
+            }
+            return Err(InvariantViolation::Summary);
+        };
+        summaries.validate(&summary_roots)?;
+        // The summary arena is valid, so its entries can be iterated.
+        for (summary, _, parent) in &walk.summaries {
+            let mut next = summaries.node(*summary).first().cloned();
+            for (h, cluster) in &walk.children[parent] {
+                if next.as_ref() != Some(&(h.clone(), *cluster)) {
+                    return Err(InvariantViolation::Summary);
+                }
+                next = summaries
+                    .successor(*summary, h)
+                    .map(|(h, cluster)| (h, *cluster));
+            }
+            if next.is_some() {
+                return Err(InvariantViolation::Summary);
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Check the subtree rooted at a node, and get its number of entries and
+    /// its min and max keys.
+    fn validate_node(
+        &self,
+        id: NodeId,
+        max_size: &K::Size,
+        walk: &mut Walk<K>,
+    ) -> Result<(usize, K, K), InvariantViolation> {
+        mark(&mut walk.seen, id)?;
+        let node = self.node(id);
+        let (min, max) = match (node.first(), &node.max) {
+            (None, _) => return Err(InvariantViolation::EmptyNode),
+            (Some((min, _)), None) => (min, min),
+            (Some((min, _)), Some((max, _))) if min < max => (min, max),
+            (Some(_), Some(_)) => {
+                return Err(InvariantViolation::MaxNotAboveMin);
+            }
+        };
+        if *max > K::size_to_key(max_size) {
+            return Err(InvariantViolation::KeyOutsideUniverse);
+        }
+        let cluster_size = K::cluster_size(max_size);
+        if K::size_to_key(&node.cluster_size) != K::size_to_key(&cluster_size) {
+            return Err(InvariantViolation::ClusterSize);
+        }
+
+        let clusters = walk.children.get(&id).cloned().unwrap_or_default();
+        if !clusters.is_empty() && node.max.is_none() {
+            return Err(InvariantViolation::ClustersWithoutMax);
+        }
+        match node.summary {
+            Some(summary) if !clusters.is_empty() => {
+                walk.summaries.push((summary, cluster_size.clone(), id));
+            }
+            None if clusters.is_empty() => {}
+            _ => return Err(InvariantViolation::Summary),
+        }
+        walk.clusters += clusters.len();
+        let mut count = if node.max.is_some() { 2 } else { 1 };
+        for (h, cluster) in clusters {
+            let (cluster_count, cluster_min, cluster_max) =
+                self.validate_node(cluster, &cluster_size, walk)?;
+            if h.index(cluster_min, &cluster_size) <= *min
+                || h.index(cluster_max, &cluster_size) >= *max
+            {
+                return Err(InvariantViolation::ClusterKeyOutsideMinMax);
+            }
+            count += cluster_count;
+        }
+        Ok((count, min.clone(), max.clone()))
+    }
+}
+
+/// Mark a node as reached, failing when it doesn't exist or was already
+/// reached.
+fn mark(seen: &mut [bool], id: NodeId) -> Result<(), InvariantViolation> {
+    match seen.get_mut(id.index()) {
+        Some(seen) if !*seen => {
+            *seen = true;
+            Ok(())
+        }
+        _ => Err(InvariantViolation::Links),
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K, V> Arena<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+{
+    /// The entries in the subtree rooted at a node, in order, with keys
+    /// relative to the node.
+    pub(crate) fn entries_of(
+        &self,
+        id: NodeId,
+    ) -> impl Iterator<Item = (K, &V)> + '_ {
+        let mut next = self.node(id).first().map(|(k, v)| (k.clone(), v));
+        core::iter::from_fn(move || {
+            let (key, value) = next.take()?;
+            next = self.successor(id, &key);
+            Some((key, value))
+        })
+    }
+
+    /// Make a node the cluster with number `h` of another node.
+    pub(crate) fn attach_cluster(&mut self, id: NodeId, h: K, cluster: NodeId) {
+        self.clusters.insert((id, h.clone()), cluster);
+        self.insert_summary(id, h, cluster);
+    }
+
+    /// Move every node of another arena into this one, mapping the values
+    /// of the moved nodes.  Returns the offset added to the moved nodes' ids.
+    pub(crate) fn append(
+        &mut self,
+        other: Arena<K, V>,
+        map_value: &dyn Fn(V) -> V,
+    ) -> u32 {
+        let offset = u32::try_from(self.nodes.len())
+            .expect("number of nodes must fit in u32");
+        let shift = |id: NodeId| NodeId(id.0 + offset);
+        // The summaries' values are ids of the nodes moved into this arena.
+        let summary_offset = match other.summaries.into_iter().next() {
+            Some(summaries) => summaries_or_insert(&mut self.summaries)
+                .append(summaries, &shift),
+            None => 0,
+        };
+        self.nodes.extend(other.nodes.into_iter().map(|mut node| {
+            node.summary = node.summary.map(|id| NodeId(id.0 + summary_offset));
+            node.min = node.min.map(|(k, v)| (k, map_value(v)));
+            node.max = node.max.map(|(k, v)| (k, map_value(v)));
+            node
+        }));
+        self.clusters.extend(
+            other
+                .clusters
+                .into_iter()
+                .map(|((id, h), cluster)| ((shift(id), h), shift(cluster))),
+        );
+        self.free.extend(other.free.into_iter().map(shift));
+        self.version = next_version();
+        offset
+    }
+
+    /// Where the entries under a node are, in order of their keys: the
+    /// node's min, each of its clusters and its max, as separate groups so
+    /// that they can be walked in parallel.  Each entry is given by its key,
+    /// its node and whether it's the node's max.
+    pub(crate) fn locate_entries(
+        &self,
+        id: NodeId,
+    ) -> Vec<Vec<(K, NodeId, bool)>>
+    where
+        K: Send + Sync,
+        K::Size: Send + Sync,
+        V: Sync,
+    {
+        let node = self.node(id);
+        let cluster_size = &node.cluster_size;
+        let min = node.min.iter().map(|(k, _)| (k.clone(), id, false));
+        let max = node.max.iter().map(|(k, _)| (k.clone(), id, true));
+        let clusters: Vec<(K, NodeId)> = self.clusters_of(id).collect();
+        let clusters: Vec<Vec<(K, NodeId, bool)>> = clusters
+            .into_par_iter()
+            .map(|(h, cluster)| {
+                let mut prefix = Prefix::default();
+                prefix.push(h, cluster_size);
+                self.locate_in_cluster(cluster, prefix)
+            })
+            .collect();
+        core::iter::once(min.collect())
+            .chain(clusters)
+            .chain(core::iter::once(max.collect()))
+            .collect()
+    }
+
+    /// Where the entries under a cluster are, in order of their keys, when
+    /// its keys start with `prefix`.
+    fn locate_in_cluster(
+        &self,
+        id: NodeId,
+        prefix: Prefix<K>,
+    ) -> Vec<(K, NodeId, bool)> {
+        // Each node is visited twice: first for its min and clusters, and
+        // then for its max, after its clusters.
+        let mut stack = vec![(id, prefix, false)];
+        let mut found = Vec::new();
+        while let Some((id, prefix, after_clusters)) = stack.pop() {
+            let node = self.node(id);
+            if after_clusters {
+                if let Some((k, _)) = &node.max {
+                    found.push((prefix.join(k.clone()), id, true));
+                }
+                continue;
+            }
+            if let Some((k, _)) = &node.min {
+                found.push((prefix.join(k.clone()), id, false));
+            }
+            stack.push((id, prefix.clone(), true));
+            let clusters: Vec<(K, NodeId)> = self.clusters_of(id).collect();
+            for (h, cluster) in clusters.into_iter().rev() {
+                let mut cluster_prefix = prefix.clone();
+                cluster_prefix.push(h, &node.cluster_size);
+                stack.push((cluster, cluster_prefix, false));
+            }
+        }
+        found
+    }
+
+    /// The values of every node by node id, min first, to be handed out in
+    /// the order from [`Arena::locate_entries`] by [`Arena::place`].
+    pub(crate) fn values_mut(&mut self) -> Vec<[Option<&mut V>; 2]>
+    where
+        K: Send,
+        K::Size: Send,
+        V: Send,
+    {
+        self.nodes
+            .par_iter_mut()
+            .map(|node| {
+                [
+                    node.min.as_mut().map(|(_, v)| v),
+                    node.max.as_mut().map(|(_, v)| v),
+                ]
+            })
+            .collect()
+    }
+
+    /// Take the values of every node by node id, min first, like
+    /// [`Arena::values_mut`].
+    pub(crate) fn into_values(self) -> Vec<[Option<V>; 2]>
+    where
+        K: Send,
+        K::Size: Send,
+        V: Send,
+    {
+        self.nodes
+            .into_par_iter()
+            .map(|node| [node.min.map(|(_, v)| v), node.max.map(|(_, v)| v)])
+            .collect()
+    }
+
+    /// Pair each located entry's key with its value, taken from `values` by
+    /// node id.
+    pub(crate) fn place<T>(
+        located: Vec<Vec<(K, NodeId, bool)>>,
+        mut values: Vec<[Option<T>; 2]>,
+    ) -> Vec<Vec<(K, T)>> {
+        located
+            .into_iter()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .map(|(key, id, is_max)| {
+                        let value = values[id.index()][usize::from(is_max)]
+                            .take()
+                            .expect("located entry must have a value");
+                        (key, value)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl NodeId {
+    /// Add an offset from [`Arena::append`] to the id.
+    pub(crate) fn shifted(self, offset: u32) -> NodeId {
+        NodeId(self.0 + offset)
+    }
+}
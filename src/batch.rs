@@ -0,0 +1,144 @@
+//! Batched lookups.
+//!
+//! Instead of finishing one query before starting the next, these walk down
+//! the tree one level at a time across the whole batch.  The memory accesses
+//! of different queries don't depend on each other, so the processor can
+//! overlap their cache misses.  On top of that, when a query finds the
+//! cluster it descends into, the cluster's node is prefetched, and it has
+//! the rest of the level's batch to arrive before the query reads it.
+//!
+//! The cluster tables don't expose where a key's bucket is, so the hash
+//! lookups themselves aren't prefetched.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::arena::{NodeId, Prefix, Probe, Step};
+use crate::instrument::{Counter, Operation};
+use crate::{VebKey, VebTreeMap};
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Lookup many keys in the tree and get their values, in the same order
+    /// as the keys.  Runs in O(lg lg u) time per key.
+    pub fn get_many(&self, keys: &[K]) -> Vec<Option<V>> {
+        self.measure(Operation::GetMany, |map| map.values_of(keys, true))
+    }
+
+    /// Same as [`VebTreeMap::get_many`], but without prefetching, so that the
+    /// benchmarks can measure what the prefetch gains.
+    #[doc(hidden)]
+    pub fn get_many_without_prefetch(&self, keys: &[K]) -> Vec<Option<V>> {
+        self.values_of(keys, false)
+    }
+
+    /// Get the successors of many keys, in the same order as the keys.  Runs
+    /// in O(lg lg u) time per key.
+    pub fn successor_many(&self, keys: &[K]) -> Vec<Option<(K, V)>> {
+        self.measure(Operation::SuccessorMany, |map| {
+            map.successors_of(keys, true)
+        })
+    }
+
+    /// Same as [`VebTreeMap::successor_many`], but without prefetching, so
+    /// that the benchmarks can measure what the prefetch gains.
+    #[doc(hidden)]
+    pub fn successor_many_without_prefetch(
+        &self,
+        keys: &[K],
+    ) -> Vec<Option<(K, V)>> {
+        self.successors_of(keys, false)
+    }
+
+    fn values_of(&self, keys: &[K], prefetch: bool) -> Vec<Option<V>> {
+        let mut values = vec![None; keys.len()];
+        let Some(root) = self.root else {
+            return values;
+        };
+
+        // The queries that are still descending: the position of the key, the
+        // current node, and the key within that node.  Keys outside the
+        // universe aren't in the tree.
+        let mut pending: Vec<(usize, NodeId, K)> = keys
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|(_, key)| self.in_universe(key))
+            .map(|(i, key)| (i, root, key))
+            .collect();
+        while !pending.is_empty() {
+            pending.retain_mut(|(i, id, key)| {
+                self.arena.count(Counter::Visits);
+                let cluster_size = &self.arena.node(*id).cluster_size;
+                match self.arena.probe(*id, key, cluster_size, |h| {
+                    self.arena.cluster(*id, h)
+                }) {
+                    Probe::Found(value) => {
+                        values[*i] = Some(value.clone());
+                        false
+                    }
+                    Probe::Absent => false,
+                    Probe::Cluster(cluster, l) => {
+                        if prefetch {
+                            self.arena.prefetch(cluster);
+                        }
+                        *id = cluster;
+                        *key = l;
+                        true
+                    }
+                }
+            });
+        }
+        values
+    }
+
+    fn successors_of(&self, keys: &[K], prefetch: bool) -> Vec<Option<(K, V)>> {
+        let mut successors = vec![None; keys.len()];
+        let Some(root) = self.root else {
+            return successors;
+        };
+
+        // The queries that are still descending: the position of the key, the
+        // current node, the key within that node, and the high bits of the key
+        // that were consumed to get there.  Nothing is above a key outside the
+        // universe.
+        let mut pending: Vec<(usize, NodeId, K, Prefix<K>)> = keys
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|(_, key)| self.in_universe(key))
+            .map(|(i, key)| (i, root, key, Prefix::default()))
+            .collect();
+        while !pending.is_empty() {
+            pending.retain_mut(|(i, id, key, prefix)| {
+                self.arena.count(Counter::Visits);
+                let cluster_size = &self.arena.node(*id).cluster_size;
+                match self.arena.successor_step(*id, key, cluster_size, |h| {
+                    self.arena.cluster(*id, h)
+                }) {
+                    Step::Found(k, v) => {
+                        successors[*i] = Some((prefix.join(k), v.clone()));
+                        false
+                    }
+                    Step::Absent => false,
+                    Step::Cluster { h, cluster, l } => {
+                        if prefetch {
+                            self.arena.prefetch(cluster);
+                        }
+                        prefix.push(h, cluster_size);
+                        *id = cluster;
+                        *key = l;
+                        true
+                    }
+                }
+            });
+        }
+        successors
+    }
+}
@@ -0,0 +1,117 @@
+//! Operations that return an error for keys outside the tree's universe,
+//! instead of panicking or treating them as greater than every key.
+
+use core::error::Error;
+use core::fmt::{self, Debug};
+use core::hash::Hash;
+
+use crate::{VebKey, VebTreeMap};
+
+/// The ways that an operation on a [`VebTreeMap`] can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VebError<K> {
+    /// The key is larger than the largest key in the tree's universe.
+    OutOfUniverse { key: K, max_key: K },
+}
+
+impl<K> fmt::Display for VebError<K>
+where
+    K: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VebError::OutOfUniverse { key, max_key } => write!(
+                f,
+                "key {key:?} is outside the tree's universe, which ends at \
+                 {max_key:?}"
+            ),
+        }
+    }
+}
+
+impl<K> Error for VebError<K> where K: Debug {}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Returns an error if the key doesn't fit in the tree's universe.
+    pub fn check_key(&self, key: &K) -> Result<(), VebError<K>> {
+        if self.in_universe(key) {
+            Ok(())
+        } else {
+            Err(VebError::OutOfUniverse {
+                key: key.clone(),
+                max_key: K::size_to_key(&self.max_size),
+            })
+        }
+    }
+
+    /// Like [`VebTreeMap::get`], but returns an error for keys outside the
+    /// universe.
+    pub fn checked_get(&self, key: &K) -> Result<Option<V>, VebError<K>> {
+        self.check_key(key)?;
+        Ok(self.get(key))
+    }
+
+    /// Like [`VebTreeMap::insert`], but returns an error for keys outside the
+    /// universe instead of panicking.
+    pub fn checked_insert(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<Option<V>, VebError<K>> {
+        self.check_key(&key)?;
+        Ok(self.insert(key, value))
+    }
+
+    /// Like [`VebTreeMap::remove`], but returns an error for keys outside the
+    /// universe.
+    pub fn checked_remove(&mut self, key: &K) -> Result<(), VebError<K>> {
+        self.check_key(key)?;
+        self.remove(key);
+        Ok(())
+    }
+
+    /// Like [`VebTreeMap::successor`], but returns an error for keys outside
+    /// the universe.
+    pub fn checked_successor(
+        &self,
+        key: &K,
+    ) -> Result<Option<(K, V)>, VebError<K>> {
+        self.check_key(key)?;
+        Ok(self.successor(key))
+    }
+
+    /// Like [`VebTreeMap::predecessor`], but returns an error for keys
+    /// outside the universe.
+    pub fn checked_predecessor(
+        &self,
+        key: &K,
+    ) -> Result<Option<(K, V)>, VebError<K>> {
+        self.check_key(key)?;
+        Ok(self.predecessor(key))
+    }
+
+    /// Like [`VebTreeMap::ceiling`], but returns an error for keys outside
+    /// the universe.
+    pub fn checked_ceiling(
+        &self,
+        key: &K,
+    ) -> Result<Option<(K, V)>, VebError<K>> {
+        self.check_key(key)?;
+        Ok(self.ceiling(key))
+    }
+
+    /// Like [`VebTreeMap::floor`], but returns an error for keys outside the
+    /// universe.
+    pub fn checked_floor(
+        &self,
+        key: &K,
+    ) -> Result<Option<(K, V)>, VebError<K>> {
+        self.check_key(key)?;
+        Ok(self.floor(key))
+    }
+}
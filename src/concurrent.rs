@@ -0,0 +1,365 @@
+//! A tree that many threads can change at once.
+//!
+//! The keys are split by their high bits into shards, each a tree behind its
+//! own lock, so threads working on different shards don't wait for each
+//! other.  A shard holds a contiguous range of the root's clusters.  A bitmap
+//! of the non-empty shards plays the part of the root's summary, so
+//! successor and predecessor can skip to the next shard with keys without
+//! locking the empty ones.
+//!
+//! A search that skips over shards only locks one shard at a time, so a key
+//! could be inserted into a shard it already passed.  Every insert of a new
+//! key bumps a counter, and the search starts over when the counter changed
+//! while it ran.  When inserts keep getting in the way, the search locks all
+//! the shards it needs at once instead.  Locks are only held together in
+//! increasing order of shards, so this can't deadlock.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::ops::Range;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering, fence};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{VebKey, VebTreeMap};
+
+/// The shards used by [`ConcurrentVebTreeMap::new`], as a number of bits.
+const DEFAULT_SHARD_BITS: u8 = 6;
+/// The most shards, as a number of bits, so that scanning the bitmap is
+/// quick.
+const MAX_SHARD_BITS: u8 = 12;
+/// The times a search across shards starts over because of inserts before it
+/// locks the shards instead.
+const SEARCH_TRIES: usize = 4;
+
+/// A key type that a [`ConcurrentVebTreeMap`] can split into shards.
+pub trait ShardKey:
+    VebKey<Size = u8> + Ord + Clone + Hash + Eq + Debug
+{
+    /// The number made of the top `bits` bits of the key, which are the top
+    /// bits of its root cluster number.  `bits` is at most half of the bits
+    /// in the key type.
+    fn top_bits(&self, bits: u8) -> usize;
+}
+
+macro_rules! impl_shard_key {
+    ($typ: ty) => {
+        impl ShardKey for $typ {
+            #[inline]
+            fn top_bits(&self, bits: u8) -> usize {
+                if bits == 0 {
+                    return 0;
+                }
+                let top = *self >> (Self::BITS - u32::from(bits));
+                usize::try_from(top).expect("top bits must fit in usize")
+            }
+        }
+    };
+}
+
+impl_shard_key!(u8);
+impl_shard_key!(u16);
+impl_shard_key!(u32);
+impl_shard_key!(u64);
+impl_shard_key!(u128);
+impl_shard_key!(usize);
+
+/// A map implemented with van Emde Boas trees that can be shared between
+/// threads.
+///
+/// Operations on keys lock a single shard.  [`ConcurrentVebTreeMap::min`],
+/// [`ConcurrentVebTreeMap::max`], and successors and predecessors that skip
+/// to other shards are still correct across shards: they return an answer
+/// that held at some instant while they ran.  [`ConcurrentVebTreeMap::len`]
+/// may see the changes of a concurrent operation in some shards and not in
+/// others.
+pub struct ConcurrentVebTreeMap<K, V>
+where
+    K: VebKey,
+{
+    shards: Vec<RwLock<VebTreeMap<K, V>>>,
+    /// A bit for each shard that's set when the shard has keys.  The bits
+    /// only change while holding the shard's write lock.
+    occupied: Vec<AtomicU64>,
+    shard_bits: u8,
+    len: AtomicUsize,
+    /// Counts the inserts of new keys, so that a search across shards can
+    /// tell when a key may have been added behind it.
+    inserts: AtomicU64,
+}
+
+impl<K, V> Debug for ConcurrentVebTreeMap<K, V>
+where
+    K: ShardKey,
+    V: Clone + Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ConcurrentVebTreeMap")
+            .field("shards", &self.shards)
+            .field("occupied", &self.occupied)
+            .field("shard_bits", &self.shard_bits)
+            .field("len", &self.len)
+            .field("inserts", &self.inserts)
+            .finish()
+    }
+}
+
+impl<K, V> ConcurrentVebTreeMap<K, V>
+where
+    K: ShardKey,
+    V: Clone + Debug,
+{
+    /// Create an empty tree with 64 shards, or fewer for small keys.
+    pub fn new() -> ConcurrentVebTreeMap<K, V> {
+        Self::with_shard_bits(DEFAULT_SHARD_BITS.min(K::max_size() / 2))
+    }
+
+    /// Create an empty tree with 2^`bits` shards.  More shards let more
+    /// threads change the tree at once, but make skipping over empty shards
+    /// slower.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is more than 12 or more than the bits in a root
+    /// cluster number, which is half of the bits in the key type.
+    pub fn with_shard_bits(bits: u8) -> ConcurrentVebTreeMap<K, V> {
+        assert!(
+            bits <= MAX_SHARD_BITS && bits <= K::max_size() / 2,
+            "shards must split the root's clusters and be at most 2^{MAX_SHARD_BITS}: bits={bits}"
+        );
+        let shard_count = 1usize << bits;
+        ConcurrentVebTreeMap {
+            shards: (0..shard_count)
+                .map(|_| RwLock::new(VebTreeMap::new()))
+                .collect(),
+            occupied: (0..shard_count.div_ceil(64))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            shard_bits: bits,
+            len: AtomicUsize::new(0),
+            inserts: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Returns true if the tree has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Lookup a key in the tree and get its value.  Runs in O(lg lg u) time.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.read(self.shard_of(key)).get(key)
+    }
+
+    /// Insert a key-value pair into the tree.  Runs in O(lg lg u) time.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let shard = self.shard_of(&key);
+        let mut map = self.write(shard);
+        let old_value = map.insert(key, value);
+        if old_value.is_none() {
+            self.len.fetch_add(1, Ordering::AcqRel);
+            if map.len() == 1 {
+                self.occupied[shard / 64]
+                    .fetch_or(1 << (shard % 64), Ordering::Release);
+            }
+            // Bump the count while the shard is still locked, so a search
+            // that sees the key also sees the count.
+            self.inserts.fetch_add(1, Ordering::SeqCst);
+        }
+        old_value
+    }
+
+    /// Remove a key from the tree.  Runs in O(lg lg u) time.
+    pub fn remove(&self, key: &K) {
+        let shard = self.shard_of(key);
+        let mut map = self.write(shard);
+        if map.take(key).is_some() {
+            self.len.fetch_sub(1, Ordering::AcqRel);
+            if map.is_empty() {
+                self.occupied[shard / 64]
+                    .fetch_and(!(1 << (shard % 64)), Ordering::Release);
+            }
+        }
+    }
+
+    /// Get the minimum element in the tree.
+    pub fn min(&self) -> Option<(K, V)> {
+        self.across_shards(|| None, || self.after_shard(0))
+            .unwrap_or_else(|| {
+                let maps = self.read_range(0..self.shards.len());
+                maps.iter().find_map(|map| map.min())
+            })
+    }
+
+    /// Get the maximum element in the tree.
+    pub fn max(&self) -> Option<(K, V)> {
+        self.across_shards(|| None, || self.before_shard(self.shards.len()))
+            .unwrap_or_else(|| {
+                let maps = self.read_range(0..self.shards.len());
+                maps.iter().rev().find_map(|map| map.max())
+            })
+    }
+
+    /// Get the successor of the given key.  Runs in O(lg lg u) time, plus
+    /// the time to skip over the empty shards after the key's shard.
+    pub fn successor(&self, key: &K) -> Option<(K, V)> {
+        let shard = self.shard_of(key);
+        self.across_shards(
+            || self.read(shard).successor(key),
+            || self.after_shard(shard + 1),
+        )
+        .unwrap_or_else(|| {
+            let maps = self.read_range(shard..self.shards.len());
+            let (first, rest) = maps.split_first().expect("shard is locked");
+            first
+                .successor(key)
+                .or_else(|| rest.iter().find_map(|map| map.min()))
+        })
+    }
+
+    /// Get the predecessor of the given key.  Runs in O(lg lg u) time, plus
+    /// the time to skip over the empty shards before the key's shard.
+    pub fn predecessor(&self, key: &K) -> Option<(K, V)> {
+        let shard = self.shard_of(key);
+        self.across_shards(
+            || self.read(shard).predecessor(key),
+            || self.before_shard(shard),
+        )
+        .unwrap_or_else(|| {
+            let maps = self.read_range(0..shard + 1);
+            let (last, rest) = maps.split_last().expect("shard is locked");
+            last.predecessor(key)
+                .or_else(|| rest.iter().rev().find_map(|map| map.max()))
+        })
+    }
+
+    /// Search the key's shard with `own`, and when it has no answer, the
+    /// other shards with `rest`, which locks one shard at a time.  Returns
+    /// `None` when keys keep being inserted while `rest` runs, since it may
+    /// have passed over a shard before a key got there.
+    fn across_shards<T>(
+        &self,
+        own: impl Fn() -> Option<T>,
+        rest: impl Fn() -> Option<T>,
+    ) -> Option<Option<T>> {
+        for _ in 0..SEARCH_TRIES {
+            let inserts = self.inserts.load(Ordering::SeqCst);
+            // The shard's lock makes an answer from it correct on its own.
+            if let Some(found) = own() {
+                return Some(Some(found));
+            }
+            let found = rest();
+            // Keep the count from being read before the shards.
+            fence(Ordering::SeqCst);
+            if self.inserts.load(Ordering::SeqCst) == inserts {
+                return Some(found);
+            }
+        }
+        None
+    }
+
+    /// The minimum of the first non-empty shard at or after `start`.
+    fn after_shard(&self, start: usize) -> Option<(K, V)> {
+        let mut next = start;
+        while let Some(shard) = self.next_occupied(next) {
+            // The shard may have been emptied since the bit was read.
+            if let Some(min) = self.read(shard).min() {
+                return Some(min);
+            }
+            next = shard + 1;
+        }
+        None
+    }
+
+    /// The maximum of the last non-empty shard before the given one.
+    fn before_shard(&self, shard: usize) -> Option<(K, V)> {
+        let mut end = shard;
+        while let Some(shard) = self.previous_occupied(end) {
+            if let Some(max) = self.read(shard).max() {
+                return Some(max);
+            }
+            end = shard;
+        }
+        None
+    }
+
+    /// The first occupied shard at or after `start`.
+    fn next_occupied(&self, start: usize) -> Option<usize> {
+        let mut word_index = start / 64;
+        let mut mask = u64::MAX << bit_index(start);
+        while let Some(word) = self.occupied.get(word_index) {
+            let word = word.load(Ordering::Acquire) & mask;
+            if word != 0 {
+                return Some(word_index * 64 + to_usize(word.trailing_zeros()));
+            }
+            word_index += 1;
+            mask = u64::MAX;
+        }
+        None
+    }
+
+    /// The last occupied shard before `end`.
+    fn previous_occupied(&self, end: usize) -> Option<usize> {
+        let last = end.checked_sub(1)?;
+        let mut word_index = last / 64;
+        let mut mask = u64::MAX >> (63 - bit_index(last));
+        loop {
+            let word = self.occupied[word_index].load(Ordering::Acquire) & mask;
+            if word != 0 {
+                return Some(
+                    word_index * 64 + 63 - to_usize(word.leading_zeros()),
+                );
+            }
+            word_index = word_index.checked_sub(1)?;
+            mask = u64::MAX;
+        }
+    }
+
+    /// The shard of a key, from the high bits of its root cluster number.
+    fn shard_of(&self, key: &K) -> usize {
+        key.top_bits(self.shard_bits)
+    }
+
+    fn read(&self, shard: usize) -> RwLockReadGuard<'_, VebTreeMap<K, V>> {
+        self.shards[shard]
+            .read()
+            .expect("shard lock must not be poisoned")
+    }
+
+    /// Lock a range of shards for reading, in increasing order.  That's the
+    /// only order that locks are held together in, so it can't deadlock.
+    fn read_range(
+        &self,
+        shards: Range<usize>,
+    ) -> Vec<RwLockReadGuard<'_, VebTreeMap<K, V>>> {
+        shards.map(|shard| self.read(shard)).collect()
+    }
+
+    fn write(&self, shard: usize) -> RwLockWriteGuard<'_, VebTreeMap<K, V>> {
+        self.shards[shard]
+            .write()
+            .expect("shard lock must not be poisoned")
+    }
+}
+
+impl<K, V> Default for ConcurrentVebTreeMap<K, V>
+where
+    K: ShardKey,
+    V: Clone + Debug,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bit_index(shard: usize) -> u32 {
+    u32::try_from(shard % 64).expect("bit index must fit in u32")
+}
+
+fn to_usize(n: u32) -> usize {
+    usize::try_from(n).expect("u32 must fit in usize")
+}
@@ -0,0 +1,358 @@
+//! Cursors, which walk over the entries of a tree in order.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::ops::Bound;
+
+use crate::instrument::Operation;
+use crate::{Finger, VebKey, VebTreeMap};
+
+/// The entries on either side of a gap between entries.
+type Gap<'a, K, V> = (Option<(K, &'a V)>, Option<(K, &'a V)>);
+
+/// A cursor over the entries of a tree.
+///
+/// Like the cursors of `BTreeMap`, a cursor is positioned in a gap between
+/// two entries, or before the minimum, or after the maximum.  Moving steps
+/// over the entry on one side of the gap and stops at the ends instead of
+/// wrapping around.  Moving the cursor keeps a [`Finger`] to the last key it
+/// stepped over, so walking over a run of nearby keys skips most of the hash
+/// lookups that repeated calls to [`VebTreeMap::successor`] would do.
+#[derive(Clone)]
+pub struct Cursor<'a, K, V>
+where
+    K: VebKey,
+{
+    map: &'a VebTreeMap<K, V>,
+    prev: Option<(K, &'a V)>,
+    next: Option<(K, &'a V)>,
+    finger: Finger<K>,
+}
+
+impl<K, V> Debug for Cursor<'_, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Cursor")
+            .field("map", &self.map)
+            .field("prev", &self.prev)
+            .field("next", &self.next)
+            .field("finger", &self.finger)
+            .finish()
+    }
+}
+
+/// A cursor over the entries of a tree, which can also change the tree.
+///
+/// See [`Cursor`] for how the cursor moves.  Only the keys on either side of
+/// the gap are kept, since the tree can't change except through the cursor.
+pub struct CursorMut<'a, K, V>
+where
+    K: VebKey,
+{
+    map: &'a mut VebTreeMap<K, V>,
+    prev: Option<K>,
+    next: Option<K>,
+    finger: Finger<K>,
+}
+
+impl<K, V> Debug for CursorMut<'_, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CursorMut")
+            .field("map", &self.map)
+            .field("prev", &self.prev)
+            .field("next", &self.next)
+            .field("finger", &self.finger)
+            .finish()
+    }
+}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Get a cursor in the gap before the first entry above the bound.  Runs
+    /// in O(lg lg u) time.
+    pub fn lower_bound(&self, bound: Bound<&K>) -> Cursor<'_, K, V> {
+        let (prev, next) = self.lower_bound_gap(bound);
+        Cursor {
+            map: self,
+            prev,
+            next,
+            finger: Finger::new(),
+        }
+    }
+
+    /// Get a cursor in the gap after the last entry below the bound.  Runs
+    /// in O(lg lg u) time.
+    pub fn upper_bound(&self, bound: Bound<&K>) -> Cursor<'_, K, V> {
+        let (prev, next) = self.upper_bound_gap(bound);
+        Cursor {
+            map: self,
+            prev,
+            next,
+            finger: Finger::new(),
+        }
+    }
+
+    /// Same as [`VebTreeMap::lower_bound`], but the cursor can change the
+    /// tree.
+    pub fn lower_bound_mut(&mut self, bound: Bound<&K>) -> CursorMut<'_, K, V> {
+        let (prev, next) = self.lower_bound_gap(bound);
+        let (prev, next) = (prev.map(|(k, _)| k), next.map(|(k, _)| k));
+        CursorMut {
+            map: self,
+            prev,
+            next,
+            finger: Finger::new(),
+        }
+    }
+
+    /// Same as [`VebTreeMap::upper_bound`], but the cursor can change the
+    /// tree.
+    pub fn upper_bound_mut(&mut self, bound: Bound<&K>) -> CursorMut<'_, K, V> {
+        let (prev, next) = self.upper_bound_gap(bound);
+        let (prev, next) = (prev.map(|(k, _)| k), next.map(|(k, _)| k));
+        CursorMut {
+            map: self,
+            prev,
+            next,
+            finger: Finger::new(),
+        }
+    }
+
+    fn lower_bound_gap(&self, bound: Bound<&K>) -> Gap<'_, K, V> {
+        self.measure(Operation::CursorSeek, |_| match bound {
+            Bound::Unbounded => (None, self.min_entry()),
+            Bound::Included(key) => self.gap_before(key),
+            Bound::Excluded(key) => self.gap_after(key),
+        })
+    }
+
+    fn upper_bound_gap(&self, bound: Bound<&K>) -> Gap<'_, K, V> {
+        self.measure(Operation::CursorSeek, |_| match bound {
+            Bound::Unbounded => (self.max_entry(), None),
+            Bound::Included(key) => self.gap_after(key),
+            Bound::Excluded(key) => self.gap_before(key),
+        })
+    }
+
+    /// The gap right before a key, whether or not it's in the tree.
+    fn gap_before(&self, key: &K) -> Gap<'_, K, V> {
+        if !self.in_universe(key) {
+            return (self.max_entry(), None);
+        }
+        let Some(root) = self.root else {
+            return (None, None);
+        };
+        (
+            self.arena.predecessor(root, key),
+            self.arena.ceiling(root, key),
+        )
+    }
+
+    /// The gap right after a key, whether or not it's in the tree.
+    fn gap_after(&self, key: &K) -> Gap<'_, K, V> {
+        if !self.in_universe(key) {
+            return (self.max_entry(), None);
+        }
+        let Some(root) = self.root else {
+            return (None, None);
+        };
+        (self.arena.floor(root, key), self.arena.successor(root, key))
+    }
+}
+
+impl<'a, K, V> Cursor<'a, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Move over the entry after the gap and return it, or stay put and
+    /// return `None` after the maximum.  Runs in O(lg lg u) time.
+    pub fn move_next(&mut self) -> Option<(K, &'a V)> {
+        let (key, value) = self.next.take()?;
+        let map = self.map;
+        self.next = map.measure(Operation::CursorMove, |_| {
+            map.successor_entry_near(&mut self.finger, &key)
+        });
+        self.prev = Some((key.clone(), value));
+        Some((key, value))
+    }
+
+    /// Move over the entry before the gap and return it, or stay put and
+    /// return `None` before the minimum.  Runs in O(lg lg u) time.
+    pub fn move_prev(&mut self) -> Option<(K, &'a V)> {
+        let (key, value) = self.prev.take()?;
+        let map = self.map;
+        self.prev = map.measure(Operation::CursorMove, |_| {
+            map.predecessor_entry_near(&mut self.finger, &key)
+        });
+        self.next = Some((key.clone(), value));
+        Some((key, value))
+    }
+
+    /// The entry after the gap, which [`Cursor::move_next`] would move over.
+    /// Runs in O(1) time.
+    pub fn peek_next(&self) -> Option<(K, &'a V)> {
+        self.next.clone()
+    }
+
+    /// The entry before the gap, which [`Cursor::move_prev`] would move over.
+    /// Runs in O(1) time.
+    pub fn peek_prev(&self) -> Option<(K, &'a V)> {
+        self.prev.clone()
+    }
+}
+
+impl<K, V> CursorMut<'_, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// The value of a key that the cursor knows is in the tree.
+    fn value_mut(&mut self, key: &K) -> &mut V {
+        #[cfg(feature = "safety_checks")]
+        self.map.shadow.lend();
+        let root = self.map.root.expect("tree with a key should have a root");
+        self.map
+            .arena
+            .get_mut(root, key)
+            .expect("key next to the cursor should be in the tree")
+    }
+
+    /// Same as [`Cursor::move_next`], but the value can be changed.
+    pub fn move_next(&mut self) -> Option<(K, &mut V)> {
+        let key = self.next.take()?;
+        self.next = self.map.measure(Operation::CursorMove, |map| {
+            map.successor_entry_near(&mut self.finger, &key)
+                .map(|(k, _)| k)
+        });
+        self.prev = Some(key.clone());
+        let value = self.value_mut(&key);
+        Some((key, value))
+    }
+
+    /// Same as [`Cursor::move_prev`], but the value can be changed.
+    pub fn move_prev(&mut self) -> Option<(K, &mut V)> {
+        let key = self.prev.take()?;
+        self.prev = self.map.measure(Operation::CursorMove, |map| {
+            map.predecessor_entry_near(&mut self.finger, &key)
+                .map(|(k, _)| k)
+        });
+        self.next = Some(key.clone());
+        let value = self.value_mut(&key);
+        Some((key, value))
+    }
+
+    /// Same as [`Cursor::peek_next`], but the value can be changed.  Runs in
+    /// O(lg lg u) time to find the value.
+    pub fn peek_next(&mut self) -> Option<(K, &mut V)> {
+        let key = self.next.clone()?;
+        let value = self.value_mut(&key);
+        Some((key, value))
+    }
+
+    /// Same as [`Cursor::peek_prev`], but the value can be changed.  Runs in
+    /// O(lg lg u) time to find the value.
+    pub fn peek_prev(&mut self) -> Option<(K, &mut V)> {
+        let key = self.prev.clone()?;
+        let value = self.value_mut(&key);
+        Some((key, value))
+    }
+
+    /// Get a read-only cursor in the same gap.  Runs in O(lg lg u) time.
+    pub fn as_cursor(&self) -> Cursor<'_, K, V> {
+        let entry = |key: &Option<K>| {
+            let key = key.clone()?;
+            let value = self.map.arena.get(self.map.root?, &key)?;
+            Some((key, value))
+        };
+        Cursor {
+            map: self.map,
+            prev: entry(&self.prev),
+            next: entry(&self.next),
+            finger: self.finger.clone(),
+        }
+    }
+
+    /// Insert an entry into the gap, leaving the cursor before it.  Runs in
+    /// O(lg lg u) time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key isn't strictly between the entries on either side
+    /// of the gap, because the entry wouldn't end up in the gap, or if it
+    /// doesn't fit in the tree's universe, which can only happen in the last
+    /// gap.
+    pub fn insert_after(&mut self, key: K, value: V) {
+        self.assert_in_gap(&key);
+        self.map.insert_near(&mut self.finger, key.clone(), value);
+        self.next = Some(key);
+    }
+
+    /// Insert an entry into the gap, leaving the cursor after it.  Runs in
+    /// O(lg lg u) time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key isn't strictly between the entries on either side
+    /// of the gap, because the entry wouldn't end up in the gap, or if it
+    /// doesn't fit in the tree's universe, which can only happen in the last
+    /// gap.
+    pub fn insert_before(&mut self, key: K, value: V) {
+        self.assert_in_gap(&key);
+        self.map.insert_near(&mut self.finger, key.clone(), value);
+        self.prev = Some(key);
+    }
+
+    fn assert_in_gap(&self, key: &K) {
+        self.map.assert_in_universe(key);
+        assert!(
+            self.prev.as_ref().is_none_or(|prev| key > prev)
+                && self.next.as_ref().is_none_or(|next| key < next),
+            "key {key:?} must be in the gap between {:?} and {:?}",
+            self.prev,
+            self.next,
+        );
+    }
+
+    /// Remove the entry after the gap and return it, or return `None` after
+    /// the maximum.  The cursor stays in the same gap, which now ends at the
+    /// following entry.  Runs in O(lg lg u) time.
+    pub fn remove_next(&mut self) -> Option<(K, V)> {
+        let key = self.next.take()?;
+        let value = self
+            .map
+            .take(&key)
+            .expect("key next to the cursor should be in the tree");
+        self.next = self.map.measure(Operation::CursorMove, |map| {
+            map.successor_entry_near(&mut self.finger, &key)
+                .map(|(k, _)| k)
+        });
+        Some((key, value))
+    }
+
+    /// Remove the entry before the gap and return it, or return `None`
+    /// before the minimum.  The cursor stays in the same gap, which now
+    /// starts at the preceding entry.  Runs in O(lg lg u) time.
+    pub fn remove_prev(&mut self) -> Option<(K, V)> {
+        let key = self.prev.take()?;
+        let value = self
+            .map
+            .take(&key)
+            .expect("key next to the cursor should be in the tree");
+        self.prev = self.map.measure(Operation::CursorMove, |map| {
+            map.predecessor_entry_near(&mut self.finger, &key)
+                .map(|(k, _)| k)
+        });
+        Some((key, value))
+    }
+}
@@ -0,0 +1,82 @@
+//! Drawing the internal structure of a tree with Graphviz, for debugging.
+
+use core::fmt::{self, Debug};
+use core::hash::Hash;
+
+use crate::{VebKey, VebTreeMap};
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Get a Graphviz DOT graph of the tree's nodes, which displays as the
+    /// graph's source.  See [`Dot`].
+    pub fn dot(&self) -> Dot<'_, K, V> {
+        Dot {
+            map: self,
+            max_depth: None,
+        }
+    }
+}
+
+/// A Graphviz DOT graph of the nodes of a [`VebTreeMap`], from
+/// [`VebTreeMap::dot`].  Formatting it with `Display` writes the graph's
+/// source, which `dot -Tsvg` can render.
+///
+/// Each node shows its min and max entries and the size of its clusters in
+/// bits.  Solid edges lead to its clusters and are labeled with their
+/// numbers, and the keys in a cluster are relative to it.  A dashed edge
+/// leads to its summary, which is a node of the summary arena and shows the
+/// cluster numbers as keys.  Nodes in the tree's arena are named
+/// `a0n<index>`, and nodes in the `n`th summary arena below it are named
+/// `a<n>n<index>`.
+#[derive(Clone, Copy)]
+pub struct Dot<'a, K, V>
+where
+    K: VebKey,
+{
+    map: &'a VebTreeMap<K, V>,
+    max_depth: Option<usize>,
+}
+
+impl<K, V> Debug for Dot<'_, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dot")
+            .field("map", &self.map)
+            .field("max_depth", &self.max_depth)
+            .finish()
+    }
+}
+
+impl<K, V> Dot<'_, K, V>
+where
+    K: VebKey,
+{
+    /// Only draw nodes up to `max_depth` levels of clusters below the root,
+    /// or below the root of a summary.  The clusters left out of a node are
+    /// drawn as a single `...` node.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+}
+
+impl<K, V> fmt::Display for Dot<'_, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "digraph veb {{")?;
+        writeln!(f, "    node [shape=box];")?;
+        if let Some(root) = self.map.root {
+            self.map.arena.write_dot(f, 0, root, self.max_depth, true)?;
+        }
+        writeln!(f, "}}")
+    }
+}
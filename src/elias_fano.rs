@@ -0,0 +1,462 @@
+//! A compressed, static set of integer keys using the Elias-Fano encoding.
+//!
+//! Each key is split into its high and low bits.  The low bits are packed
+//! together, `low_bits` per key.  The high bits are stored in unary as a bit
+//! vector with a one for each key, at its high bits plus its index, so the
+//! zeros between the ones count up the high bits.  This takes at most 2 +
+//! lg(u / n) bits per key, which is close to the minimum for a set of n keys
+//! from a universe of u.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::marker::PhantomData;
+use core::ops::Bound;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{VebKey, VebTreeMap};
+
+/// A key type that can be stored in an [`EliasFanoSet`].
+pub trait EliasFanoKey: VebKey + Copy + Ord + Hash + Debug {
+    /// Widen the key.
+    fn to_u64(self) -> u64;
+    /// Narrow a key that came from [`EliasFanoKey::to_u64`].
+    fn from_u64(key: u64) -> Self;
+}
+
+macro_rules! impl_elias_fano_key {
+    ($typ: ty) => {
+        impl EliasFanoKey for $typ {
+            fn to_u64(self) -> u64 {
+                u64::try_from(self).expect("key must fit in u64")
+            }
+
+            fn from_u64(key: u64) -> Self {
+                Self::try_from(key).expect("key must fit in the key type")
+            }
+        }
+    };
+}
+
+impl_elias_fano_key!(u8);
+impl_elias_fano_key!(u16);
+impl_elias_fano_key!(u32);
+impl_elias_fano_key!(u64);
+impl_elias_fano_key!(usize);
+
+/// How many ones, or zeros, are in each block of the select index.
+const BLOCK: usize = 512;
+/// How many ones, or zeros, are in each group within a block.
+const GROUP: usize = 64;
+/// The most bits that select scans, the longest that a group can span
+/// without storing where each of its ones, or zeros, is.
+const MAX_SCAN: usize = 2048;
+
+/// A static set of keys compressed with the Elias-Fano encoding.
+///
+/// It's much smaller than a [`VebTreeMap`], but it can't change.  Select
+/// runs in O(1) time, by finding the key's high bits with an index of the
+/// ones.  Rank, successor and predecessor find the keys that share the high
+/// bits of the key in O(1) time, and binary search their low bits, which
+/// takes O(lg(u / n)) time.
+#[derive(Debug, Clone)]
+pub struct EliasFanoSet<K> {
+    len: usize,
+    low_bits: u32,
+    lows: Vec<u64>,
+    highs: BitVector,
+    marker: PhantomData<K>,
+}
+
+impl<K> EliasFanoSet<K>
+where
+    K: EliasFanoKey,
+{
+    /// Build a set from keys in strictly increasing order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keys aren't strictly increasing.
+    pub fn from_sorted_iter<I>(iter: I) -> EliasFanoSet<K>
+    where
+        I: IntoIterator<Item = K>,
+    {
+        let keys: Vec<u64> = iter.into_iter().map(K::to_u64).collect();
+        for pair in keys.windows(2) {
+            assert!(
+                pair[0] < pair[1],
+                "keys must be strictly increasing: key={:?}, previous={:?}",
+                pair[1],
+                pair[0]
+            );
+        }
+        let len = keys.len();
+        let max = keys.last().copied().unwrap_or(0);
+        // lg(u / n), but keeping the low bits narrower than a word.
+        let low_bits = (u128::from(max) + 1)
+            .checked_div(u128::try_from(len).expect("length must fit in u128"))
+            .filter(|ratio| *ratio > 0)
+            .map_or(0, |ratio| ratio.ilog2().min(63));
+
+        let mut lows = vec![0; (len * to_usize(low_bits)).div_ceil(64)];
+        let high_len = len + to_usize(max >> low_bits) + 1;
+        let mut highs = vec![0; high_len.div_ceil(64)];
+        for (i, key) in keys.iter().enumerate() {
+            set_bits(&mut lows, i * to_usize(low_bits), low_bits, *key);
+            let position = to_usize(key >> low_bits) + i;
+            highs[position / 64] |= 1 << (position % 64);
+        }
+        EliasFanoSet {
+            len,
+            low_bits,
+            lows,
+            highs: BitVector::new(highs, high_len),
+            marker: PhantomData,
+        }
+    }
+
+    /// Build a set of the keys of a tree.  Runs in O(n lg lg u) time.
+    pub fn from_map<V>(map: &VebTreeMap<K, V>) -> EliasFanoSet<K>
+    where
+        V: Clone + Debug,
+    {
+        let mut cursor = map.lower_bound(Bound::Unbounded);
+        Self::from_sorted_iter(core::iter::from_fn(|| {
+            cursor.move_next().map(|(key, _)| key)
+        }))
+    }
+
+    /// Build a tree of the keys in the set.  Runs in O(n lg lg u) time.
+    pub fn to_map(&self) -> VebTreeMap<K, ()> {
+        VebTreeMap::from_sorted_iter(self.iter().map(|key| (key, ())))
+    }
+
+    /// Returns the number of keys in the set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the set has no keys.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of bytes used by the encoding.
+    pub fn size_in_bytes(&self) -> usize {
+        (self.lows.len() + self.highs.words.len()) * size_of::<u64>()
+            + self.highs.ones.size_in_bytes()
+            + self.highs.zeros.size_in_bytes()
+    }
+
+    /// Returns true if the key is in the set.
+    pub fn contains(&self, key: &K) -> bool {
+        let i = self.rank(key);
+        i < self.len && self.get(i) == key.to_u64()
+    }
+
+    /// Get the key at an index in sorted order, or `None` when the index is
+    /// out of bounds.  Runs in O(1) time.
+    pub fn select(&self, i: usize) -> Option<K> {
+        (i < self.len).then(|| K::from_u64(self.get(i)))
+    }
+
+    /// Get the number of keys in the set less than the given key.
+    pub fn rank(&self, key: &K) -> usize {
+        self.rank_u64(key.to_u64())
+    }
+
+    /// Get the smallest key greater than the given key.
+    pub fn successor(&self, key: &K) -> Option<K> {
+        let next = key.to_u64().checked_add(1)?;
+        self.select(self.rank_u64(next))
+    }
+
+    /// Get the largest key less than the given key.
+    pub fn predecessor(&self, key: &K) -> Option<K> {
+        let i = self.rank(key).checked_sub(1)?;
+        self.select(i)
+    }
+
+    /// Iterate over the keys in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = K> + '_ {
+        (0..self.len).map(|i| K::from_u64(self.get(i)))
+    }
+
+    /// The number of keys less than the given widened key.
+    fn rank_u64(&self, key: u64) -> usize {
+        let high = to_usize(key >> self.low_bits);
+        // The keys with smaller high bits come before the high'th zero.
+        let Some(start) = (match high {
+            0 => Some(0),
+            _ => self.highs.select0(high - 1).map(|zero| zero + 1),
+        }) else {
+            return self.len;
+        };
+        // The keys with the same high bits end at the next zero.
+        let end = self.highs.select0(high).unwrap_or(self.highs.len);
+        // Binary search the keys with the same high bits by their low bits.
+        let low = key & low_mask(self.low_bits);
+        let (mut first, mut last) = (start - high, end - high);
+        while first < last {
+            let middle = first + (last - first) / 2;
+            if self.low(middle) < low {
+                first = middle + 1;
+            } else {
+                last = middle;
+            }
+        }
+        first
+    }
+
+    /// The key at an index, which must be in bounds.
+    fn get(&self, i: usize) -> u64 {
+        let position = self.highs.select1(i).expect("index must be in bounds");
+        let high = u64::try_from(position - i).expect("high bits fit in u64");
+        (high << self.low_bits) | self.low(i)
+    }
+
+    /// The low bits of the key at an index.
+    fn low(&self, i: usize) -> u64 {
+        get_bits(&self.lows, i * to_usize(self.low_bits), self.low_bits)
+    }
+}
+
+impl<K, V> From<&VebTreeMap<K, V>> for EliasFanoSet<K>
+where
+    K: EliasFanoKey,
+    V: Clone + Debug,
+{
+    fn from(map: &VebTreeMap<K, V>) -> Self {
+        EliasFanoSet::from_map(map)
+    }
+}
+
+impl<K> From<&EliasFanoSet<K>> for VebTreeMap<K, ()>
+where
+    K: EliasFanoKey,
+{
+    fn from(set: &EliasFanoSet<K>) -> Self {
+        set.to_map()
+    }
+}
+
+/// A bit vector with indexes to find the ith one or zero without scanning
+/// from the start.
+#[derive(Debug, Clone)]
+struct BitVector {
+    words: Vec<u64>,
+    len: usize,
+    ones: SelectIndex,
+    zeros: SelectIndex,
+}
+
+impl BitVector {
+    fn new(words: Vec<u64>, len: usize) -> BitVector {
+        let ones = SelectIndex::new(&words, len, |word| word);
+        let zeros = SelectIndex::new(&words, len, |word| !word);
+        BitVector {
+            words,
+            len,
+            ones,
+            zeros,
+        }
+    }
+
+    /// The position of the ith one.
+    fn select1(&self, i: usize) -> Option<usize> {
+        self.ones.select(&self.words, i, |word| word)
+    }
+
+    /// The position of the ith zero.
+    fn select0(&self, i: usize) -> Option<usize> {
+        self.zeros.select(&self.words, i, |word| !word)
+    }
+}
+
+/// Where the ones of a bit vector are, or its zeros with the words flipped,
+/// in the style of Okanohara and Sadakane's darray.
+///
+/// The ones are split into blocks of [`BLOCK`].  A block that spans 2^16 bits
+/// or more is sparse, and the position of each of its ones is stored.  Since
+/// it spans at least 128 bits per one, that takes at most half as many bits
+/// as it spans.  In a dense block, the offsets from its first one fit in 16
+/// bits, and the offset of the first one of every group of [`GROUP`] is
+/// stored.  A group that spans more than [`MAX_SCAN`] bits has the offset of
+/// each of its ones stored too, which likewise takes at most half as many
+/// bits as it spans.  So select reads one stored position, or scans at most
+/// [`MAX_SCAN`] bits.
+#[derive(Debug, Clone)]
+struct SelectIndex {
+    /// The number of ones.
+    count: usize,
+    blocks: Vec<Block>,
+    /// The positions of the ones in sparse blocks.
+    positions: Vec<usize>,
+    /// For each dense block: the offsets of the first one of each of its
+    /// groups and of its last one, followed by the offsets of every one in
+    /// each of its sparse groups.
+    offsets: Vec<u16>,
+}
+
+#[derive(Debug, Clone)]
+enum Block {
+    /// A block whose ones' positions start at an index in `positions`.
+    Sparse(usize),
+    /// A block whose first one is at `first`, and whose offsets start at
+    /// an index in `offsets`.
+    Dense { first: usize, offsets: usize },
+}
+
+impl SelectIndex {
+    fn new(words: &[u64], len: usize, flip: impl Fn(u64) -> u64) -> Self {
+        let mut index = SelectIndex {
+            count: 0,
+            blocks: Vec::new(),
+            positions: Vec::new(),
+            offsets: Vec::new(),
+        };
+        let mut block = Vec::with_capacity(BLOCK);
+        for (word_index, word) in words.iter().enumerate() {
+            let mut word = flip(*word);
+            while word != 0 {
+                let position =
+                    word_index * 64 + to_usize(word.trailing_zeros());
+                if position >= len {
+                    // The padding after the last bit.
+                    break;
+                }
+                block.push(position);
+                if block.len() == BLOCK {
+                    index.push_block(&block);
+                    block.clear();
+                }
+                word &= word - 1;
+            }
+        }
+        if !block.is_empty() {
+            index.push_block(&block);
+        }
+        index
+    }
+
+    fn push_block(&mut self, block: &[usize]) {
+        self.count += block.len();
+        let first = block[0];
+        let last = block[block.len() - 1];
+        if u16::try_from(last - first).is_err() {
+            self.blocks.push(Block::Sparse(self.positions.len()));
+            self.positions.extend_from_slice(block);
+            return;
+        }
+        let offset = |position: usize| {
+            u16::try_from(position - first).expect("dense block offset fits")
+        };
+        self.blocks.push(Block::Dense {
+            first,
+            offsets: self.offsets.len(),
+        });
+        let groups: Vec<&[usize]> = block.chunks(GROUP).collect();
+        self.offsets
+            .extend(groups.iter().map(|group| offset(group[0])));
+        self.offsets.push(offset(last));
+        for (g, group) in groups.iter().enumerate() {
+            let end = groups.get(g + 1).map_or(last, |next| next[0]);
+            if end - group[0] > MAX_SCAN {
+                self.offsets.extend(group.iter().map(|&p| offset(p)));
+            }
+        }
+    }
+
+    /// The position of the ith one, in the words after `flip`.
+    fn select(
+        &self,
+        words: &[u64],
+        i: usize,
+        flip: impl Fn(u64) -> u64,
+    ) -> Option<usize> {
+        if i >= self.count {
+            return None;
+        }
+        let (first, offsets) = match self.blocks[i / BLOCK] {
+            Block::Sparse(positions) => {
+                return Some(self.positions[positions + i % BLOCK]);
+            }
+            Block::Dense { first, offsets } => (first, offsets),
+        };
+        let ones = (self.count - i / BLOCK * BLOCK).min(BLOCK);
+        let groups = ones.div_ceil(GROUP);
+        let (g, k) = (i % BLOCK / GROUP, i % GROUP);
+        let group = &self.offsets[offsets..=offsets + groups];
+        let span = |g: usize| usize::from(group[g + 1] - group[g]);
+        let start = first + usize::from(group[g]);
+        if span(g) > MAX_SCAN {
+            // The offsets of the sparse groups' ones follow in order.
+            let sparse = (0..g).filter(|&g| span(g) > MAX_SCAN).count();
+            let at = offsets + groups + 1 + sparse * GROUP + k;
+            return Some(first + usize::from(self.offsets[at]));
+        }
+        // Scan the group from its first one, across at most MAX_SCAN bits.
+        let mut remaining = k;
+        let mut word_index = start / 64;
+        let mut mask = u64::MAX << (start % 64);
+        loop {
+            let mut word = flip(words[word_index]) & mask;
+            let count = to_usize(word.count_ones());
+            if remaining < count {
+                for _ in 0..remaining {
+                    word &= word - 1;
+                }
+                return Some(word_index * 64 + to_usize(word.trailing_zeros()));
+            }
+            remaining -= count;
+            word_index += 1;
+            mask = u64::MAX;
+        }
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.blocks.len() * size_of::<Block>()
+            + self.positions.len() * size_of::<usize>()
+            + self.offsets.len() * size_of::<u16>()
+    }
+}
+
+/// Write the low `width` bits of a value at a bit position.
+fn set_bits(words: &mut [u64], position: usize, width: u32, value: u64) {
+    if width == 0 {
+        return;
+    }
+    let value = value & low_mask(width);
+    let (index, offset) = (position / 64, position % 64);
+    words[index] |= value << offset;
+    let written = 64 - offset;
+    if to_usize(width) > written {
+        words[index + 1] |= value >> written;
+    }
+}
+
+/// Read `width` bits at a bit position.
+fn get_bits(words: &[u64], position: usize, width: u32) -> u64 {
+    if width == 0 {
+        return 0;
+    }
+    let (index, offset) = (position / 64, position % 64);
+    let mut value = words[index] >> offset;
+    let read = 64 - offset;
+    if to_usize(width) > read {
+        value |= words[index + 1] << read;
+    }
+    value & low_mask(width)
+}
+
+fn low_mask(width: u32) -> u64 {
+    u64::MAX.checked_shr(64 - width).unwrap_or(0)
+}
+
+fn to_usize<T>(n: T) -> usize
+where
+    usize: TryFrom<T>,
+{
+    usize::try_from(n).unwrap_or_else(|_| panic!("value must fit in usize"))
+}
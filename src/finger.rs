@@ -0,0 +1,318 @@
+//! Fingers, which remember a path down a tree so that operations on nearby
+//! keys don't have to find it again.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+
+use alloc::vec::Vec;
+
+use crate::arena::{Arena, NodeId, Placement, Prefix, Probe, Step};
+use crate::instrument::{Counter, Operation};
+use crate::{VebKey, VebTreeMap};
+
+/// A remembered path down a tree to the last key it was used with.
+///
+/// Each level of the path is a link from a node to one of its clusters.
+/// Operations given a finger reuse the links that the new key shares with the
+/// previous one instead of looking them up, so runs of nearby keys, like
+/// nearly sorted inserts, skip most of the hash lookups.
+///
+/// A finger can be used with any tree, but it only helps when it's used with
+/// the same tree over and over.  Whenever the tree releases a node, for
+/// example because of a remove, the finger starts over from the root.
+#[derive(Debug, Clone)]
+pub struct Finger<K> {
+    /// The arena version that the links were seen at.
+    version: Option<u64>,
+    path: Vec<Link<K>>,
+}
+
+/// A link from a node to the cluster with number `h`.
+#[derive(Debug, Clone)]
+struct Link<K> {
+    node: NodeId,
+    h: K,
+    cluster: NodeId,
+}
+
+impl<K> Finger<K> {
+    /// Create a finger that doesn't remember any path yet.
+    pub fn new() -> Finger<K> {
+        Finger {
+            version: None,
+            path: Vec::new(),
+        }
+    }
+}
+
+impl<K> Default for Finger<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Finger<K>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+{
+    /// Forget the path when it was seen at a different version of the arena.
+    fn sync<V>(&mut self, arena: &Arena<K, V>) {
+        if self.version != Some(arena.version()) {
+            self.version = Some(arena.version());
+            self.path.clear();
+        }
+    }
+
+    /// The remembered link at this depth, when it leads from the same node
+    /// into the same cluster.  Otherwise, the rest of the path is forgotten.
+    fn follow(&mut self, depth: usize, id: NodeId, h: &K) -> Option<NodeId> {
+        match self.path.get(depth) {
+            Some(link) if link.node == id && link.h == *h => Some(link.cluster),
+            _ => {
+                self.path.truncate(depth);
+                None
+            }
+        }
+    }
+
+    /// Find the cluster with number `h` of the node at this depth.
+    fn cluster<V>(
+        &mut self,
+        arena: &Arena<K, V>,
+        depth: usize,
+        id: NodeId,
+        h: &K,
+    ) -> Option<NodeId> {
+        if let Some(cluster) = self.follow(depth, id, h) {
+            return Some(cluster);
+        }
+        let cluster = arena.cluster(id, h)?;
+        self.path.push(Link {
+            node: id,
+            h: h.clone(),
+            cluster,
+        });
+        Some(cluster)
+    }
+
+    /// Find the cluster with number `h` of the node at this depth, creating it
+    /// when it doesn't exist.
+    fn cluster_or_insert<V>(
+        &mut self,
+        arena: &mut Arena<K, V>,
+        depth: usize,
+        id: NodeId,
+        h: K,
+        cluster_size: K::Size,
+    ) -> NodeId {
+        if let Some(cluster) = self.follow(depth, id, &h) {
+            return cluster;
+        }
+        let cluster = arena.cluster_or_insert(id, h.clone(), cluster_size);
+        self.path.push(Link {
+            node: id,
+            h,
+            cluster,
+        });
+        cluster
+    }
+}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Same as [`VebTreeMap::get`], but starting from the path that the
+    /// finger remembers.  Runs in O(lg lg u) time, but only does hash lookups
+    /// below where the key's path leaves the finger's.
+    pub fn get_near(&self, finger: &mut Finger<K>, key: &K) -> Option<V> {
+        if !self.in_universe(key) {
+            return None;
+        }
+        self.measure(Operation::GetNear, |map| {
+            map.value_near(finger, key).cloned()
+        })
+    }
+
+    /// The value of a key, without cloning it.
+    fn value_near(&self, finger: &mut Finger<K>, key: &K) -> Option<&V> {
+        let mut id = self.root?;
+        finger.sync(&self.arena);
+
+        let mut key = key.clone();
+        let mut depth = 0;
+        loop {
+            self.arena.count(Counter::Visits);
+            let cluster_size = &self.arena.node(id).cluster_size;
+            match self.arena.probe(id, &key, cluster_size, |h| {
+                finger.cluster(&self.arena, depth, id, h)
+            }) {
+                Probe::Found(value) => return Some(value),
+                Probe::Absent => return None,
+                Probe::Cluster(cluster, l) => {
+                    id = cluster;
+                    key = l;
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// Same as [`VebTreeMap::insert`], but starting from the path that the
+    /// finger remembers.  Runs in O(lg lg u) time, but only does hash lookups
+    /// below where the key's path leaves the finger's.
+    pub fn insert_near(
+        &mut self,
+        finger: &mut Finger<K>,
+        key: K,
+        value: V,
+    ) -> Option<V> {
+        self.assert_in_universe(&key);
+        #[cfg(feature = "safety_checks")]
+        let shadow_entry = (key.clone(), value.clone());
+        let old_value = self.measure_mut(Operation::InsertNear, |map| {
+            map.insert_entry_near(finger, key, value)
+        });
+        if old_value.is_none() {
+            self.len += 1;
+        }
+        #[cfg(feature = "safety_checks")]
+        self.shadow_insert(
+            "insert_near",
+            &shadow_entry.0,
+            &shadow_entry.1,
+            old_value.as_ref(),
+        );
+        old_value
+    }
+
+    /// Insert an entry into the arena, without counting it in the length.
+    fn insert_entry_near(
+        &mut self,
+        finger: &mut Finger<K>,
+        mut key: K,
+        mut value: V,
+    ) -> Option<V> {
+        let mut id = *self
+            .root
+            .get_or_insert_with(|| self.arena.alloc(self.max_size.clone()));
+        finger.sync(&self.arena);
+
+        let mut depth = 0;
+        loop {
+            self.arena.count(Counter::Visits);
+            let node = self.arena.node_mut(id);
+            node.assert_in_universe(&key);
+            (key, value) = match node.insert_min_max(key, value) {
+                Placement::Done(old_value) => return old_value,
+                Placement::Cluster(key, value) => (key, value),
+            };
+            let cluster_size = node.cluster_size.clone();
+            let h = key.high(&cluster_size);
+            key = key.low(&cluster_size);
+            id = finger.cluster_or_insert(
+                &mut self.arena,
+                depth,
+                id,
+                h,
+                cluster_size,
+            );
+            depth += 1;
+        }
+    }
+
+    /// Same as [`VebTreeMap::successor`], but starting from the path that the
+    /// finger remembers.  Runs in O(lg lg u) time, but only does hash lookups
+    /// below where the key's path leaves the finger's.
+    pub fn successor_near(
+        &self,
+        finger: &mut Finger<K>,
+        key: &K,
+    ) -> Option<(K, V)> {
+        self.measure(Operation::SuccessorNear, |map| {
+            map.successor_entry_near(finger, key)
+                .map(|(k, v)| (k, v.clone()))
+        })
+    }
+
+    /// Same as [`VebTreeMap::predecessor`], but starting from the path that
+    /// the finger remembers.  Runs in O(lg lg u) time, but only does hash
+    /// lookups below where the key's path leaves the finger's.
+    pub fn predecessor_near(
+        &self,
+        finger: &mut Finger<K>,
+        key: &K,
+    ) -> Option<(K, V)> {
+        self.measure(Operation::PredecessorNear, |map| {
+            map.predecessor_entry_near(finger, key)
+                .map(|(k, v)| (k, v.clone()))
+        })
+    }
+
+    /// The successor of a key, without cloning the value.
+    pub(crate) fn successor_entry_near(
+        &self,
+        finger: &mut Finger<K>,
+        key: &K,
+    ) -> Option<(K, &V)> {
+        if !self.in_universe(key) {
+            return None;
+        }
+        let mut id = self.root?;
+        finger.sync(&self.arena);
+
+        let mut key = key.clone();
+        let mut prefix = Prefix::default();
+        let mut depth = 0;
+        loop {
+            self.arena.count(Counter::Visits);
+            let cluster_size = &self.arena.node(id).cluster_size;
+            match self.arena.successor_step(id, &key, cluster_size, |h| {
+                finger.cluster(&self.arena, depth, id, h)
+            }) {
+                Step::Found(k, v) => return Some((prefix.join(k), v)),
+                Step::Absent => return None,
+                Step::Cluster { h, cluster, l } => {
+                    prefix.push(h, cluster_size);
+                    id = cluster;
+                    key = l;
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    /// The predecessor of a key, without cloning the value.
+    pub(crate) fn predecessor_entry_near(
+        &self,
+        finger: &mut Finger<K>,
+        key: &K,
+    ) -> Option<(K, &V)> {
+        if !self.in_universe(key) {
+            return self.max_entry();
+        }
+        let mut id = self.root?;
+        finger.sync(&self.arena);
+
+        let mut key = key.clone();
+        let mut prefix = Prefix::default();
+        let mut depth = 0;
+        loop {
+            self.arena.count(Counter::Visits);
+            let cluster_size = &self.arena.node(id).cluster_size;
+            match self.arena.predecessor_step(id, &key, cluster_size, |h| {
+                finger.cluster(&self.arena, depth, id, h)
+            }) {
+                Step::Found(k, v) => return Some((prefix.join(k), v)),
+                Step::Absent => return None,
+                Step::Cluster { h, cluster, l } => {
+                    prefix.push(h, cluster_size);
+                    id = cluster;
+                    key = l;
+                    depth += 1;
+                }
+            }
+        }
+    }
+}
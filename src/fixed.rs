@@ -0,0 +1,109 @@
+//! Iterative operations for key types where the cluster size at each depth of
+//! the tree is known at compile time.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::ops::Add;
+
+use crate::instrument::Operation;
+use crate::{VebKey, VebTreeMap};
+
+/// A key type whose trees always split into the same cluster sizes at each
+/// depth.  Maps with these keys get iterative versions of their hot
+/// operations that loop over the levels instead of recursing.
+pub trait FixedDepthKey:
+    VebKey<Size = u8>
+    + Ord
+    + Clone
+    + Hash
+    + Eq
+    + Debug
+    + Add<Output = Self>
+    + Default
+{
+    /// The cluster size (in bits) of the nodes at each depth, starting from
+    /// the root.
+    const CLUSTER_SIZES: &'static [u8];
+}
+
+impl FixedDepthKey for u32 {
+    const CLUSTER_SIZES: &'static [u8] = &[16, 8, 4, 2, 1, 0];
+}
+
+impl FixedDepthKey for u64 {
+    const CLUSTER_SIZES: &'static [u8] = &[32, 16, 8, 4, 2, 1, 0];
+}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: FixedDepthKey,
+    V: Clone + Debug,
+{
+    /// The cluster sizes for the tree's universe, which are the tail of
+    /// [`FixedDepthKey::CLUSTER_SIZES`] when the universe is smaller than the
+    /// key type.
+    #[inline]
+    fn cluster_sizes(&self) -> &'static [u8] {
+        if self.max_size == K::max_size() {
+            return K::CLUSTER_SIZES;
+        }
+        let cluster_size = K::cluster_size(&self.max_size);
+        let depth = K::CLUSTER_SIZES
+            .iter()
+            .position(|size| *size == cluster_size)
+            .expect("universe should be a power of two bits");
+        &K::CLUSTER_SIZES[depth..]
+    }
+
+    /// Same as [`VebTreeMap::get`], but without recursion.  Runs in O(lg lg
+    /// u) time.
+    pub fn get_fixed(&self, key: &K) -> Option<V> {
+        if !self.in_universe(key) {
+            return None;
+        }
+        self.measure(Operation::GetFixed, |map| {
+            map.arena
+                .get_iterative(map.root?, key, map.cluster_sizes())
+                .cloned()
+        })
+    }
+
+    /// Same as [`VebTreeMap::insert`], but without recursion, except on a
+    /// summary when a new cluster is created.  Runs in O(lg lg u) time.
+    pub fn insert_fixed(&mut self, key: K, value: V) -> Option<V> {
+        self.assert_in_universe(&key);
+        let cluster_sizes = self.cluster_sizes();
+        let root = *self
+            .root
+            .get_or_insert_with(|| self.arena.alloc(self.max_size));
+        #[cfg(feature = "safety_checks")]
+        let shadow_entry = (key.clone(), value.clone());
+        let old_value = self.measure_mut(Operation::InsertFixed, |map| {
+            map.arena.insert_iterative(root, key, value, cluster_sizes)
+        });
+        if old_value.is_none() {
+            self.len += 1;
+        }
+        #[cfg(feature = "safety_checks")]
+        self.shadow_insert(
+            "insert_fixed",
+            &shadow_entry.0,
+            &shadow_entry.1,
+            old_value.as_ref(),
+        );
+        old_value
+    }
+
+    /// Same as [`VebTreeMap::successor`], but without recursion, except on a
+    /// summary to find the next cluster.  Runs in O(lg lg u) time.
+    pub fn successor_fixed(&self, key: &K) -> Option<(K, V)> {
+        if !self.in_universe(key) {
+            return None;
+        }
+        self.measure(Operation::SuccessorFixed, |map| {
+            map.arena
+                .successor_iterative(map.root?, key, map.cluster_sizes())
+                .map(|(k, v)| (k, v.clone()))
+        })
+    }
+}
@@ -0,0 +1,743 @@
+//! Frozen trees, which can't change, but live in one flat buffer of bytes
+//! instead of nodes and hash maps, so they can be loaded without copying, for
+//! example from a memory-mapped file.
+//!
+//! The buffer is laid out as a header followed by three arrays of fixed-size
+//! little-endian records:
+//!
+//! - Nodes, each with its min and max, cluster size, summary node, and the
+//!   range of its cluster table in the slots.
+//! - Slots, the open-addressing tables that map a node's cluster numbers to
+//!   cluster nodes.
+//! - Values, referred to by index from the mins and maxes of the nodes.  In
+//!   summary nodes, the mins and maxes refer to cluster nodes instead.
+//!
+//! Reading the buffer never trusts it: a corrupted tree gives wrong answers,
+//! but it doesn't panic or loop forever.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::arena::{Arena, NodeId};
+use crate::{SnapshotError, SnapshotKey, VebTreeMap};
+
+const MAGIC: [u8; 4] = *b"VEBF";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 40;
+/// Marks an empty slot or a missing node.
+const NONE: u32 = u32::MAX;
+
+/// A value type that's stored in a fixed number of bytes in a frozen tree.
+pub trait FrozenValue: Sized {
+    /// The number of bytes in the encoding.
+    const SIZE: usize;
+    /// Append exactly [`FrozenValue::SIZE`] bytes.
+    fn encode(&self, out: &mut Vec<u8>);
+    /// Decode a value from exactly [`FrozenValue::SIZE`] bytes.
+    fn decode(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_frozen_value {
+    ($typ: ty) => {
+        impl FrozenValue for $typ {
+            const SIZE: usize = size_of::<$typ>();
+
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn decode(bytes: &[u8]) -> Self {
+                <$typ>::from_le_bytes(
+                    bytes.try_into().expect("value must have the right size"),
+                )
+            }
+        }
+    };
+}
+
+impl_frozen_value!(u8);
+impl_frozen_value!(u16);
+impl_frozen_value!(u32);
+impl_frozen_value!(u64);
+impl_frozen_value!(u128);
+
+impl FrozenValue for () {
+    const SIZE: usize = 0;
+
+    fn encode(&self, _out: &mut Vec<u8>) {}
+
+    fn decode(_bytes: &[u8]) -> Self {}
+}
+
+/// A read-only tree stored in a flat buffer of bytes, `B`, which is either
+/// owned or borrowed.
+///
+/// Build one from a [`VebTreeMap`], or load one from the bytes of another with
+/// [`FrozenVebTree::from_bytes`].  Operations run in O(lg lg u) time, like
+/// the tree it was built from, but look up clusters in flat tables instead of
+/// hash maps.
+pub struct FrozenVebTree<K, V, B = Vec<u8>> {
+    bytes: B,
+    layout: Layout,
+    root: Option<u32>,
+    universe: u8,
+    len: usize,
+    marker: PhantomData<fn() -> (K, V)>,
+}
+
+/// Where the arrays are in the buffer.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    key_size: usize,
+    node_size: usize,
+    slot_size: usize,
+    value_size: usize,
+    node_count: usize,
+    slot_count: usize,
+    value_count: usize,
+    nodes: usize,
+    slots: usize,
+    values: usize,
+    end: usize,
+}
+
+impl Layout {
+    /// The layout for these sizes, or `None` if it would overflow.
+    fn new(
+        key_size: usize,
+        value_size: usize,
+        node_count: usize,
+        slot_count: usize,
+        value_count: usize,
+    ) -> Option<Layout> {
+        let node_size = key_size.checked_mul(2)?.checked_add(22)?;
+        let slot_size = key_size.checked_add(4)?;
+        let nodes = HEADER_SIZE;
+        let slots = nodes.checked_add(node_count.checked_mul(node_size)?)?;
+        let values = slots.checked_add(slot_count.checked_mul(slot_size)?)?;
+        let end = values.checked_add(value_count.checked_mul(value_size)?)?;
+        Some(Layout {
+            key_size,
+            node_size,
+            slot_size,
+            value_size,
+            node_count,
+            slot_count,
+            value_count,
+            nodes,
+            slots,
+            values,
+            end,
+        })
+    }
+}
+
+/// A node read from the buffer.  Each entry's value is the index of a value,
+/// or of a cluster node for summary nodes.
+struct FrozenNode<K> {
+    min: (K, u32),
+    max: Option<(K, u32)>,
+    cluster_size: u8,
+    summary: Option<u32>,
+    /// The first slot and the number of slots of the cluster table.
+    table: (usize, usize),
+}
+
+impl<K> FrozenNode<K> {
+    /// The maximum entry, which is the min when there's only one.
+    fn last(&self) -> &(K, u32) {
+        self.max.as_ref().unwrap_or(&self.min)
+    }
+
+    /// Whether the node has clusters to descend into.
+    fn has_clusters(&self) -> bool {
+        self.summary.is_some() && self.cluster_size > 0
+    }
+}
+
+impl<K, V> FrozenVebTree<K, V>
+where
+    K: SnapshotKey,
+    V: FrozenValue + Clone + fmt::Debug,
+{
+    /// Freeze a copy of a tree.  Runs in O(n lg lg u) time.
+    pub fn from_map(map: &VebTreeMap<K, V>) -> FrozenVebTree<K, V> {
+        let mut builder = Builder {
+            key_size: usize::from(K::max_size() / 8),
+            nodes: Vec::new(),
+            node_count: 0,
+            slots: Vec::new(),
+            slot_count: 0,
+            values: Vec::new(),
+            value_count: 0,
+        };
+        let root = match map.root {
+            Some(root) => {
+                builder.freeze(&map.arena, root, &|b: &mut Builder, v: &V| {
+                    v.encode(&mut b.values);
+                    b.value_count += 1;
+                    b.value_count - 1
+                })
+            }
+            None => NONE,
+        };
+
+        let mut bytes = Vec::with_capacity(
+            HEADER_SIZE
+                + builder.nodes.len()
+                + builder.slots.len()
+                + builder.values.len(),
+        );
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&[K::max_size(), map.max_size]);
+        let value_size = u32::try_from(V::SIZE).expect("value must fit in u32");
+        bytes.extend_from_slice(&value_size.to_le_bytes());
+        bytes.extend_from_slice(&root.to_le_bytes());
+        bytes.extend_from_slice(&builder.node_count.to_le_bytes());
+        bytes.extend_from_slice(&builder.slot_count.to_le_bytes());
+        bytes.extend_from_slice(&builder.value_count.to_le_bytes());
+        let len = u64::try_from(map.len()).expect("length must fit in u64");
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend_from_slice(&builder.nodes);
+        bytes.extend_from_slice(&builder.slots);
+        bytes.extend_from_slice(&builder.values);
+        FrozenVebTree::from_bytes(bytes).expect("frozen tree must be valid")
+    }
+
+    /// Freeze entries in strictly increasing order of keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keys aren't strictly increasing.
+    pub fn from_sorted_iter<I>(iter: I) -> FrozenVebTree<K, V>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        Self::from_map(&VebTreeMap::from_sorted_iter(iter))
+    }
+}
+
+impl<K, V, B> FrozenVebTree<K, V, B>
+where
+    K: SnapshotKey,
+    V: FrozenValue,
+    B: AsRef<[u8]>,
+{
+    /// Load a frozen tree from the bytes of another, without copying them.
+    /// Only the header is checked, so this runs in O(1) time.
+    pub fn from_bytes(
+        bytes: B,
+    ) -> Result<FrozenVebTree<K, V, B>, SnapshotError> {
+        let data = bytes.as_ref();
+        let header = data.get(..HEADER_SIZE).ok_or(SnapshotError::Truncated)?;
+        if header[..4] != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let (key_bits, universe) = (header[6], header[7]);
+        if key_bits != K::max_size() {
+            return Err(SnapshotError::KeyWidth {
+                expected: K::max_size(),
+                found: key_bits,
+            });
+        }
+        if !universe.is_power_of_two() || universe > key_bits {
+            return Err(SnapshotError::Universe(universe));
+        }
+        let field = |at: usize| read_usize(&header[at..at + 4]);
+        let value_size = field(8);
+        if value_size != V::SIZE {
+            return Err(SnapshotError::ValueWidth {
+                expected: V::SIZE,
+                found: value_size,
+            });
+        }
+        let root = u32::from_le_bytes(
+            header[12..16].try_into().expect("slice has 4 bytes"),
+        );
+        let layout = Layout::new(
+            usize::from(key_bits / 8),
+            value_size,
+            field(16),
+            field(20),
+            field(24),
+        )
+        .ok_or(SnapshotError::Corrupt("arrays are too large"))?;
+        if data.len() < layout.end {
+            return Err(SnapshotError::Truncated);
+        }
+        if data.len() > layout.end {
+            return Err(SnapshotError::Corrupt("trailing bytes"));
+        }
+        let len = usize::try_from(read_u128(&header[28..36]))
+            .map_err(|_| SnapshotError::Corrupt("length must fit in usize"))?;
+        Ok(FrozenVebTree {
+            bytes,
+            layout,
+            root: (root != NONE).then_some(root),
+            universe,
+            len,
+            marker: PhantomData,
+        })
+    }
+
+    /// The bytes of the tree, which [`FrozenVebTree::from_bytes`] can load.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the tree has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// The size (in bits) of the tree's key universe.
+    pub fn universe_bits(&self) -> u8 {
+        self.universe
+    }
+
+    /// Get the minimum element in the tree.  Runs in O(1) time.
+    pub fn min(&self) -> Option<(K, V)> {
+        let (k, v) = self.node(self.root?, self.universe)?.min;
+        Some((k, self.value(v)?))
+    }
+
+    /// Get the maximum element in the tree.  Runs in O(1) time.
+    pub fn max(&self) -> Option<(K, V)> {
+        let node = self.node(self.root?, self.universe)?;
+        let (k, v) = node.last();
+        Some((k.clone(), self.value(*v)?))
+    }
+
+    /// Lookup a key in the tree and get its value.  Runs in O(lg lg u) time.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut id = self.root?;
+        let mut universe = self.universe;
+        let mut key = key.clone();
+        if key > K::size_to_key(&universe) {
+            return None;
+        }
+        loop {
+            let node = self.node(id, universe)?;
+            for (k, v) in
+                [Some(&node.min), node.max.as_ref()].into_iter().flatten()
+            {
+                if *k == key {
+                    return self.value(*v);
+                }
+            }
+            if !node.has_clusters() {
+                return None;
+            }
+            id = self.cluster(&node, &key.high(&node.cluster_size))?;
+            key = key.low(&node.cluster_size);
+            universe = node.cluster_size;
+        }
+    }
+
+    /// Get the successor of the given key.  Runs in O(lg lg u) time.
+    pub fn successor(&self, key: &K) -> Option<(K, V)> {
+        self.entry(self.after(self.root_node()?, key, false))
+    }
+
+    /// Get the predecessor of the given key.  Runs in O(lg lg u) time.
+    pub fn predecessor(&self, key: &K) -> Option<(K, V)> {
+        self.entry(self.before(self.root_node()?, key, false))
+    }
+
+    /// Iterate over the entries with keys in the range, in order.  Each step
+    /// runs in O(lg lg u) time.
+    pub fn range<R>(&self, range: R) -> FrozenRange<'_, K, V, B>
+    where
+        R: RangeBounds<K>,
+    {
+        let next =
+            self.root_node().and_then(|root| match range.start_bound() {
+                Bound::Included(key) => self.after(root, key, true),
+                Bound::Excluded(key) => self.after(root, key, false),
+                Bound::Unbounded => Some(root.min),
+            });
+        FrozenRange {
+            tree: self,
+            next,
+            end: range.end_bound().cloned(),
+        }
+    }
+
+    fn root_node(&self) -> Option<FrozenNode<K>> {
+        self.node(self.root?, self.universe)
+    }
+
+    fn entry(&self, entry: Option<(K, u32)>) -> Option<(K, V)> {
+        let (k, v) = entry?;
+        Some((k, self.value(v)?))
+    }
+
+    /// The value at an index.
+    fn value(&self, index: u32) -> Option<V> {
+        let index = usize::try_from(index).ok()?;
+        if index >= self.layout.value_count {
+            return None;
+        }
+        let at = self.layout.values + index * self.layout.value_size;
+        Some(V::decode(
+            &self.bytes.as_ref()[at..at + self.layout.value_size],
+        ))
+    }
+
+    /// The node at an index, if it's valid for a universe of this size.
+    fn node(&self, id: u32, universe: u8) -> Option<FrozenNode<K>> {
+        let index = usize::try_from(id).ok()?;
+        if index >= self.layout.node_count {
+            return None;
+        }
+        let at = self.layout.nodes + index * self.layout.node_size;
+        let record = &self.bytes.as_ref()[at..at + self.layout.node_size];
+        let (min_key, record) = record.split_at(self.layout.key_size);
+        let (min_value, record) = record.split_at(4);
+        let (max_key, record) = record.split_at(self.layout.key_size);
+        let (max_value, record) = record.split_at(4);
+        let (&[cluster_size, has_max], record) = record.split_at(2) else {
+            unreachable!("split_at(2) has two bytes");
+        };
+        let (summary, record) = record.split_at(4);
+        let (table_start, table_len) = record.split_at(4);
+
+        let max_key_in_universe = K::size_to_key(&universe);
+        let key = |bytes: &[u8]| {
+            K::from_u128(read_u128(bytes))
+                .filter(|key| *key <= max_key_in_universe)
+        };
+        let min = (key(min_key)?, read_u32(min_value));
+        let max = match has_max {
+            0 => None,
+            _ => Some((key(max_key)?, read_u32(max_value))),
+        };
+        if cluster_size != K::cluster_size(&universe) {
+            return None;
+        }
+        let summary = read_u32(summary);
+        let table = (read_usize(table_start), read_usize(table_len));
+        if (table.1 != 0 && !table.1.is_power_of_two())
+            || table.0.checked_add(table.1)? > self.layout.slot_count
+        {
+            return None;
+        }
+        Some(FrozenNode {
+            min,
+            max,
+            cluster_size,
+            summary: (summary != NONE).then_some(summary),
+            table,
+        })
+    }
+
+    /// Find the cluster with number `h` in a node's table.
+    fn cluster(&self, node: &FrozenNode<K>, h: &K) -> Option<u32> {
+        let (start, len) = node.table;
+        if len == 0 {
+            return None;
+        }
+        let mask = len - 1;
+        let hash = slot_hash(h.to_u128());
+        for i in 0..len {
+            let index = start + ((hash + i) & mask);
+            let at = self.layout.slots + index * self.layout.slot_size;
+            let slot = &self.bytes.as_ref()[at..at + self.layout.slot_size];
+            let (key, cluster) = slot.split_at(self.layout.key_size);
+            let cluster = read_u32(cluster);
+            if cluster == NONE {
+                return None;
+            }
+            if read_u128(key) == h.to_u128() {
+                return Some(cluster);
+            }
+        }
+        None
+    }
+
+    /// Find the first key after a key, or at it when `inclusive`, in the
+    /// subtree rooted at a node.  This mirrors the tree's successor.
+    fn after(
+        &self,
+        node: FrozenNode<K>,
+        key: &K,
+        inclusive: bool,
+    ) -> Option<(K, u32)> {
+        let after = |key: &K, other: &K| {
+            if inclusive { key <= other } else { key < other }
+        };
+        if after(key, &node.min.0) {
+            return Some(node.min);
+        }
+        let max = node.max.clone()?;
+        if !after(key, &max.0) {
+            return None;
+        }
+        if !node.has_clusters() {
+            return Some(max);
+        }
+
+        let cluster_size = node.cluster_size;
+        let h = key.high(&cluster_size);
+        let l = key.low(&cluster_size);
+        if let Some(cluster) = self.cluster(&node, &h)
+            && let Some(cluster_node) = self.node(cluster, cluster_size)
+            && after(&l, &cluster_node.last().0)
+        {
+            let (next_l, v) = self.after(cluster_node, &l, inclusive)?;
+            return Some((h.index(next_l, &cluster_size), v));
+        }
+        let summary = self.node(node.summary?, cluster_size)?;
+        if let Some((next_h, next)) = self.after(summary, &h, false) {
+            let (next_l, v) = self.node(next, cluster_size)?.min;
+            return Some((next_h.index(next_l, &cluster_size), v));
+        }
+        Some(max)
+    }
+
+    /// Find the last key before a key, or at it when `inclusive`, in the
+    /// subtree rooted at a node.  This mirrors the tree's predecessor.
+    fn before(
+        &self,
+        node: FrozenNode<K>,
+        key: &K,
+        inclusive: bool,
+    ) -> Option<(K, u32)> {
+        let before = |key: &K, other: &K| {
+            if inclusive { key >= other } else { key > other }
+        };
+        if before(key, &node.last().0) {
+            return Some(node.last().clone());
+        }
+        if !before(key, &node.min.0) {
+            return None;
+        }
+        if !node.has_clusters() {
+            return Some(node.min);
+        }
+
+        let cluster_size = node.cluster_size;
+        let h = key.high(&cluster_size);
+        let l = key.low(&cluster_size);
+        if let Some(cluster) = self.cluster(&node, &h)
+            && let Some(cluster_node) = self.node(cluster, cluster_size)
+            && before(&l, &cluster_node.min.0)
+        {
+            let (prev_l, v) = self.before(cluster_node, &l, inclusive)?;
+            return Some((h.index(prev_l, &cluster_size), v));
+        }
+        let summary = self.node(node.summary?, cluster_size)?;
+        if let Some((prev_h, prev)) = self.before(summary, &h, false) {
+            let (prev_l, v) = self.node(prev, cluster_size)?.last().clone();
+            return Some((prev_h.index(prev_l, &cluster_size), v));
+        }
+        Some(node.min)
+    }
+}
+
+impl<K, V, B> fmt::Debug for FrozenVebTree<K, V, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrozenVebTree")
+            .field("len", &self.len)
+            .field("universe", &self.universe)
+            .field("bytes", &self.layout.end)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An iterator over a range of entries in a frozen tree.  See
+/// [`FrozenVebTree::range`].
+#[derive(Debug)]
+pub struct FrozenRange<'a, K, V, B> {
+    tree: &'a FrozenVebTree<K, V, B>,
+    next: Option<(K, u32)>,
+    end: Bound<K>,
+}
+
+impl<K, V, B> Iterator for FrozenRange<'_, K, V, B>
+where
+    K: SnapshotKey,
+    V: FrozenValue,
+    B: AsRef<[u8]>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.next.take()?;
+        let in_range = match &self.end {
+            Bound::Included(end) => key <= *end,
+            Bound::Excluded(end) => key < *end,
+            Bound::Unbounded => true,
+        };
+        if !in_range {
+            return None;
+        }
+        let tree = self.tree;
+        self.next = tree
+            .root_node()
+            .and_then(|root| tree.after(root, &key, false));
+        Some((key, tree.value(value)?))
+    }
+}
+
+/// Accumulates the arrays of a frozen tree.
+struct Builder {
+    key_size: usize,
+    nodes: Vec<u8>,
+    node_count: u32,
+    slots: Vec<u8>,
+    slot_count: u32,
+    values: Vec<u8>,
+    value_count: u32,
+}
+
+impl Builder {
+    /// Append the subtree rooted at a node, and get the frozen node's index.
+    /// `value` appends or finds what the frozen entries should refer to.
+    fn freeze<K, X>(
+        &mut self,
+        arena: &Arena<K, X>,
+        id: NodeId,
+        value: &dyn Fn(&mut Builder, &X) -> u32,
+    ) -> u32
+    where
+        K: SnapshotKey,
+    {
+        // Clusters come first, so that their summary can refer to them.
+        let mut clusters = Vec::new();
+        let mut summary = NONE;
+        if let Some((summaries, summary_id)) = arena.summary(id) {
+            let mut frozen = BTreeMap::new();
+            let mut next = summaries.node(summary_id).first().cloned();
+            while let Some((h, cluster)) = next {
+                let frozen_cluster = self.freeze(arena, cluster, value);
+                frozen.insert(cluster, frozen_cluster);
+                next = summaries
+                    .successor(summary_id, &h)
+                    .map(|(h, cluster)| (h, *cluster));
+                clusters.push((h, frozen_cluster));
+            }
+            summary = self.freeze(
+                summaries,
+                summary_id,
+                &|_: &mut Builder, cluster: &NodeId| frozen[cluster],
+            );
+        }
+        let table = self.table(&clusters);
+
+        let node = arena.node(id);
+        let (min_key, min_value) =
+            node.first().expect("frozen nodes must be non-empty");
+        let min_value = value(self, min_value);
+        let max = node.max.as_ref().map(|(k, v)| (k, value(self, v)));
+
+        self.push_key(min_key);
+        self.nodes.extend_from_slice(&min_value.to_le_bytes());
+        match max {
+            Some((max_key, max_value)) => {
+                self.push_key(max_key);
+                self.nodes.extend_from_slice(&max_value.to_le_bytes());
+            }
+            None => self.nodes.resize(self.nodes.len() + self.key_size + 4, 0),
+        }
+        self.nodes.push(node.cluster_size);
+        self.nodes.push(u8::from(max.is_some()));
+        self.nodes.extend_from_slice(&summary.to_le_bytes());
+        self.nodes.extend_from_slice(&table.0.to_le_bytes());
+        self.nodes.extend_from_slice(&table.1.to_le_bytes());
+
+        let index = self.node_count;
+        self.node_count = index
+            .checked_add(1)
+            .filter(|count| *count != NONE)
+            .expect("too many nodes to freeze");
+        index
+    }
+
+    /// Append the cluster table of a node, and get its first slot and number
+    /// of slots.
+    fn table<K>(&mut self, clusters: &[(K, u32)]) -> (u32, u32)
+    where
+        K: SnapshotKey,
+    {
+        if clusters.is_empty() {
+            return (0, 0);
+        }
+        // At most half full, so probes stay short.
+        let len = (clusters.len() * 2).next_power_of_two();
+        let mut slots = vec![None; len];
+        for (h, cluster) in clusters {
+            let mut index = slot_hash(h.to_u128()) & (len - 1);
+            while slots[index].is_some() {
+                index = (index + 1) & (len - 1);
+            }
+            slots[index] = Some((h, *cluster));
+        }
+        for slot in slots {
+            match slot {
+                Some((h, cluster)) => {
+                    self.push_slot_key(h);
+                    self.slots.extend_from_slice(&cluster.to_le_bytes());
+                }
+                None => {
+                    self.slots.resize(self.slots.len() + self.key_size, 0);
+                    self.slots.extend_from_slice(&NONE.to_le_bytes());
+                }
+            }
+        }
+        let start = self.slot_count;
+        let len = u32::try_from(len).expect("too many slots to freeze");
+        self.slot_count =
+            start.checked_add(len).expect("too many slots to freeze");
+        (start, len)
+    }
+
+    fn push_key<K: SnapshotKey>(&mut self, key: &K) {
+        self.nodes
+            .extend_from_slice(&key.to_u128().to_le_bytes()[..self.key_size]);
+    }
+
+    fn push_slot_key<K: SnapshotKey>(&mut self, key: &K) {
+        self.slots
+            .extend_from_slice(&key.to_u128().to_le_bytes()[..self.key_size]);
+    }
+}
+
+/// The preferred slot of a key in a cluster table, before masking.
+fn slot_hash(key: u128) -> usize {
+    let folded = u64::try_from((key ^ (key >> 64)) & u128::from(u64::MAX))
+        .expect("masked to 64 bits");
+    // Fibonacci hashing, keeping the well-mixed high bits.
+    let hash = folded.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32;
+    usize::try_from(hash).expect("hash has 32 bits")
+}
+
+fn read_u128(bytes: &[u8]) -> u128 {
+    let mut buf = [0; 16];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u128::from_le_bytes(buf)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().expect("slice must have 4 bytes"))
+}
+
+fn read_usize(bytes: &[u8]) -> usize {
+    usize::try_from(read_u32(bytes)).expect("u32 must fit in usize")
+}
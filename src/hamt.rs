@@ -0,0 +1,329 @@
+//! A hash array mapped trie, a hash map whose versions share their unchanged
+//! branches.
+//!
+//! Each branch splits on the next [`BITS`] bits of a key's hash, and only has
+//! slots for the children that exist, which it finds with a bitmap.  Hashes
+//! are 64 bits, so there are at most 11 levels, and lookups and changes run
+//! in O(1) time.  A change copies the branches on its path that another
+//! version shares, so copying a trie is O(1).
+
+use core::fmt::{self, Debug};
+use core::hash::{Hash, Hasher};
+use core::mem::replace;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The number of hash bits that each level of the trie splits on.
+const BITS: u32 = 6;
+
+/// A map from keys to values that shares its unchanged branches with its
+/// copies.
+#[derive(Clone)]
+pub(crate) struct Hamt<K, T> {
+    /// The root branch, which is only allocated once there's an entry.
+    root: Option<Arc<Branch<K, T>>>,
+}
+
+#[derive(Clone)]
+struct Branch<K, T> {
+    /// The children that exist, by the hash bits of this level.
+    bitmap: u64,
+    slots: Vec<Slot<K, T>>,
+}
+
+#[derive(Clone)]
+enum Slot<K, T> {
+    Leaf(u64, K, T),
+    /// Entries whose full hashes are the same.
+    Collision(u64, Vec<(K, T)>),
+    Branch(Arc<Branch<K, T>>),
+}
+
+impl<K, T> Slot<K, T> {
+    fn hash(&self) -> u64 {
+        match self {
+            Slot::Leaf(hash, ..) | Slot::Collision(hash, _) => *hash,
+            Slot::Branch(_) => panic!("slot must be an entry"),
+        }
+    }
+}
+
+/// The bit of a branch's bitmap for a hash at a level.
+#[inline]
+fn bit_of(hash: u64, shift: u32) -> u64 {
+    1 << ((hash >> shift) & 63)
+}
+
+impl<K, T> Hamt<K, T> {
+    pub(crate) fn new() -> Hamt<K, T> {
+        Hamt { root: None }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+}
+
+impl<K, T> Hamt<K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    pub(crate) fn get(&self, key: &K) -> Option<&T> {
+        self.root.as_ref()?.get(hash(key), 0, key)
+    }
+
+    /// Get a value to change, copying the shared branches on its path.
+    pub(crate) fn get_mut(&mut self, key: &K) -> Option<&mut T> {
+        Arc::make_mut(self.root.as_mut()?).get_mut(hash(key), 0, key)
+    }
+
+    pub(crate) fn insert(&mut self, key: K, value: T) -> Option<T> {
+        let root = self.root.get_or_insert_with(|| {
+            Arc::new(Branch {
+                bitmap: 0,
+                slots: Vec::new(),
+            })
+        });
+        Arc::make_mut(root).insert(hash(&key), 0, key, value)
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) -> Option<T> {
+        let root = self.root.as_mut()?;
+        let value = Arc::make_mut(root).remove(hash(key), 0, key)?;
+        if root.slots.is_empty() {
+            self.root = None;
+        }
+        Some(value)
+    }
+}
+
+impl<K, T> Branch<K, T>
+where
+    K: Hash + Eq + Clone,
+    T: Clone,
+{
+    /// The index of the slot for a bit of the bitmap.
+    #[inline]
+    fn index(&self, bit: u64) -> usize {
+        usize::try_from((self.bitmap & (bit - 1)).count_ones())
+            .expect("slot index must fit in usize")
+    }
+
+    fn get(&self, hash: u64, shift: u32, key: &K) -> Option<&T> {
+        let bit = bit_of(hash, shift);
+        if self.bitmap & bit == 0 {
+            return None;
+        }
+        match &self.slots[self.index(bit)] {
+            Slot::Leaf(_, k, value) => (k == key).then_some(value),
+            Slot::Collision(_, entries) => entries
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value),
+            Slot::Branch(branch) => branch.get(hash, shift + BITS, key),
+        }
+    }
+
+    fn get_mut(&mut self, hash: u64, shift: u32, key: &K) -> Option<&mut T> {
+        let bit = bit_of(hash, shift);
+        if self.bitmap & bit == 0 {
+            return None;
+        }
+        let index = self.index(bit);
+        match &mut self.slots[index] {
+            Slot::Leaf(_, k, value) => (k == key).then_some(value),
+            Slot::Collision(_, entries) => entries
+                .iter_mut()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value),
+            Slot::Branch(branch) => {
+                Arc::make_mut(branch).get_mut(hash, shift + BITS, key)
+            }
+        }
+    }
+
+    fn insert(&mut self, hash: u64, shift: u32, key: K, value: T) -> Option<T> {
+        let bit = bit_of(hash, shift);
+        let index = self.index(bit);
+        if self.bitmap & bit == 0 {
+            self.bitmap |= bit;
+            self.slots.insert(index, Slot::Leaf(hash, key, value));
+            return None;
+        }
+        let slot = &mut self.slots[index];
+        match slot {
+            Slot::Branch(branch) => {
+                return Arc::make_mut(branch).insert(
+                    hash,
+                    shift + BITS,
+                    key,
+                    value,
+                );
+            }
+            Slot::Leaf(h, k, old_value) if *h == hash && *k == key => {
+                return Some(replace(old_value, value));
+            }
+            Slot::Collision(h, entries) if *h == hash => {
+                match entries.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, old_value)) => {
+                        return Some(replace(old_value, value));
+                    }
+                    None => entries.push((key, value)),
+                }
+                return None;
+            }
+            _ => {}
+        }
+
+        // The slot has a different key, so the two need to be split up.
+        match replace(slot, Slot::Collision(hash, Vec::new())) {
+            Slot::Leaf(h, k, v) if h == hash => {
+                *slot = Slot::Collision(hash, vec![(k, v), (key, value)]);
+            }
+            old => {
+                // The hashes are different, and they're the same up to this
+                // level, so they differ in the bits further down.
+                let shift = shift + BITS;
+                debug_assert!(shift < 64, "different hashes must split");
+                let mut branch = Branch {
+                    bitmap: bit_of(old.hash(), shift),
+                    slots: vec![old],
+                };
+                branch.insert(hash, shift, key, value);
+                *slot = Slot::Branch(Arc::new(branch));
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, hash: u64, shift: u32, key: &K) -> Option<T> {
+        let bit = bit_of(hash, shift);
+        if self.bitmap & bit == 0 {
+            return None;
+        }
+        let index = self.index(bit);
+        match &mut self.slots[index] {
+            Slot::Leaf(_, k, _) => {
+                if k != key {
+                    return None;
+                }
+                self.bitmap &= !bit;
+                match self.slots.remove(index) {
+                    Slot::Leaf(_, _, value) => Some(value),
+                    _ => unreachable!("slot was a leaf"),
+                }
+            }
+            Slot::Collision(h, entries) => {
+                let i = entries.iter().position(|(k, _)| k == key)?;
+                let (_, value) = entries.swap_remove(i);
+                if let [_] = entries.as_slice() {
+                    let (k, v) = entries.pop().expect("one entry is left");
+                    self.slots[index] = Slot::Leaf(*h, k, v);
+                }
+                Some(value)
+            }
+            Slot::Branch(branch) => {
+                let branch = Arc::make_mut(branch);
+                let value = branch.remove(hash, shift + BITS, key)?;
+                // Pull up a branch's last entry, so that every branch below
+                // the root has at least two entries.
+                if let [Slot::Leaf(..) | Slot::Collision(..)] =
+                    branch.slots.as_slice()
+                {
+                    let slot = branch.slots.pop().expect("one slot is left");
+                    self.slots[index] = slot;
+                }
+                Some(value)
+            }
+        }
+    }
+}
+
+impl<K, T> Branch<K, T> {
+    fn for_each<'a>(&'a self, f: &mut impl FnMut(&'a K, &'a T)) {
+        for slot in &self.slots {
+            match slot {
+                Slot::Leaf(_, key, value) => f(key, value),
+                Slot::Collision(_, entries) => {
+                    entries.iter().for_each(|(key, value)| f(key, value));
+                }
+                Slot::Branch(branch) => branch.for_each(f),
+            }
+        }
+    }
+}
+
+impl<K, T> Debug for Hamt<K, T>
+where
+    K: Debug,
+    T: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        if let Some(root) = &self.root {
+            root.for_each(&mut |key, value| {
+                map.entry(key, value);
+            });
+        }
+        map.finish()
+    }
+}
+
+/// Hash a key for the trie.
+fn hash<K: Hash>(key: &K) -> u64 {
+    let mut hasher = KeyHasher(0);
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A hasher for integer keys.  Hashing a single integer of up to 64 bits is a
+/// bijection, so those keys never have the same hash.  The hash doesn't
+/// depend on the process, so versions built separately hash alike.
+struct KeyHasher(u64);
+
+impl Hasher for KeyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.write_u64(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.write_u64(u64::from(n));
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.write_u64(u64::from(n));
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write_u64(u64::from(n));
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.0 = mix(self.0 ^ n);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(u64::try_from(n).expect("usize must fit in u64"));
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The finalizer of `MurmurHash3`, which spreads every bit of the input over
+/// the whole output and is a bijection.
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^ (x >> 33)
+}
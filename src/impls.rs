@@ -0,0 +1,187 @@
+//! Standard traits for trees, which only look at their entries.  Two trees
+//! with the same entries are equal, whatever their universes and however
+//! their nodes are laid out.
+//!
+//! `Ord`'s `min` and `max` take `self` by value, so when the values
+//! implement `Ord`, they're picked over [`VebTreeMap::min`] and
+//! [`VebTreeMap::max`] for a tree that isn't borrowed.
+
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::hash::{Hash, Hasher};
+use core::ops::Index;
+
+use alloc::collections::BTreeMap;
+
+use crate::{Finger, VebKey, VebTreeMap};
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Get a view of the tree whose `Debug` shows its nodes, rather than its
+    /// entries like the tree's own `Debug`.  See also [`VebTreeMap::dot`].
+    pub fn debug_structure(&self) -> DebugStructure<'_, K, V> {
+        DebugStructure { map: self }
+    }
+
+    /// The entries in order.  Each step runs in O(lg lg u) time.
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (K, &V)> + '_ {
+        let mut finger = Finger::new();
+        let mut next = self.min_entry();
+        core::iter::from_fn(move || {
+            let (key, value) = next.take()?;
+            next = self.successor_entry_near(&mut finger, &key);
+            Some((key, value))
+        })
+    }
+}
+
+/// Shows the nodes of a tree with `Debug`, from
+/// [`VebTreeMap::debug_structure`].
+#[derive(Clone, Copy)]
+pub struct DebugStructure<'a, K, V>
+where
+    K: VebKey,
+{
+    map: &'a VebTreeMap<K, V>,
+}
+
+impl<K, V> Debug for DebugStructure<'_, K, V>
+where
+    K: VebKey + Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VebTreeMap")
+            .field("root", &self.map.root)
+            .field("arena", &self.map.arena)
+            .field("max_size", &self.map.max_size)
+            .field("len", &self.map.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V> Debug for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.entries()).finish()
+    }
+}
+
+impl<K, V> PartialEq for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug + PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.entries().eq(other.entries())
+    }
+}
+
+impl<K, V> Eq for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug + Eq,
+{
+}
+
+impl<K, V> Hash for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug + Hash,
+{
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: Hasher,
+    {
+        // Like `BTreeMap`, so that a prefix of entries hashes differently.
+        state.write_usize(self.len);
+        for entry in self.entries() {
+            entry.hash(state);
+        }
+    }
+}
+
+impl<K, V> PartialOrd for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug + PartialOrd,
+{
+    /// Compares the entries in order, like `BTreeMap`.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.entries().partial_cmp(other.entries())
+    }
+}
+
+impl<K, V> Ord for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug + Ord,
+{
+    /// Compares the entries in order, like `BTreeMap`.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.entries().cmp(other.entries())
+    }
+}
+
+impl<K, V> Index<&K> for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    type Output = V;
+
+    /// Get a reference to the value for a key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key isn't in the tree.
+    fn index(&self, key: &K) -> &V {
+        self.root
+            .and_then(|root| self.arena.get(root, key))
+            .unwrap_or_else(|| panic!("key must be in the tree: key={key:?}"))
+    }
+}
+
+impl<K, V, const N: usize> From<[(K, V); N]> for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Insert the entries in order, so later values replace earlier ones
+    /// with the same key.
+    fn from(entries: [(K, V); N]) -> Self {
+        let mut map = VebTreeMap::new();
+        for (key, value) in entries {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl<K, V> From<BTreeMap<K, V>> for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    fn from(map: BTreeMap<K, V>) -> Self {
+        VebTreeMap::from_sorted_iter(map)
+    }
+}
+
+impl<K, V> From<VebTreeMap<K, V>> for BTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    fn from(map: VebTreeMap<K, V>) -> Self {
+        map.entries()
+            .map(|(key, value)| (key, value.clone()))
+            .collect()
+    }
+}
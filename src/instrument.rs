@@ -0,0 +1,329 @@
+//! With the `stats` feature, trees count the work that their operations do,
+//! so tests can check that operations stay within O(lg lg u) steps.
+//!
+//! Each arena counts the work done in its nodes.  An operation on the tree
+//! reads the counts of every arena before and after it runs, and adds the
+//! difference to the totals of its kind.  The counts are relaxed atomics, so
+//! operations that take `&self` can count too, but they're only exact when a
+//! tree isn't used from several threads at once.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+#[cfg(feature = "stats")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{VebKey, VebTreeMap};
+
+/// Define the operations whose work is counted, with a field of
+/// [`OperationStats`] and a total in [`Recorder`] for each.
+macro_rules! operations {
+    ($($(#[$doc:meta])* $operation:ident => $field:ident,)*) => {
+        /// The operations whose work is counted.
+        #[derive(Debug, Clone, Copy)]
+        pub(crate) enum Operation {
+            $($operation,)*
+        }
+
+        /// The work done by each kind of operation on a tree, from
+        /// [`VebTreeMap::operation_stats`].
+        ///
+        /// `remove` also counts the removals of
+        /// [`CursorMut`](crate::CursorMut).
+        #[cfg(feature = "stats")]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+        #[non_exhaustive]
+        pub struct OperationStats {
+            $($(#[$doc])* pub $field: OperationCounts,)*
+        }
+
+        /// The totals of each kind of a tree's operations.
+        #[cfg(feature = "stats")]
+        #[derive(Debug, Default)]
+        pub(crate) struct Recorder {
+            $($field: Totals,)*
+        }
+
+        #[cfg(feature = "stats")]
+        impl Recorder {
+            fn totals(&self, operation: Operation) -> &Totals {
+                match operation {
+                    $(Operation::$operation => &self.$field,)*
+                }
+            }
+
+            fn load(&self) -> OperationStats {
+                OperationStats {
+                    $($field: self.$field.load(),)*
+                }
+            }
+
+            fn store(&self, stats: &OperationStats) {
+                $(self.$field.store(&stats.$field);)*
+            }
+        }
+    };
+}
+
+operations! {
+    Get => get,
+    Insert => insert,
+    Remove => remove,
+    Successor => successor,
+    Predecessor => predecessor,
+    Ceiling => ceiling,
+    Floor => floor,
+    Nearest => nearest,
+    GetFixed => get_fixed,
+    InsertFixed => insert_fixed,
+    SuccessorFixed => successor_fixed,
+    GetNear => get_near,
+    InsertNear => insert_near,
+    SuccessorNear => successor_near,
+    PredecessorNear => predecessor_near,
+    /// A call counts once, and its work covers every key of the batch, so
+    /// `max_depth` is the most nodes that a whole batch visited.
+    GetMany => get_many,
+    /// See `get_many`.
+    SuccessorMany => successor_many,
+    /// Finding the gap that a cursor starts in, for
+    /// [`VebTreeMap::lower_bound`] and [`VebTreeMap::upper_bound`] and their
+    /// `_mut` versions.
+    CursorSeek => cursor_seek,
+    /// The moves of [`Cursor`](crate::Cursor) and
+    /// [`CursorMut`](crate::CursorMut) in either direction, and the steps to
+    /// the new neighbor after [`CursorMut`](crate::CursorMut) removes one.
+    CursorMove => cursor_move,
+}
+
+/// The kinds of work that an arena counts.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Counter {
+    /// A node was visited by a recursive call.
+    Visits,
+    /// A cluster was looked up in, added to or removed from the cluster table.
+    Probes,
+    /// An operation recursed into a node's summary.
+    SummaryDescents,
+    /// A key was cloned to look up, add or remove a cluster.
+    Clones,
+}
+
+/// The work done by one kind of operation, from
+/// [`VebTreeMap::operation_stats`].
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct OperationCounts {
+    /// The number of calls.
+    pub calls: usize,
+    /// The most nodes of the tree's own arena that a single call visited,
+    /// which is how deep it recursed into clusters.
+    pub max_depth: usize,
+    /// The nodes visited in every arena, including the summary arenas.
+    pub node_visits: usize,
+    /// The clusters looked up in, added to or removed from cluster tables.
+    pub probes: usize,
+    /// The times that an operation recursed into a node's summary.
+    pub summary_descents: usize,
+    /// The keys cloned to look up, add or remove clusters.
+    pub clones: usize,
+}
+
+/// The work counted by an arena and the summary arenas below it.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Counts {
+    /// The nodes visited in the arena itself, without the summary arenas.
+    pub(crate) own_visits: usize,
+    pub(crate) visits: usize,
+    pub(crate) probes: usize,
+    pub(crate) summary_descents: usize,
+    pub(crate) clones: usize,
+}
+
+#[cfg(feature = "stats")]
+impl Counts {
+    /// Add the counts of the summary arenas below an arena.
+    pub(crate) fn add_below(&mut self, below: Counts) {
+        self.visits = self.visits.wrapping_add(below.visits);
+        self.probes = self.probes.wrapping_add(below.probes);
+        self.summary_descents =
+            self.summary_descents.wrapping_add(below.summary_descents);
+        self.clones = self.clones.wrapping_add(below.clones);
+    }
+}
+
+/// The running counts of an arena's work.  They only ever grow, and wrap
+/// around on overflow.
+#[cfg(feature = "stats")]
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    visits: AtomicUsize,
+    probes: AtomicUsize,
+    summary_descents: AtomicUsize,
+    clones: AtomicUsize,
+}
+
+#[cfg(feature = "stats")]
+impl Counters {
+    #[inline]
+    pub(crate) fn add(&self, counter: Counter) {
+        let counter = match counter {
+            Counter::Visits => &self.visits,
+            Counter::Probes => &self.probes,
+            Counter::SummaryDescents => &self.summary_descents,
+            Counter::Clones => &self.clones,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn counts(&self) -> Counts {
+        let visits = self.visits.load(Ordering::Relaxed);
+        Counts {
+            own_visits: visits,
+            visits,
+            probes: self.probes.load(Ordering::Relaxed),
+            summary_descents: self.summary_descents.load(Ordering::Relaxed),
+            clones: self.clones.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The totals of a tree's operations of one kind.
+#[cfg(feature = "stats")]
+#[derive(Debug, Default)]
+struct Totals {
+    calls: AtomicUsize,
+    max_depth: AtomicUsize,
+    node_visits: AtomicUsize,
+    probes: AtomicUsize,
+    summary_descents: AtomicUsize,
+    clones: AtomicUsize,
+}
+
+#[cfg(feature = "stats")]
+impl Totals {
+    fn record(&self, before: Counts, after: Counts) {
+        let add = |total: &AtomicUsize, before: usize, after: usize| {
+            total.fetch_add(after.wrapping_sub(before), Ordering::Relaxed);
+        };
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.max_depth.fetch_max(
+            after.own_visits.wrapping_sub(before.own_visits),
+            Ordering::Relaxed,
+        );
+        add(&self.node_visits, before.visits, after.visits);
+        add(&self.probes, before.probes, after.probes);
+        add(
+            &self.summary_descents,
+            before.summary_descents,
+            after.summary_descents,
+        );
+        add(&self.clones, before.clones, after.clones);
+    }
+
+    fn load(&self) -> OperationCounts {
+        OperationCounts {
+            calls: self.calls.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            node_visits: self.node_visits.load(Ordering::Relaxed),
+            probes: self.probes.load(Ordering::Relaxed),
+            summary_descents: self.summary_descents.load(Ordering::Relaxed),
+            clones: self.clones.load(Ordering::Relaxed),
+        }
+    }
+
+    fn store(&self, counts: &OperationCounts) {
+        self.calls.store(counts.calls, Ordering::Relaxed);
+        self.max_depth.store(counts.max_depth, Ordering::Relaxed);
+        self.node_visits
+            .store(counts.node_visits, Ordering::Relaxed);
+        self.probes.store(counts.probes, Ordering::Relaxed);
+        self.summary_descents
+            .store(counts.summary_descents, Ordering::Relaxed);
+        self.clones.store(counts.clones, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "stats")]
+impl Clone for Recorder {
+    /// The copy starts with the same totals.
+    fn clone(&self) -> Self {
+        let recorder = Recorder::default();
+        recorder.store(&self.load());
+        recorder
+    }
+}
+
+#[cfg(feature = "stats")]
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Get the work done by each kind of operation since the tree was created
+    /// or the stats were last reset.
+    pub fn operation_stats(&self) -> OperationStats {
+        self.operations.load()
+    }
+
+    /// Set every count of the operations back to zero.
+    pub fn reset_operation_stats(&self) {
+        self.operations.store(&OperationStats::default());
+    }
+
+    /// Run an operation, adding the work it does to its totals.
+    #[inline]
+    pub(crate) fn measure<T>(
+        &self,
+        operation: Operation,
+        f: impl FnOnce(&Self) -> T,
+    ) -> T {
+        let before = self.arena.counts();
+        let result = f(self);
+        self.operations
+            .totals(operation)
+            .record(before, self.arena.counts());
+        result
+    }
+
+    /// Like [`VebTreeMap::measure`], for an operation that changes the tree.
+    #[inline]
+    pub(crate) fn measure_mut<T>(
+        &mut self,
+        operation: Operation,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let before = self.arena.counts();
+        let result = f(self);
+        self.operations
+            .totals(operation)
+            .record(before, self.arena.counts());
+        result
+    }
+}
+
+#[cfg(not(feature = "stats"))]
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    #[inline]
+    pub(crate) fn measure<T>(
+        &self,
+        _operation: Operation,
+        f: impl FnOnce(&Self) -> T,
+    ) -> T {
+        f(self)
+    }
+
+    #[inline]
+    pub(crate) fn measure_mut<T>(
+        &mut self,
+        _operation: Operation,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        f(self)
+    }
+}
@@ -10,6 +10,9 @@
 //! To achieve the space bound, we need to use a sparse data-structure, so keys
 //! must be hashable.
 //!
+//! The nodes of a tree are allocated in an arena, so they're contiguous in
+//! memory and refer to each other by index.
+//!
 //! The tree accepts any Clone type for values, but you probably want to use
 //! values that are Copy.
 //!
@@ -18,7 +21,13 @@
 //! square root of u.
 //!
 //! For example, if u = 2^32, then the cluster size is 2^16.
+//!
+//! The crate only needs `alloc`.  Without the default `std` feature, clusters
+//! are found in ordered maps instead of hash maps, and the parts that need
+//! I/O or locks, like reading snapshots and the concurrent tree, are left
+//! out.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(
     clippy::as_conversions,
     clippy::borrow_as_ptr,
@@ -40,11 +49,74 @@
     clippy::suboptimal_flops
 )]
 
+extern crate alloc;
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+use core::cmp::Ordering;
+use core::fmt::Debug;
 use core::hash::Hash;
-use std::borrow::Cow;
-use std::collections::HashMap;
-use std::fmt::Debug;
-use std::mem::{replace, swap};
+use core::ops::Sub;
+
+use alloc::collections::TryReserveError;
+
+use arena::{Arena, NodeId};
+use instrument::Operation;
+
+pub use checked::VebError;
+#[cfg(feature = "std")]
+pub use concurrent::{ConcurrentVebTreeMap, ShardKey};
+pub use cursor::{Cursor, CursorMut};
+pub use dot::Dot;
+pub use elias_fano::{EliasFanoKey, EliasFanoSet};
+pub use finger::Finger;
+pub use fixed::FixedDepthKey;
+pub use frozen::{FrozenRange, FrozenValue, FrozenVebTree};
+pub use impls::DebugStructure;
+#[cfg(feature = "stats")]
+pub use instrument::{OperationCounts, OperationStats};
+pub use persistent::PersistentVebTreeMap;
+#[cfg(feature = "rcu")]
+pub use rcu::{RcuCell, RcuReader};
+pub use snapshot::{
+    LittleEndianCodec, SnapshotError, SnapshotKey, UnitCodec, ValueCodec,
+};
+pub use stats::{ArenaStats, TreeStats};
+pub use validate::InvariantViolation;
+pub use wrapping::RangeWrapping;
+
+#[cfg(feature = "serde")]
+pub use crate::serde::UniverseSeed;
+
+mod arena;
+mod batch;
+mod checked;
+#[cfg(feature = "std")]
+mod concurrent;
+mod cursor;
+mod dot;
+mod elias_fano;
+mod finger;
+mod fixed;
+mod frozen;
+mod hamt;
+mod impls;
+mod instrument;
+mod persistent;
+#[cfg(feature = "rayon")]
+mod rayon;
+#[cfg(feature = "rcu")]
+mod rcu;
+#[cfg(feature = "std")]
+mod roaring;
+#[cfg(feature = "serde")]
+mod serde;
+#[cfg(feature = "safety_checks")]
+mod shadow;
+mod snapshot;
+mod stats;
+mod validate;
+mod wrapping;
 
 #[cfg(test)]
 mod tests;
@@ -53,18 +125,19 @@ mod tests;
 mod property_tests;
 
 /// A map implemented with a van Emde Boas tree.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct VebTreeMap<K, V>
 where
     K: VebKey,
 {
-    min: Option<(K, V)>,
-    max: Option<(K, V)>,
-    summary: Option<Box<VebTreeMap<K, ()>>>,
-    clusters: HashMap<K, VebTreeMap<K, V>>,
-    cluster_size: K::Size,
-    #[cfg(any(test, feature = "safety_checks"))]
+    root: Option<NodeId>,
+    arena: Arena<K, V>,
     max_size: K::Size,
+    len: usize,
+    #[cfg(feature = "safety_checks")]
+    shadow: shadow::Shadow<K>,
+    #[cfg(feature = "stats")]
+    operations: instrument::Recorder,
 }
 
 impl<K, V> VebTreeMap<K, V>
@@ -77,24 +150,69 @@ where
 
     fn with_max_size(max_size: K::Size) -> VebTreeMap<K, V> {
         VebTreeMap {
-            min: None,
-            max: None,
-            summary: None,
-            clusters: HashMap::new(),
-            cluster_size: K::cluster_size(&max_size),
-            #[cfg(any(test, feature = "safety_checks"))]
+            root: None,
+            arena: Arena::new(),
             max_size,
+            len: 0,
+            #[cfg(feature = "safety_checks")]
+            shadow: shadow::Shadow::new(),
+            #[cfg(feature = "stats")]
+            operations: instrument::Recorder::default(),
         }
     }
 
+    /// The size (in bits) of the tree's key universe.
+    pub fn universe_bits(&self) -> K::Size {
+        self.max_size.clone()
+    }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
     /// Returns true if the tree has no elements.
     pub fn is_empty(&self) -> bool {
-        self.min.is_none()
+        self.root.is_none()
     }
 
-    /// Removes all elements.
+    /// Removes all elements.  The memory used by the tree's nodes is kept to
+    /// be reused.
     pub fn clear(&mut self) {
-        *self = Self::new();
+        self.release_all();
+        #[cfg(feature = "safety_checks")]
+        self.shadow.clear();
+    }
+
+    /// Release every node, keeping the memory they used.
+    fn release_all(&mut self) {
+        self.root = None;
+        self.arena.clear();
+        self.len = 0;
+    }
+}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey<Size = u8>,
+{
+    /// Create an empty tree whose keys are all less than 2^`bits`.  Smaller
+    /// universes make shallower trees, so operations do less work.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` isn't a power of two or is larger than the number of
+    /// bits in the key type.
+    ///
+    /// Lookups treat keys outside the universe as greater than every key in
+    /// the tree, and inserting them panics.  The `checked_*` methods, like
+    /// [`VebTreeMap::checked_insert`], return a [`VebError`] instead.
+    pub fn with_universe_bits(bits: u8) -> VebTreeMap<K, V> {
+        assert!(
+            bits.is_power_of_two() && bits <= K::max_size(),
+            "universe must be a power of two bits no larger than the key type: bits={bits}"
+        );
+        Self::with_max_size(bits)
     }
 }
 
@@ -103,331 +221,270 @@ where
     K: VebKey + Ord + Clone + Hash + Eq + Debug,
     V: Clone + Debug,
 {
+    /// Returns true if the key fits in the tree's universe.
+    #[inline]
+    pub(crate) fn in_universe(&self, key: &K) -> bool {
+        *key <= K::size_to_key(&self.max_size)
+    }
+
+    /// Panics if the key doesn't fit in the tree's universe.  Inserting it
+    /// would put it in the wrong cluster.
+    #[inline]
+    pub(crate) fn assert_in_universe(&self, key: &K) {
+        assert!(
+            self.in_universe(key),
+            "key must fit in the tree's universe: key={key:?}, max_size={:?}",
+            self.max_size
+        );
+    }
+
+    /// Create a tree from entries in strictly increasing order of keys.  This
+    /// is faster than inserting them one at a time because each insert starts
+    /// from the previous key's path instead of the root.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keys aren't strictly increasing.
+    pub fn from_sorted_iter<I>(iter: I) -> VebTreeMap<K, V>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = Self::new();
+        let mut finger = Finger::new();
+        #[cfg(feature = "safety_checks")]
+        map.shadow.begin_batch();
+        for (key, value) in iter {
+            if let Some((max, _)) = map.max_entry() {
+                assert!(
+                    key > max,
+                    "keys must be strictly increasing: key={key:?}, previous={max:?}"
+                );
+            }
+            map.insert_near(&mut finger, key, value);
+        }
+        #[cfg(feature = "safety_checks")]
+        map.shadow_end_batch();
+        map
+    }
+
     /// Get the maximum element in the tree.  Runs in O(1) time.
+    ///
+    /// When the values implement `Ord`, so does the tree, and `tree.max()`
+    /// on a tree that isn't borrowed calls `Ord::max` instead.  Call
+    /// `VebTreeMap::max(&tree)` then.
     pub fn max(&self) -> Option<(K, V)> {
-        self.max.clone()
+        self.arena.node(self.root?).last().cloned()
     }
 
     /// Get the minimum element in the tree.  Runs in O(1) time.
+    ///
+    /// When the values implement `Ord`, so does the tree, and `tree.min()`
+    /// on a tree that isn't borrowed calls `Ord::min` instead.  Call
+    /// `VebTreeMap::min(&tree)` then.
     pub fn min(&self) -> Option<(K, V)> {
-        self.min.clone()
+        self.arena.node(self.root?).first().cloned()
     }
 
     /// Lookup a key in the tree and get its value.  Runs in O(lg lg u) time.
     pub fn get(&self, key: &K) -> Option<V> {
-        #[cfg(any(test, feature = "safety_checks"))]
-        assert!(*key <= K::size_to_key(&self.max_size));
-
-        // Check the min.
-        if let Some((min_key, min_value)) = self.min.as_ref() {
-            match key.cmp(min_key) {
-                std::cmp::Ordering::Less => return None,
-                std::cmp::Ordering::Equal => return Some(min_value.clone()),
-                std::cmp::Ordering::Greater => {}
-            }
-        }
-        // Check the max.
-        if let Some((max_key, max_value)) = self.max.as_ref() {
-            match key.cmp(max_key) {
-                std::cmp::Ordering::Greater => return None,
-                std::cmp::Ordering::Equal => return Some(max_value.clone()),
-                std::cmp::Ordering::Less => {}
-            }
+        if !self.in_universe(key) {
+            return None;
         }
-
-        // Get the cluster.
-        let h = key.high(&self.cluster_size);
-        let cluster = self.clusters.get(&h)?;
-        let l = key.low(&self.cluster_size);
-
-        cluster.get(&l)
+        self.measure(Operation::Get, |map| {
+            map.arena.get(map.root?, key).cloned()
+        })
     }
 
     /// Insert a key-value pair into the tree.  Runs in O(lg lg u) time.
-    pub fn insert(&mut self, mut key: K, mut value: V) -> Option<V> {
-        #[cfg(any(test, feature = "safety_checks"))]
-        assert!(
-            key <= K::size_to_key(&self.max_size),
-            "key must be representable by cluster's maximum size: key={:?}, max_size={:?}, size_to_key={:?}",
-            key,
-            self.max_size,
-            K::size_to_key(&self.max_size)
-        );
-
-        if self.is_empty() {
-            // When currently empty, be lazy to prevent recursive calls.
-            self.min = Some((key.clone(), value.clone()));
-            self.max = Some((key, value));
-            return None;
-        }
-
-        let mut return_value = None;
-
-        // If it's less than the min, swap it with the min.
-        if let Some((min_key, min_value)) = self.min.as_mut() {
-            match key.cmp(min_key) {
-                std::cmp::Ordering::Less => {
-                    swap(min_key, &mut key);
-                    swap(min_value, &mut value);
-                }
-                std::cmp::Ordering::Equal => {
-                    // If the key is the same, update the value.  Don't return
-                    // early in case the max is the same and needs to be updated
-                    // also.
-                    return_value = Some(replace(min_value, value.clone()));
-                }
-                std::cmp::Ordering::Greater => {}
-            }
-        }
-        // If it's greater than the max, swap it with the max.
-        if let Some((max_key, max_value)) = self.max.as_mut() {
-            match key.cmp(max_key) {
-                std::cmp::Ordering::Greater => {
-                    swap(max_key, &mut key);
-                    swap(max_value, &mut value);
-                }
-                std::cmp::Ordering::Equal => {
-                    // If the key is the same, update the value.
-                    return_value = Some(replace(max_value, value.clone()));
-                }
-                std::cmp::Ordering::Less => {}
-            }
-        }
-
-        // If we replaced the min or max, we're done.
-        if return_value.is_some() {
-            return return_value;
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key doesn't fit in the tree's universe.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.assert_in_universe(&key);
+        let root = *self
+            .root
+            .get_or_insert_with(|| self.arena.alloc(self.max_size.clone()));
+        #[cfg(feature = "safety_checks")]
+        let shadow_entry = (key.clone(), value.clone());
+        let old_value = self.measure_mut(Operation::Insert, |map| {
+            map.arena.insert(root, key, value)
+        });
+        if old_value.is_none() {
+            self.len += 1;
         }
+        #[cfg(feature = "safety_checks")]
+        self.shadow_insert(
+            "insert",
+            &shadow_entry.0,
+            &shadow_entry.1,
+            old_value.as_ref(),
+        );
+        old_value
+    }
 
-        if let Some((min_key, _)) = self.min.as_ref()
-            && key == *min_key
-        {
-            // If the key is the same as the min key, min and max were
-            // duplicates, and we just swapped the key with the max so that
-            // they aren't duplicates anymore.
-            return None;
-        }
+    /// Insert a key-value pair into the tree, or leave the tree unchanged and
+    /// return an error when the memory for it can't be allocated.  Runs in
+    /// O(lg lg u) time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key doesn't fit in the tree's universe.
+    pub fn try_insert(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<Option<V>, TryReserveError> {
+        self.try_reserve(1)?;
+        Ok(self.insert(key, value))
+    }
 
-        let h = key.high(&self.cluster_size);
-        let cluster = self.clusters.entry(h.clone()).or_insert_with(|| {
-            VebTreeMap::with_max_size(self.cluster_size.clone())
-        });
-        // Only recurse on the summary if the cluster is empty and is about to
-        // transition to non-empty.  This prevents unneeded recursive calls on
-        // the summary.
-        if cluster.is_empty() {
-            self.summary
-                .get_or_insert_with(|| {
-                    Box::new(VebTreeMap::with_max_size(
-                        self.cluster_size.clone(),
-                    ))
-                })
-                .insert(h, ());
-        }
-        // When cluster is empty, this recursive call will trigger the lazy case
-        // and run in constant time.
-        let l = key.low(&self.cluster_size);
-        cluster.insert(l, value)
+    /// Reserve memory for at least `additional` more inserts, so that they
+    /// don't allocate, or return an error when it can't be allocated.
+    ///
+    /// This covers the nodes, the cluster hash tables, and the arenas of the
+    /// summaries.  Without the `std` feature, clusters are found in ordered
+    /// maps, which can't reserve memory ahead of time, so inserts may still
+    /// allocate.
+    pub fn try_reserve(
+        &mut self,
+        additional: usize,
+    ) -> Result<(), TryReserveError> {
+        self.arena.try_reserve(&self.max_size, additional)
     }
 
     /// Remove a key from the tree.  Runs in O(lg lg u) time.
     pub fn remove(&mut self, key: &K) {
-        #[cfg(any(test, feature = "safety_checks"))]
-        assert!(
-            *key <= K::size_to_key(&self.max_size),
-            "key must be representable by cluster's maximum size: key={:?}, max_size={:?}, size_to_key={:?}",
-            key,
-            self.max_size,
-            K::size_to_key(&self.max_size)
-        );
+        self.take(key);
+    }
 
-        let mut key = Cow::Borrowed(key);
-        if let Some((min_key, _)) = self.min.as_ref()
-            && *key == *min_key
-        {
-            match self.summary.as_ref().and_then(|summary| summary.min()) {
-                None => {
-                    self.min = None;
-                    self.max = None;
-                    return;
-                }
-                Some((summary_min, _)) => {
-                    let cluster = self
-                        .clusters
-                        .get_mut(&summary_min)
-                        .expect("cluster for summary min should exist");
-                    let (cluster_min, new_min_value) = cluster.min().expect(
-                        "cluster for summary min should have a min element",
-                    );
-                    let new_min_key =
-                        summary_min.index(cluster_min, &self.cluster_size);
-                    self.min = Some((new_min_key.clone(), new_min_value));
-                    key = Cow::Owned(new_min_key);
-                }
-            }
+    /// Remove a key from the tree and get its value.
+    pub(crate) fn take(&mut self, key: &K) -> Option<V> {
+        if !self.in_universe(key) {
+            return None;
         }
-
-        let h = key.high(&self.cluster_size);
-        if let Some(cluster) = self.clusters.get_mut(&h) {
-            cluster.remove(&key.low(&self.cluster_size));
-            if cluster.is_empty()
-                && let Some(summary) = self.summary.as_mut()
-            {
-                summary.remove(&h);
-            }
+        let root = self.root?;
+        let value = self
+            .measure_mut(Operation::Remove, |map| map.arena.remove(root, key));
+        if value.is_some() {
+            self.len -= 1;
         }
-
-        if let Some((max_key, _)) = self.max.as_ref()
-            && *key == *max_key
-        {
-            // TODO: summary should never be None here.
-            match self.summary.as_ref().and_then(|summary| summary.max()) {
-                None => {
-                    self.max = self.min.clone();
-                }
-                Some((summary_max, _)) => {
-                    let cluster = self
-                        .clusters
-                        .get_mut(&summary_max)
-                        .expect("cluster for summary min should exist");
-                    let (cluster_max, new_max_value) = cluster.max().expect(
-                        "cluster for summary min should have a min element",
-                    );
-                    let new_max_key =
-                        summary_max.index(cluster_max, &self.cluster_size);
-                    self.max = Some((new_max_key, new_max_value));
-                }
-            }
+        if self.arena.node(root).is_empty() {
+            // Every other node has been released already.
+            self.release_all();
         }
+        #[cfg(feature = "safety_checks")]
+        self.shadow_remove("remove", key, value.as_ref());
+        value
+    }
+
+    /// The minimum entry, without cloning the value.
+    pub(crate) fn min_entry(&self) -> Option<(K, &V)> {
+        let (k, v) = self.arena.node(self.root?).first()?;
+        Some((k.clone(), v))
+    }
+
+    /// The maximum entry, without cloning the value.
+    pub(crate) fn max_entry(&self) -> Option<(K, &V)> {
+        let (k, v) = self.arena.node(self.root?).last()?;
+        Some((k.clone(), v))
     }
 
     /// Get the successor of the given key.  Runs in O(lg lg u) time.
     pub fn successor(&self, key: &K) -> Option<(K, V)> {
-        #[cfg(any(test, feature = "safety_checks"))]
-        assert!(
-            *key <= K::size_to_key(&self.max_size),
-            "key must be representable by cluster's maximum size: key={:?}, max_size={:?}, size_to_key={:?}",
-            key,
-            self.max_size,
-            K::size_to_key(&self.max_size)
-        );
-
-        // If the key is less than the min, then the successor is the min.
-        if let Some((min_key, min_value)) = self.min.as_ref()
-            && *key < *min_key
-        {
-            return Some((min_key.clone(), min_value.clone()));
+        if !self.in_universe(key) {
+            return None;
         }
+        self.measure(Operation::Successor, |map| {
+            map.arena
+                .successor(map.root?, key)
+                .map(|(k, v)| (k, v.clone()))
+        })
+    }
 
-        // If the key is less than its cluster's max, then the successor is in
-        // that cluster.
-        let h = key.high(&self.cluster_size);
-        if let Some(cluster) = self.clusters.get(&h)
-            && let Some((cluster_max, _)) = cluster.max()
-        {
-            let l = key.low(&self.cluster_size);
-            if l < cluster_max {
-                // Recurse.
-                let successor = cluster.successor(&l);
-                match successor {
-                    // This should never happen since we checked that the
-                    // key is less than the cluster max.
-                    None => panic!(
-                        "key is less than cluster max, but successor wasn't found; key={key:?}, h={h:?}, l={l:?}, cluster_max={cluster_max:?}"
-                    ),
-                    Some((next_l, v)) => {
-                        return Some((h.index(next_l, &self.cluster_size), v));
-                    }
-                }
-            }
+    /// Get the predecessor of the given key.  Runs in O(lg lg u) time.
+    pub fn predecessor(&self, key: &K) -> Option<(K, V)> {
+        if !self.in_universe(key) {
+            return self.max();
         }
+        self.measure(Operation::Predecessor, |map| {
+            map.arena
+                .predecessor(map.root?, key)
+                .map(|(k, v)| (k, v.clone()))
+        })
+    }
 
-        // Recurse on the summary table to find the next cluster.  The successor
-        // is the min in that cluster.
-        if let Some(summary) = &self.summary {
-            // Recurse.
-            if let Some((next_h, _)) = summary.successor(&h)
-                && let Some(next_cluster) = self.clusters.get(&next_h)
-                && let Some((next_l, v)) = next_cluster.min()
-            {
-                return Some((next_h.index(next_l, &self.cluster_size), v));
-            }
+    /// Get the first entry at or after the given key.  Runs in O(lg lg u)
+    /// time.
+    pub fn ceiling(&self, key: &K) -> Option<(K, V)> {
+        if !self.in_universe(key) {
+            return None;
         }
+        self.measure(Operation::Ceiling, |map| {
+            map.arena
+                .ceiling(map.root?, key)
+                .map(|(k, v)| (k, v.clone()))
+        })
+    }
 
-        // If the key is less than the max, then the successor is the max.
-        if let Some((max_key, max_value)) = self.max.as_ref()
-            && *key < *max_key
-        {
-            return Some((max_key.clone(), max_value.clone()));
+    /// Get the last entry at or before the given key.  Runs in O(lg lg u)
+    /// time.
+    pub fn floor(&self, key: &K) -> Option<(K, V)> {
+        if !self.in_universe(key) {
+            return self.max();
         }
-
-        None
+        self.measure(Operation::Floor, |map| {
+            map.arena.floor(map.root?, key).map(|(k, v)| (k, v.clone()))
+        })
     }
+}
 
-    /// Get the predecessor of the given key.  Runs in O(lg lg u) time.
-    pub fn predecessor(&self, key: &K) -> Option<(K, V)> {
-        #[cfg(any(test, feature = "safety_checks"))]
-        assert!(
-            *key <= K::size_to_key(&self.max_size),
-            "key must be representable by cluster's maximum size: key={:?}, max_size={:?}, size_to_key={:?}",
-            key,
-            self.max_size,
-            K::size_to_key(&self.max_size)
-        );
-
-        // If the key is greater than the max, then the predecessor is the max.
-        if let Some((max_key, max_value)) = self.max.as_ref()
-            && *key > *max_key
-        {
-            return Some((max_key.clone(), max_value.clone()));
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug + Sub<Output = K>,
+    V: Clone + Debug,
+{
+    /// Get the entry with the key closest to the given key, on either side.
+    /// `tie` picks the side when two keys are equally close.  Runs in O(lg lg
+    /// u) time.
+    pub fn nearest(&self, key: &K, tie: Tie) -> Option<(K, V)> {
+        if !self.in_universe(key) {
+            return self.max();
         }
-
-        // If the key is greater than its cluster's min, then the predecessor is
-        // in that cluster.
-        let h = key.high(&self.cluster_size);
-        if let Some(cluster) = self.clusters.get(&h)
-            && let Some((cluster_min, _)) = cluster.min()
-        {
-            let l = key.low(&self.cluster_size);
-            if l > cluster_min {
-                // Recurse.
-                let predecessor = cluster.predecessor(&l);
-                match predecessor {
-                    // This should never happen since we checked that the
-                    // key is less than the cluster min.
-                    None => panic!(
-                        "key is less than cluster min, but predecessor wasn't found; key={key:?}, h={h:?}, l={l:?}, cluster_min={cluster_min:?}"
-                    ),
-                    Some((next_l, v)) => {
-                        return Some((h.index(next_l, &self.cluster_size), v));
+        let root = self.root?;
+        // The closure's tree can't lend the entries out, so this one does.
+        let bracket =
+            self.measure(Operation::Nearest, |_| self.arena.bracket(root, key));
+        let nearest = match bracket {
+            (Some(floor), Some(ceiling)) => {
+                let below = key.clone() - floor.0.clone();
+                let above = ceiling.0.clone() - key.clone();
+                match (below.cmp(&above), tie) {
+                    (Ordering::Less, _) | (Ordering::Equal, Tie::Lower) => {
+                        floor
+                    }
+                    (Ordering::Greater, _) | (Ordering::Equal, Tie::Higher) => {
+                        ceiling
                     }
                 }
             }
-        }
-
-        // Recurse on the summary table to find the previous cluster.  The
-        // predecessor is the max in that cluster.
-        if let Some(summary) = &self.summary {
-            // Recurse.
-            if let Some((prev_h, _)) = summary.predecessor(&h)
-                && let Some(prev_cluster) = self.clusters.get(&prev_h)
-                && let Some((prev_l, v)) = prev_cluster.max()
-            {
-                return Some((prev_h.index(prev_l, &self.cluster_size), v));
-            }
-        }
-
-        // If the key is greater than the min, then the predecessor is the min.
-        if let Some((min_key, min_value)) = self.min.as_ref()
-            && *key > *min_key
-        {
-            return Some((min_key.clone(), min_value.clone()));
-        }
-
-        None
+            (Some(entry), None) | (None, Some(entry)) => entry,
+            (None, None) => return None,
+        };
+        Some((nearest.0, nearest.1.clone()))
     }
 }
 
+/// Which entry [`VebTreeMap::nearest`] picks when the closest keys on both
+/// sides are equally close.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tie {
+    /// Pick the lower key.
+    Lower,
+    /// Pick the higher key.
+    Higher,
+}
+
 pub trait VebKey {
     /// The size (in bits) of a universe or child cluster.
     type Size: Clone + Debug;
@@ -480,10 +537,10 @@ macro_rules! impl_veb_key {
 
             #[inline]
             fn low(&self, cluster_size: &Self::Size) -> Self {
-                // self % cluster_size
+                // self % 2^cluster_size
                 #[cfg(any(test, feature = "safety_checks"))]
                 assert!(*cluster_size > 0);
-                *self & Self::from(cluster_size - 1)
+                *self & ((1 << *cluster_size) - 1)
             }
 
             #[inline]
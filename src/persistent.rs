@@ -0,0 +1,437 @@
+//! A tree whose versions share their unchanged nodes.
+//!
+//! Each node owns its min and max, an `Arc` of its summary, and its clusters
+//! in a [`Hamt`], a hash map that shares its unchanged branches between
+//! versions.  Finding a cluster is a lookup in that map, so, like the arena's
+//! hash table, it takes O(1) time.  Changing a node copies it only when
+//! another version shares it, so a change copies the nodes on its path and
+//! the branches of their maps that lead to the next node, and shares
+//! everything else.
+
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::mem::{replace, swap};
+
+use crate::VebKey;
+use crate::hamt::Hamt;
+
+#[derive(Debug, Clone)]
+struct Node<K, V> {
+    min: (K, V),
+    /// The maximum entry, only when it differs from the min.
+    max: Option<(K, V)>,
+    /// The numbers of the non-empty clusters.
+    summary: Option<Arc<Node<K, ()>>>,
+    /// The non-empty clusters by their number.
+    clusters: Hamt<K, Arc<Node<K, V>>>,
+}
+
+impl<K, V> Node<K, V>
+where
+    K: VebKey<Size = u8> + Ord + Clone + Hash,
+    V: Clone,
+{
+    fn single(key: K, value: V) -> Self {
+        Node {
+            min: (key, value),
+            max: None,
+            summary: None,
+            clusters: Hamt::new(),
+        }
+    }
+
+    /// The maximum entry, which is the min when there's only one.
+    fn last(&self) -> &(K, V) {
+        self.max.as_ref().unwrap_or(&self.min)
+    }
+
+    /// A cluster whose number is in the summary.
+    fn cluster(&self, high: &K) -> &Node<K, V> {
+        self.clusters
+            .get(high)
+            .expect("cluster in the summary must exist")
+    }
+
+    fn get(&self, bits: u8, key: &K) -> Option<&V> {
+        if *key == self.min.0 {
+            return Some(&self.min.1);
+        }
+        let max = self.max.as_ref()?;
+        match key.cmp(&max.0) {
+            Ordering::Equal => return Some(&max.1),
+            Ordering::Greater => return None,
+            Ordering::Less if *key < self.min.0 => return None,
+            Ordering::Less => {}
+        }
+        let c = K::cluster_size(&bits);
+        let cluster = self.clusters.get(&key.high(&c))?;
+        cluster.get(c, &key.low(&c))
+    }
+
+    /// Insert an entry.  It either goes into an existing cluster, or into a
+    /// new cluster whose number goes into the summary, so there's only one
+    /// recursive call.
+    fn insert(&mut self, bits: u8, mut key: K, mut value: V) -> Option<V> {
+        match key.cmp(&self.min.0) {
+            Ordering::Equal => return Some(replace(&mut self.min.1, value)),
+            Ordering::Less => {
+                swap(&mut self.min.0, &mut key);
+                swap(&mut self.min.1, &mut value);
+            }
+            Ordering::Greater => {}
+        }
+        let Some(max) = self.max.as_mut() else {
+            self.max = Some((key, value));
+            return None;
+        };
+        match key.cmp(&max.0) {
+            Ordering::Equal => return Some(replace(&mut max.1, value)),
+            Ordering::Greater => {
+                swap(&mut max.0, &mut key);
+                swap(&mut max.1, &mut value);
+            }
+            Ordering::Less => {}
+        }
+
+        // The entry is between the min and max, so it goes in a cluster.
+        let c = K::cluster_size(&bits);
+        let (high, low) = (key.high(&c), key.low(&c));
+        if let Some(cluster) = self.clusters.get_mut(&high) {
+            return Arc::make_mut(cluster).insert(c, low, value);
+        }
+        self.clusters
+            .insert(high.clone(), Arc::new(Node::single(low, value)));
+        match &mut self.summary {
+            None => self.summary = Some(Arc::new(Node::single(high, ()))),
+            Some(summary) => {
+                Arc::make_mut(summary).insert(c, high, ());
+            }
+        }
+        None
+    }
+
+    /// Remove an entry from a node with at least two entries, so that it
+    /// doesn't become empty.  Returns `None` when the key isn't in the node,
+    /// which is found out on the way down instead of with a separate lookup.
+    fn remove(&mut self, bits: u8, key: &K) -> Option<V> {
+        let c = K::cluster_size(&bits);
+        if *key == self.min.0 {
+            // Pull up the next entry to be the min.
+            let first = self.summary.as_ref().map(|summary| {
+                let high = summary.min.0.clone();
+                let low = self.cluster(&high).min.0.clone();
+                (high, low)
+            });
+            let next = match first {
+                None => self.max.take().expect("node must have two entries"),
+                Some((high, low)) => {
+                    let value = self
+                        .remove_from_cluster(c, &high, &low)
+                        .expect("cluster min must be in the cluster");
+                    (high.index(low, &c), value)
+                }
+            };
+            return Some(replace(&mut self.min, next).1);
+        }
+        let max = self.max.as_ref()?;
+        if *key == max.0 {
+            // Pull up the previous entry to be the max.
+            let last = self.summary.as_ref().map(|summary| {
+                let high = summary.last().0.clone();
+                let low = self.cluster(&high).last().0.clone();
+                (high, low)
+            });
+            let previous = match last {
+                None => return self.max.take().map(|(_, value)| value),
+                Some((high, low)) => {
+                    let value = self
+                        .remove_from_cluster(c, &high, &low)
+                        .expect("cluster max must be in the cluster");
+                    (high.index(low, &c), value)
+                }
+            };
+            let max = self.max.as_mut().expect("max was found");
+            return Some(replace(max, previous).1);
+        }
+        if *key < self.min.0 || *key > max.0 {
+            return None;
+        }
+        self.remove_from_cluster(c, &key.high(&c), &key.low(&c))
+    }
+
+    /// Remove an entry from a cluster, and the cluster from the summary when
+    /// it becomes empty.  Only one of the two recurses, since a cluster with
+    /// a single entry is released without recursing into it.
+    fn remove_from_cluster(&mut self, c: u8, high: &K, low: &K) -> Option<V> {
+        let cluster = self.clusters.get(high)?;
+        if cluster.max.is_some() {
+            let cluster = self.clusters.get_mut(high).expect("cluster exists");
+            return Arc::make_mut(cluster).remove(c, low);
+        }
+        if cluster.min.0 != *low {
+            return None;
+        }
+        // The entry is the cluster's only one, so release the cluster.
+        let cluster = self.clusters.remove(high).expect("cluster exists");
+        let summary = self.summary.as_mut().expect("summary has the cluster");
+        if summary.max.is_some() {
+            Arc::make_mut(summary).remove(c, high);
+        } else {
+            self.summary = None;
+        }
+        debug_assert_eq!(self.summary.is_none(), self.clusters.is_empty());
+        Some(into_min(cluster).1)
+    }
+
+    fn successor(&self, bits: u8, key: &K) -> Option<(K, &V)> {
+        if *key < self.min.0 {
+            return Some((self.min.0.clone(), &self.min.1));
+        }
+        let max = self.max.as_ref().filter(|max| *key < max.0)?;
+        let Some(summary) = &self.summary else {
+            return Some((max.0.clone(), &max.1));
+        };
+        let c = K::cluster_size(&bits);
+        let (high, low) = (key.high(&c), key.low(&c));
+        if let Some(cluster) = self.clusters.get(&high)
+            && low < cluster.last().0
+        {
+            let (low, value) = cluster
+                .successor(c, &low)
+                .expect("cluster has a greater key");
+            return Some((high.index(low, &c), value));
+        }
+        match summary.successor(c, &high) {
+            Some((high, ())) => {
+                let (low, value) = &self.cluster(&high).min;
+                Some((high.index(low.clone(), &c), value))
+            }
+            None => Some((max.0.clone(), &max.1)),
+        }
+    }
+
+    fn predecessor(&self, bits: u8, key: &K) -> Option<(K, &V)> {
+        let last = self.last();
+        if *key > last.0 {
+            return Some((last.0.clone(), &last.1));
+        }
+        if *key <= self.min.0 {
+            return None;
+        }
+        let Some(summary) = &self.summary else {
+            return Some((self.min.0.clone(), &self.min.1));
+        };
+        let c = K::cluster_size(&bits);
+        let (high, low) = (key.high(&c), key.low(&c));
+        if let Some(cluster) = self.clusters.get(&high)
+            && low > cluster.min.0
+        {
+            let (low, value) = cluster
+                .predecessor(c, &low)
+                .expect("cluster has a lesser key");
+            return Some((high.index(low, &c), value));
+        }
+        match summary.predecessor(c, &high) {
+            Some((high, ())) => {
+                let (low, value) = self.cluster(&high).last();
+                Some((high.index(low.clone(), &c), value))
+            }
+            None => Some((self.min.0.clone(), &self.min.1)),
+        }
+    }
+}
+
+/// Take the min of a node with a single entry, copying it if the node is
+/// shared.
+fn into_min<K, V>(node: Arc<Node<K, V>>) -> (K, V)
+where
+    K: Clone,
+    V: Clone,
+{
+    Arc::try_unwrap(node).map_or_else(|node| node.min.clone(), |node| node.min)
+}
+
+/// A map implemented with a van Emde Boas tree whose versions share their
+/// unchanged nodes.
+///
+/// Cloning is O(1), and changing a clone leaves the original as it was.  A
+/// change copies the O(lg lg u) nodes on its path when they're shared, and
+/// changes them in place when they aren't.  Each node finds its clusters in
+/// a hash map that versions share, so operations run in O(lg lg u) time.
+pub struct PersistentVebTreeMap<K, V> {
+    root: Option<Arc<Node<K, V>>>,
+    len: usize,
+}
+
+impl<K, V> PersistentVebTreeMap<K, V> {
+    pub fn new() -> PersistentVebTreeMap<K, V> {
+        PersistentVebTreeMap { root: None, len: 0 }
+    }
+
+    /// Returns the number of elements in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the tree has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Removes all elements.
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
+}
+
+impl<K, V> PersistentVebTreeMap<K, V>
+where
+    K: VebKey<Size = u8> + Ord + Clone + Hash + Debug,
+    V: Clone + Debug,
+{
+    /// Get the maximum element in the tree.  Runs in O(1) time.
+    pub fn max(&self) -> Option<(K, V)> {
+        let (key, value) = self.root.as_ref()?.last();
+        Some((key.clone(), value.clone()))
+    }
+
+    /// Get the minimum element in the tree.  Runs in O(1) time.
+    pub fn min(&self) -> Option<(K, V)> {
+        let (key, value) = &self.root.as_ref()?.min;
+        Some((key.clone(), value.clone()))
+    }
+
+    /// Lookup a key in the tree and get its value.  Runs in O(lg lg u) time.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let root = self.root.as_ref()?;
+        root.get(K::max_size(), key).cloned()
+    }
+
+    /// Insert a key-value pair into the tree.  Runs in O(lg lg u) time.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let old_value = match &mut self.root {
+            None => {
+                let root = Node::single(key, value);
+                self.root = Some(Arc::new(root));
+                None
+            }
+            Some(root) => Arc::make_mut(root).insert(K::max_size(), key, value),
+        };
+        if old_value.is_none() {
+            self.len += 1;
+        }
+        old_value
+    }
+
+    /// Remove a key from the tree.  Runs in O(lg lg u) time.
+    ///
+    /// A missing key is only noticed on the way down, so the shared nodes on
+    /// its path may still be copied.
+    pub fn remove(&mut self, key: &K) {
+        let Some(root) = &mut self.root else {
+            return;
+        };
+        if root.max.is_none() {
+            if root.min.0 == *key {
+                self.root = None;
+                self.len -= 1;
+            }
+        } else if Arc::make_mut(root).remove(K::max_size(), key).is_some() {
+            self.len -= 1;
+        }
+    }
+
+    /// Get a new version of the tree with a key-value pair inserted.  Runs in
+    /// O(lg lg u) time.
+    pub fn update(&self, key: K, value: V) -> Self {
+        let mut map = self.clone();
+        map.insert(key, value);
+        map
+    }
+
+    /// Get a new version of the tree without a key.  Runs in O(lg lg u) time.
+    pub fn without(&self, key: &K) -> Self {
+        let mut map = self.clone();
+        map.remove(key);
+        map
+    }
+
+    /// Get the successor of the given key.  Runs in O(lg lg u) time.
+    pub fn successor(&self, key: &K) -> Option<(K, V)> {
+        let root = self.root.as_ref()?;
+        let (key, value) = root.successor(K::max_size(), key)?;
+        Some((key, value.clone()))
+    }
+
+    /// Get the predecessor of the given key.  Runs in O(lg lg u) time.
+    pub fn predecessor(&self, key: &K) -> Option<(K, V)> {
+        let root = self.root.as_ref()?;
+        let (key, value) = root.predecessor(K::max_size(), key)?;
+        Some((key, value.clone()))
+    }
+
+    /// Iterate over the entries in order of their keys.
+    pub fn iter(&self) -> impl Iterator<Item = (K, &V)> + '_ {
+        let mut next = self
+            .root
+            .as_ref()
+            .map(|root| (root.min.0.clone(), &root.min.1));
+        core::iter::from_fn(move || {
+            let (key, value) = next.take()?;
+            next = self
+                .root
+                .as_ref()
+                .and_then(|root| root.successor(K::max_size(), &key));
+            Some((key, value))
+        })
+    }
+}
+
+impl<K, V> Clone for PersistentVebTreeMap<K, V> {
+    /// Share the tree's nodes with the clone.  Runs in O(1) time.
+    fn clone(&self) -> Self {
+        PersistentVebTreeMap {
+            root: self.root.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<K, V> Debug for PersistentVebTreeMap<K, V>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PersistentVebTreeMap")
+            .field("root", &self.root)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<K, V> Default for PersistentVebTreeMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for PersistentVebTreeMap<K, V>
+where
+    K: VebKey<Size = u8> + Ord + Clone + Hash + Debug,
+    V: Clone + Debug,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = Self::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
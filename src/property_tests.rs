@@ -1,6 +1,17 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+#[cfg(not(feature = "std"))]
+use std::prelude::rust_2024::*;
+#[cfg(not(feature = "std"))]
+use std::vec;
+
 use proptest::prelude::*;
 
-use crate::VebTreeMap;
+#[cfg(feature = "std")]
+use crate::{ConcurrentVebTreeMap, LittleEndianCodec};
+use crate::{
+    EliasFanoSet, Finger, FrozenVebTree, PersistentVebTreeMap, Tie, VebTreeMap,
+};
 
 proptest! {
     #[test]
@@ -85,7 +96,7 @@ fn verify_predecessor_successor(keys: &mut [u64]) -> Result<(), TestCaseError> {
     // Sort keys after inserting.
     keys.sort_unstable();
 
-    let min = t.min();
+    let min = VebTreeMap::min(&t);
     prop_assert_eq!(min, Some((keys[0], keys[0])));
     let mut key = min.unwrap().0;
     let mut i = 0;
@@ -102,7 +113,7 @@ fn verify_predecessor_successor(keys: &mut [u64]) -> Result<(), TestCaseError> {
         i += 1;
     }
 
-    let max = t.max();
+    let max = VebTreeMap::max(&t);
     prop_assert_eq!(max, Some((*keys.last().unwrap(), *keys.last().unwrap())));
     let mut key = max.unwrap().0;
     let mut i = 0;
@@ -121,3 +132,478 @@ fn verify_predecessor_successor(keys: &mut [u64]) -> Result<(), TestCaseError> {
 
     Ok(())
 }
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(u32, u32),
+    Remove(u32),
+}
+
+fn op_strategy() -> impl Strategy<Value = Op> {
+    // Keys from a small range collide often enough to exercise removal and
+    // overwrites.  Keys from the full range exercise sparse clusters.
+    let key = prop_oneof![0u32..64, any::<u32>()];
+    prop_oneof![
+        (key.clone(), any::<u32>()).prop_map(|(k, v)| Op::Insert(k, v)),
+        key.prop_map(Op::Remove),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn matches_btree_map(ops in prop::collection::vec(op_strategy(), 0..200)) {
+        let mut t = VebTreeMap::<u32, u32>::new();
+        let mut expected = BTreeMap::new();
+        for op in ops {
+            match op {
+                Op::Insert(k, v) => {
+                    prop_assert_eq!(t.insert(k, v), expected.insert(k, v));
+                }
+                Op::Remove(k) => {
+                    t.remove(&k);
+                    expected.remove(&k);
+                }
+            }
+            prop_assert_eq!(t.validate(), Ok(()));
+            prop_assert_eq!(t.is_empty(), expected.is_empty());
+            prop_assert_eq!(t.len(), expected.len());
+            prop_assert_eq!(
+                VebTreeMap::min(&t),
+                expected.first_key_value().map(|(k, v)| (*k, *v))
+            );
+            prop_assert_eq!(
+                VebTreeMap::max(&t),
+                expected.last_key_value().map(|(k, v)| (*k, *v))
+            );
+        }
+
+        // Walk the whole tree in both directions.
+        let mut entries = Vec::new();
+        let mut next = VebTreeMap::min(&t);
+        while let Some((k, v)) = next {
+            entries.push((k, v));
+            next = t.successor(&k);
+        }
+        let expected_entries: Vec<_> =
+            expected.iter().map(|(k, v)| (*k, *v)).collect();
+        prop_assert_eq!(&entries, &expected_entries);
+        let mut entries = Vec::new();
+        let mut next = VebTreeMap::max(&t);
+        while let Some((k, v)) = next {
+            entries.push((k, v));
+            next = t.predecessor(&k);
+        }
+        entries.reverse();
+        prop_assert_eq!(&entries, &expected_entries);
+
+        for k in expected.keys() {
+            prop_assert_eq!(t.get(k), expected.get(k).copied());
+            prop_assert_eq!(t.get(&k.wrapping_add(1)), expected.get(&k.wrapping_add(1)).copied());
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn fixed_matches_generic(
+        keys in prop::collection::vec(prop_oneof![0u64..64, any::<u64>()], 0..200),
+        targets in prop::collection::vec(any::<u64>(), 0..20),
+    ) {
+        let mut generic = VebTreeMap::<u64, u64>::new();
+        let mut fixed = VebTreeMap::<u64, u64>::new();
+        for (i, k) in keys.iter().enumerate() {
+            let v = u64::try_from(i).unwrap();
+            prop_assert_eq!(fixed.insert_fixed(*k, v), generic.insert(*k, v));
+        }
+        for k in keys.iter().chain(&targets) {
+            prop_assert_eq!(fixed.get_fixed(k), generic.get(k));
+            prop_assert_eq!(fixed.get(k), generic.get(k));
+            prop_assert_eq!(fixed.successor_fixed(k), generic.successor(k));
+            prop_assert_eq!(fixed.successor(k), generic.successor(k));
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn batches_match_single_lookups(
+        keys in prop::collection::vec(prop_oneof![0u64..64, any::<u64>()], 0..200),
+        targets in prop::collection::vec(prop_oneof![0u64..64, any::<u64>()], 0..50),
+    ) {
+        let mut t = VebTreeMap::<u64, u64>::new();
+        for k in &keys {
+            t.insert(*k, k.wrapping_mul(3));
+        }
+        let targets: Vec<u64> = keys.iter().chain(&targets).copied().collect();
+        let values: Vec<_> = targets.iter().map(|k| t.get(k)).collect();
+        prop_assert_eq!(t.get_many(&targets), values);
+        let successors: Vec<_> = targets.iter().map(|k| t.successor(k)).collect();
+        prop_assert_eq!(t.successor_many(&targets), successors);
+    }
+}
+
+#[derive(Debug, Clone)]
+enum FingerOp {
+    Insert(u32),
+    Remove(u32),
+    Get(u32),
+    Successor(u32),
+    Predecessor(u32),
+}
+
+fn finger_op_strategy() -> impl Strategy<Value = FingerOp> {
+    // Keys from a few narrow ranges share most of their paths.
+    let key = prop_oneof![0u32..256, 0x1_0000u32..0x1_0100, any::<u32>()];
+    prop_oneof![
+        key.clone().prop_map(FingerOp::Insert),
+        key.clone().prop_map(FingerOp::Remove),
+        key.clone().prop_map(FingerOp::Get),
+        key.clone().prop_map(FingerOp::Successor),
+        key.prop_map(FingerOp::Predecessor),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn finger_matches_btree_map(
+        ops in prop::collection::vec(finger_op_strategy(), 0..300),
+    ) {
+        let mut t = VebTreeMap::<u32, u32>::new();
+        let mut expected = BTreeMap::new();
+        let mut finger = Finger::new();
+        for op in ops {
+            match op {
+                FingerOp::Insert(k) => {
+                    prop_assert_eq!(
+                        t.insert_near(&mut finger, k, k ^ 1),
+                        expected.insert(k, k ^ 1)
+                    );
+                }
+                FingerOp::Remove(k) => {
+                    t.remove(&k);
+                    expected.remove(&k);
+                }
+                FingerOp::Get(k) => {
+                    prop_assert_eq!(
+                        t.get_near(&mut finger, &k),
+                        expected.get(&k).copied()
+                    );
+                }
+                FingerOp::Successor(k) => {
+                    prop_assert_eq!(
+                        t.successor_near(&mut finger, &k),
+                        expected
+                            .range(k..)
+                            .find(|(x, _)| **x > k)
+                            .map(|(k, v)| (*k, *v))
+                    );
+                }
+                FingerOp::Predecessor(k) => {
+                    prop_assert_eq!(
+                        t.predecessor_near(&mut finger, &k),
+                        expected.range(..k).next_back().map(|(k, v)| (*k, *v))
+                    );
+                }
+            }
+        }
+        prop_assert_eq!(t.validate(), Ok(()));
+    }
+}
+
+proptest! {
+    #[test]
+    fn cursor_walks_match_btree_map(
+        keys in prop::collection::vec(any::<u16>(), 0..200),
+        start in any::<u16>(),
+        remove_every in 1usize..4,
+    ) {
+        let mut t = VebTreeMap::<u16, u16>::new();
+        let mut expected = BTreeMap::new();
+        for k in keys {
+            t.insert(k, !k);
+            expected.insert(k, !k);
+        }
+
+        let mut c = t.lower_bound(Bound::Included(&start));
+        let mut forward = Vec::new();
+        while let Some((k, v)) = c.move_next() {
+            forward.push((k, *v));
+        }
+        let range: Vec<_> =
+            expected.range(start..).map(|(k, v)| (*k, *v)).collect();
+        prop_assert_eq!(forward, range);
+
+        let mut c = t.upper_bound(Bound::Excluded(&start));
+        let mut backward = Vec::new();
+        while let Some((k, v)) = c.move_prev() {
+            backward.push((k, *v));
+        }
+        let range: Vec<_> =
+            expected.range(..start).rev().map(|(k, v)| (*k, *v)).collect();
+        prop_assert_eq!(backward, range);
+
+        let mut c = t.lower_bound_mut(Bound::Unbounded);
+        let mut i = 0;
+        while c.peek_next().is_some() {
+            if i % remove_every == 0 {
+                c.remove_next();
+            } else {
+                c.move_next();
+            }
+            i += 1;
+        }
+        let mut i = 0;
+        expected.retain(|_, _| {
+            i += 1;
+            (i - 1) % remove_every != 0
+        });
+        prop_assert_eq!(VebTreeMap::min(&t), expected.first_key_value().map(|(k, v)| (*k, *v)));
+        for (k, v) in &expected {
+            prop_assert_eq!(t.get(k), Some(*v));
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn floor_ceiling_nearest_match_btree_map(
+        keys in prop::collection::vec(prop_oneof![0u16..512, any::<u16>()], 0..200),
+        targets in prop::collection::vec(prop_oneof![0u16..512, any::<u16>()], 0..50),
+    ) {
+        let mut t = VebTreeMap::<u16, u16>::new();
+        let mut expected = BTreeMap::new();
+        for k in keys.iter().chain(&targets).step_by(2) {
+            t.insert(*k, !*k);
+            expected.insert(*k, !*k);
+        }
+        for k in keys.iter().chain(&targets) {
+            let floor = expected.range(..=k).next_back().map(|(k, v)| (*k, *v));
+            let ceiling = expected.range(k..).next().map(|(k, v)| (*k, *v));
+            prop_assert_eq!(t.floor(k), floor);
+            prop_assert_eq!(t.ceiling(k), ceiling);
+
+            let (lower, higher) = match (floor, ceiling) {
+                (Some(f), Some(c)) if k - f.0 < c.0 - k => (f, f),
+                (Some(f), Some(c)) if k - f.0 > c.0 - k => (c, c),
+                (Some(f), Some(c)) => (f, c),
+                (Some(e), None) | (None, Some(e)) => (e, e),
+                (None, None) => {
+                    prop_assert_eq!(t.nearest(k, Tie::Lower), None);
+                    continue;
+                }
+            };
+            prop_assert_eq!(t.nearest(k, Tie::Lower), Some(lower));
+            prop_assert_eq!(t.nearest(k, Tie::Higher), Some(higher));
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn range_wrapping_matches_btree_map(
+        keys in prop::collection::vec(any::<u8>(), 0..100),
+        start in any::<u8>(),
+        end in any::<u8>(),
+    ) {
+        let mut t = VebTreeMap::<u8, u8>::new();
+        let mut expected = BTreeMap::new();
+        for k in keys {
+            t.insert(k, !k);
+            expected.insert(k, !k);
+        }
+
+        let range: Vec<_> = t.range_wrapping(start, end).map(|(k, v)| (k, *v)).collect();
+        let expected_range: Vec<_> = if start <= end {
+            expected.range(start..end).map(|(k, v)| (*k, *v)).collect()
+        } else {
+            expected
+                .range(start..)
+                .chain(expected.range(..end))
+                .map(|(k, v)| (*k, *v))
+                .collect()
+        };
+        prop_assert_eq!(range, expected_range);
+
+        let successor = expected
+            .range(start..)
+            .find(|(k, _)| **k > start)
+            .or_else(|| expected.first_key_value())
+            .map(|(k, v)| (*k, *v));
+        prop_assert_eq!(t.successor_wrapping(&start), successor);
+        let predecessor = expected
+            .range(..start)
+            .next_back()
+            .or_else(|| expected.last_key_value())
+            .map(|(k, v)| (*k, *v));
+        prop_assert_eq!(t.predecessor_wrapping(&start), predecessor);
+    }
+}
+
+proptest! {
+    #[cfg(feature = "std")]
+    #[test]
+    fn snapshot_round_trips(
+        entries in prop::collection::btree_map(any::<u64>(), any::<u16>(), 0..200),
+    ) {
+        let t = VebTreeMap::from_sorted_iter(entries.iter().map(|(k, v)| (*k, *v)));
+        let mut bytes = Vec::new();
+        t.write_to(&mut bytes, &LittleEndianCodec).unwrap();
+        let u = VebTreeMap::<u64, u16>::read_from(bytes.as_slice(), &LittleEndianCodec)
+            .unwrap();
+        prop_assert_eq!(u.len(), entries.len());
+        for (k, v) in &entries {
+            prop_assert_eq!(u.get(k), Some(*v));
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn frozen_matches_btree_map(
+        entries in prop::collection::btree_map(
+            prop_oneof![0u64..1024, any::<u64>()],
+            any::<u32>(),
+            0..200,
+        ),
+        targets in prop::collection::vec(prop_oneof![0u64..1024, any::<u64>()], 0..50),
+    ) {
+        let frozen =
+            FrozenVebTree::from_sorted_iter(entries.iter().map(|(k, v)| (*k, *v)));
+        prop_assert_eq!(frozen.len(), entries.len());
+        for k in entries.keys().chain(&targets) {
+            prop_assert_eq!(frozen.get(k), entries.get(k).copied());
+            prop_assert_eq!(
+                frozen.successor(k),
+                entries.range(k..).find(|(x, _)| *x > k).map(|(k, v)| (*k, *v))
+            );
+            prop_assert_eq!(
+                frozen.predecessor(k),
+                entries.range(..k).next_back().map(|(k, v)| (*k, *v))
+            );
+        }
+        for k in &targets {
+            let range: Vec<_> = frozen.range(..=k).collect();
+            let expected: Vec<_> =
+                entries.range(..=k).map(|(k, v)| (*k, *v)).collect();
+            prop_assert_eq!(range, expected);
+        }
+    }
+
+    #[test]
+    fn elias_fano_matches_btree_set(
+        keys in prop::collection::btree_set(
+            prop_oneof![0u64..4096, any::<u64>()],
+            0..1000,
+        ),
+        targets in prop::collection::vec(prop_oneof![0u64..4096, any::<u64>()], 0..50),
+    ) {
+        let set = EliasFanoSet::from_sorted_iter(keys.iter().copied());
+        prop_assert_eq!(set.len(), keys.len());
+        prop_assert!(set.iter().eq(keys.iter().copied()));
+        for k in keys.iter().chain(&targets) {
+            prop_assert_eq!(set.contains(k), keys.contains(k));
+            prop_assert_eq!(set.rank(k), keys.range(..k).count());
+            prop_assert_eq!(
+                set.successor(k),
+                keys.range(k..).find(|x| *x > k).copied()
+            );
+            prop_assert_eq!(
+                set.predecessor(k),
+                keys.range(..k).next_back().copied()
+            );
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn roaring_round_trips(
+        ranges in prop::collection::vec((any::<u32>(), 0u32..5000, 1usize..4), 0..4),
+    ) {
+        let mut keys = std::collections::BTreeSet::new();
+        for (start, len, step) in ranges {
+            keys.extend((start..start.saturating_add(len)).step_by(step));
+        }
+        let t = VebTreeMap::from_sorted_iter(keys.into_iter().map(|k| (k, ())));
+        let mut bytes = Vec::new();
+        t.write_roaring(&mut bytes).unwrap();
+        let read = VebTreeMap::read_roaring(bytes.as_slice()).unwrap();
+        prop_assert_eq!(read.len(), t.len());
+        let mut next = VebTreeMap::min(&t);
+        while let Some((k, _)) = next {
+            prop_assert_eq!(read.get(&k), Some(()));
+            next = t.successor(&k);
+        }
+    }
+
+    #[test]
+    fn persistent_versions_match_btree_maps(
+        operations in prop::collection::vec(
+            (any::<bool>(), prop_oneof![0u16..64, any::<u16>()], any::<u8>()),
+            0..300,
+        ),
+    ) {
+        let mut versions = vec![(PersistentVebTreeMap::new(), BTreeMap::new())];
+        for (i, (insert, k, v)) in operations.into_iter().enumerate() {
+            // Change an older version, so changes to shared nodes are checked.
+            let (mut t, mut expected) = versions[i / 3].clone();
+            if insert {
+                prop_assert_eq!(t.insert(k, v), expected.insert(k, v));
+            } else {
+                t.remove(&k);
+                expected.remove(&k);
+            }
+            versions.push((t, expected));
+        }
+        for (t, expected) in &versions {
+            prop_assert_eq!(t.len(), expected.len());
+            prop_assert!(t.iter().map(|(k, v)| (k, *v)).eq(expected.iter().map(|(k, v)| (*k, *v))));
+            for k in [0, 1, 31, 32, 63, 1000, u16::MAX] {
+                prop_assert_eq!(t.get(&k), expected.get(&k).copied());
+                prop_assert_eq!(
+                    t.successor(&k),
+                    expected.range(k..).find(|(x, _)| **x > k).map(|(k, v)| (*k, *v))
+                );
+                prop_assert_eq!(
+                    t.predecessor(&k),
+                    expected.range(..k).next_back().map(|(k, v)| (*k, *v))
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn concurrent_matches_btree_map(
+        shard_bits in 0u8..=8,
+        operations in prop::collection::vec(
+            (any::<bool>(), prop_oneof![0u16..64, any::<u16>()]),
+            0..200,
+        ),
+        targets in prop::collection::vec(any::<u16>(), 0..20),
+    ) {
+        let t = ConcurrentVebTreeMap::with_shard_bits(shard_bits);
+        let mut expected = BTreeMap::new();
+        for (insert, k) in operations {
+            if insert {
+                prop_assert_eq!(t.insert(k, k), expected.insert(k, k));
+            } else {
+                t.remove(&k);
+                expected.remove(&k);
+            }
+        }
+        prop_assert_eq!(t.len(), expected.len());
+        prop_assert_eq!(t.min(), expected.first_key_value().map(|(k, v)| (*k, *v)));
+        prop_assert_eq!(t.max(), expected.last_key_value().map(|(k, v)| (*k, *v)));
+        for k in expected.keys().copied().collect::<Vec<_>>().iter().chain(&targets) {
+            prop_assert_eq!(t.get(k), expected.get(k).copied());
+            prop_assert_eq!(
+                t.successor(k),
+                expected.range(k..).find(|(x, _)| *x > k).map(|(k, v)| (*k, *v))
+            );
+            prop_assert_eq!(
+                t.predecessor(k),
+                expected.range(..k).next_back().map(|(k, v)| (*k, *v))
+            );
+        }
+    }
+}
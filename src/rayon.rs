@@ -0,0 +1,184 @@
+//! Parallel iteration and building with [Rayon](https://docs.rs/rayon).
+//!
+//! The root's clusters don't share any nodes, so the work is split along
+//! them.  Building makes each of the root's clusters in its own arena, and
+//! then moves the arenas into the tree's and adds the clusters to the root's
+//! summary.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+
+use ::rayon::prelude::*;
+
+use crate::arena::{Arena, NodeId};
+use crate::{Finger, VebKey, VebTreeMap};
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug + Send + Sync,
+    K::Size: Send + Sync,
+    V: Clone + Debug + Send + Sync,
+{
+    /// Create a tree from entries in strictly increasing order of keys,
+    /// building the root's clusters in parallel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keys aren't strictly increasing.
+    pub fn from_sorted_par_iter<I>(iter: I) -> VebTreeMap<K, V>
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        Self::new().build_sorted_par(iter)
+    }
+
+    /// Fill an empty tree from entries in strictly increasing order of keys,
+    /// building the root's clusters in parallel.
+    fn build_sorted_par<I>(self, iter: I) -> VebTreeMap<K, V>
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        let mut entries: Vec<(K, V)> = iter.into_par_iter().collect();
+        if let Some(i) = entries
+            .par_windows(2)
+            .position_any(|pair| pair[0].0 >= pair[1].0)
+        {
+            panic!(
+                "keys must be strictly increasing: key={:?}, previous={:?}",
+                entries[i + 1].0,
+                entries[i].0
+            );
+        }
+
+        let mut map = self;
+        let Some(last) = entries.pop() else {
+            return map;
+        };
+        map.assert_in_universe(&last.0);
+        map.len = entries.len() + 1;
+        let mut entries = entries.into_iter();
+        // A single entry is only the min.
+        let (min, max) = match entries.next() {
+            Some(first) => (first, Some(last)),
+            None => (last, None),
+        };
+
+        // Group the rest by the root cluster they go in.
+        let cluster_size = K::cluster_size(&map.max_size);
+        let mut groups: Vec<(K, Vec<(K, V)>)> = Vec::new();
+        for (key, value) in entries {
+            let (h, l) = (key.high(&cluster_size), key.low(&cluster_size));
+            match groups.last_mut() {
+                Some((last, group)) if *last == h => group.push((l, value)),
+                _ => groups.push((h, vec![(l, value)])),
+            }
+        }
+        let clusters: Vec<(K, VebTreeMap<K, V>)> = groups
+            .into_par_iter()
+            .map(|(h, group)| {
+                let mut cluster = Self::with_max_size(cluster_size.clone());
+                let mut finger = Finger::new();
+                for (l, value) in group {
+                    cluster.insert_near(&mut finger, l, value);
+                }
+                (h, cluster)
+            })
+            .collect();
+
+        // Stitch the clusters to the root.
+        let root = map.arena.alloc(map.max_size.clone());
+        let node = map.arena.node_mut(root);
+        node.min = Some(min);
+        node.max = max;
+        for (h, cluster) in clusters {
+            let cluster_root = cluster.root.expect("cluster must not be empty");
+            let offset = map.arena.append(cluster.arena, &|value| value);
+            map.arena
+                .attach_cluster(root, h, cluster_root.shifted(offset));
+        }
+        map.root = Some(root);
+        #[cfg(feature = "safety_checks")]
+        map.shadow_build("from_sorted_par_iter");
+        map
+    }
+
+    /// Iterate over the entries in parallel, in order of their keys when
+    /// collected.
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = (K, &V)> {
+        let root = self.root.map(|root| self.arena.node(root));
+        let min = root
+            .and_then(|node| node.first())
+            .map(|(k, v)| (k.clone(), v));
+        let max = root
+            .and_then(|node| node.max.as_ref())
+            .map(|(k, v)| (k.clone(), v));
+        let clusters: Vec<(K, NodeId)> = self
+            .root
+            .map(|root| self.arena.clusters_of(root).collect())
+            .unwrap_or_default();
+        let cluster_size = K::cluster_size(&self.max_size);
+        min.into_par_iter()
+            .chain(clusters.into_par_iter().flat_map_iter(
+                move |(h, cluster)| {
+                    let cluster_size = cluster_size.clone();
+                    self.arena
+                        .entries_of(cluster)
+                        .map(move |(l, v)| (h.index(l, &cluster_size), v))
+                },
+            ))
+            .chain(max)
+    }
+
+    /// Iterate over the entries in parallel with mutable values, in order of
+    /// their keys when collected.
+    pub fn par_iter_mut(
+        &mut self,
+    ) -> impl ParallelIterator<Item = (K, &mut V)> {
+        #[cfg(feature = "safety_checks")]
+        self.shadow.lend();
+        let located = self
+            .root
+            .map(|root| self.arena.locate_entries(root))
+            .unwrap_or_default();
+        Arena::<K, V>::place(located, self.arena.values_mut())
+            .into_par_iter()
+            .flat_map_iter(|group| group)
+    }
+
+    /// Iterate over the entries in parallel, taking ownership of them, in
+    /// order of their keys when collected.
+    pub fn into_par_iter(self) -> impl ParallelIterator<Item = (K, V)> {
+        let located = self
+            .root
+            .map(|root| self.arena.locate_entries(root))
+            .unwrap_or_default();
+        Arena::<K, V>::place(located, self.arena.into_values())
+            .into_par_iter()
+            .flat_map_iter(|group| group)
+    }
+}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey<Size = u8> + Ord + Clone + Hash + Eq + Debug + Send + Sync,
+    V: Clone + Debug + Send + Sync,
+{
+    /// Create a tree whose keys are all less than 2^`bits` from entries in
+    /// strictly increasing order of keys, building the root's clusters in
+    /// parallel.  See [`VebTreeMap::with_universe_bits`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keys aren't strictly increasing, if a key doesn't fit in
+    /// the universe, or if `bits` isn't a power of two no larger than the key
+    /// type.
+    pub fn from_sorted_par_iter_with_universe_bits<I>(
+        bits: u8,
+        iter: I,
+    ) -> VebTreeMap<K, V>
+    where
+        I: IntoParallelIterator<Item = (K, V)>,
+    {
+        Self::with_universe_bits(bits).build_sorted_par(iter)
+    }
+}
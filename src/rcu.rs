@@ -0,0 +1,158 @@
+//! Publishing read-only versions of a tree for read-mostly workloads.
+//!
+//! Writers build a new version off to the side and swap it in, and readers
+//! keep using the version they have until they check for a newer one.  The
+//! current version is an atomically swapped pointer, so readers never lock:
+//! loading it is a few atomic operations, and a version is freed once the
+//! last reader holding it lets go.  Each reader also caches the version it
+//! loaded, so a read only compares pointers unless a new version was
+//! published since the reader last looked.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use arc_swap::ArcSwap;
+
+/// A value, such as a [`VebTreeMap`](crate::VebTreeMap),
+/// [`PersistentVebTreeMap`](crate::PersistentVebTreeMap) or
+/// [`FrozenVebTree`](crate::FrozenVebTree), that's replaced by publishing
+/// new versions of it.
+///
+/// Readers are lock-free: they never wait for writers or for each other.
+/// Writers wait for each other, so a batch of changes is applied to the
+/// latest version.
+#[derive(Debug)]
+pub struct RcuCell<T> {
+    current: ArcSwap<Version<T>>,
+    /// Held while building a new version, so that concurrent updates don't
+    /// lose each other's changes.
+    writer: Mutex<()>,
+}
+
+/// A published value and its version number.  The value has its own `Arc`,
+/// so that [`RcuCell::load`] can hand it out.
+#[derive(Debug)]
+struct Version<T> {
+    number: u64,
+    value: Arc<T>,
+}
+
+impl<T> RcuCell<T> {
+    pub fn new(value: T) -> RcuCell<T> {
+        RcuCell {
+            current: ArcSwap::from_pointee(Version {
+                number: 0,
+                value: Arc::new(value),
+            }),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// The number of versions published after the first.
+    pub fn version(&self) -> u64 {
+        self.current.load().number
+    }
+
+    /// Get the current version without locking.  Readers that read often
+    /// should use [`RcuCell::reader`], which skips the reference count
+    /// update while the version doesn't change.
+    pub fn load(&self) -> Arc<T> {
+        Arc::clone(&self.current.load().value)
+    }
+
+    /// Replace the current version.  Readers see it the next time they
+    /// check, and the versions they hold stay valid until they drop them.
+    pub fn publish(&self, value: T) {
+        let _writer = self.lock_writer();
+        self.swap(value);
+    }
+
+    /// Build a new version from the current one and publish it.  The
+    /// function runs while holding the writer lock, so batch many changes
+    /// into one update.
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&T) -> T,
+    {
+        let _writer = self.lock_writer();
+        let value = f(&self.current.load().value);
+        self.swap(value);
+    }
+
+    /// Change a copy of the current version and publish it.  Cloning a
+    /// [`PersistentVebTreeMap`](crate::PersistentVebTreeMap) is O(1), so
+    /// only the changed nodes are copied.
+    pub fn update_clone<F>(&self, f: F)
+    where
+        T: Clone,
+        F: FnOnce(&mut T),
+    {
+        self.update(|current| {
+            let mut value = current.clone();
+            f(&mut value);
+            value
+        });
+    }
+
+    /// Create a reader that caches the current version.
+    pub fn reader(&self) -> RcuReader<'_, T> {
+        RcuReader {
+            cell: self,
+            cached: self.current.load_full(),
+        }
+    }
+
+    /// Publish a value as the next version.  The caller holds the writer
+    /// lock, so the number can't be taken twice.
+    fn swap(&self, value: T) {
+        let number = self.current.load().number + 1;
+        self.current.store(Arc::new(Version {
+            number,
+            value: Arc::new(value),
+        }));
+    }
+
+    fn lock_writer(&self) -> MutexGuard<'_, ()> {
+        self.writer
+            .lock()
+            .expect("writer lock must not be poisoned")
+    }
+}
+
+/// A reader of an [`RcuCell`] that only updates reference counts when a new
+/// version has been published.  Give each thread its own reader.
+#[derive(Debug)]
+pub struct RcuReader<'a, T> {
+    cell: &'a RcuCell<T>,
+    cached: Arc<Version<T>>,
+}
+
+impl<T> RcuReader<'_, T> {
+    /// Get the latest version, refreshing the cached version if a newer one
+    /// was published.  Never locks.
+    pub fn get(&mut self) -> &T {
+        let current = self.cell.current.load();
+        if !Arc::ptr_eq(&current, &self.cached) {
+            self.cached = arc_swap::Guard::into_inner(current);
+        }
+        &self.cached.value
+    }
+
+    /// Get the cached version without checking for a newer one.
+    pub fn cached(&self) -> &T {
+        &self.cached.value
+    }
+
+    /// The version number of the cached version.
+    pub fn version(&self) -> u64 {
+        self.cached.number
+    }
+}
+
+impl<T> Clone for RcuReader<'_, T> {
+    fn clone(&self) -> Self {
+        RcuReader {
+            cell: self.cell,
+            cached: Arc::clone(&self.cached),
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Reading and writing sets of u32 keys in the portable serialization format
+//! of [Roaring bitmaps](https://github.com/RoaringBitmap/RoaringFormatSpec).
+//!
+//! Roaring splits the keys by their high 16 bits into containers of up to
+//! 2^16 low bits each.  A container is stored as whichever is smallest:
+//!
+//! - An array of the sorted low bits, for at most 4096 keys.
+//! - A bitset of 2^16 bits.
+//! - A list of runs, each the start and length minus one of consecutive keys.
+//!
+//! The data starts with a cookie that says whether there are run containers.
+//! Without them, it's the u32 `12346` and the number of containers as a u32.
+//! With them, it's the u16 `12347`, the number of containers minus one as a
+//! u16, and a bitset marking the run containers.  Next, each container's high
+//! bits and number of keys minus one, as u16s.  Then the offset of each
+//! container from the start of the data, as u32s, which is left out when
+//! there are run containers and fewer than 4 containers.  Last come the
+//! containers.  Everything is little-endian.
+
+use core::ops::Bound;
+use std::io::{self, Read, Write};
+
+use crate::{Finger, SnapshotError, VebTreeMap};
+
+const COOKIE_NO_RUNS: u32 = 12346;
+const COOKIE: u16 = 12347;
+/// With run containers, offsets are only written for at least this many
+/// containers.
+const NO_OFFSET_THRESHOLD: usize = 4;
+/// The most keys in an array container.
+const MAX_ARRAY: usize = 4096;
+const BITSET_WORDS: usize = 1024;
+
+enum Container {
+    Array(Vec<u16>),
+    Bitset(Box<[u64; BITSET_WORDS]>),
+    /// Runs of the first key and the number of keys after it.
+    Run(Vec<(u16, u16)>),
+}
+
+impl Container {
+    /// Pick the smallest container for the sorted low bits.
+    fn new(lows: Vec<u16>) -> Container {
+        let mut runs: Vec<(u16, u16)> = Vec::new();
+        for low in &lows {
+            match runs.last_mut() {
+                Some((start, length))
+                    if u32::from(*start) + u32::from(*length) + 1
+                        == u32::from(*low) =>
+                {
+                    *length += 1;
+                }
+                _ => runs.push((*low, 0)),
+            }
+        }
+        let run_size = 2 + 4 * runs.len();
+        let other_size = (2 * lows.len()).min(8 * BITSET_WORDS);
+        if run_size < other_size {
+            Container::Run(runs)
+        } else if lows.len() <= MAX_ARRAY {
+            Container::Array(lows)
+        } else {
+            let mut bits = Box::new([0; BITSET_WORDS]);
+            for low in lows {
+                let low = usize::from(low);
+                bits[low / 64] |= 1 << (low % 64);
+            }
+            Container::Bitset(bits)
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        match self {
+            Container::Array(lows) => 2 * lows.len(),
+            Container::Bitset(_) => 8 * BITSET_WORDS,
+            Container::Run(runs) => 2 + 4 * runs.len(),
+        }
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Container::Array(lows) => {
+                for low in lows {
+                    writer.write_all(&low.to_le_bytes())?;
+                }
+            }
+            Container::Bitset(bits) => {
+                for word in bits.iter() {
+                    writer.write_all(&word.to_le_bytes())?;
+                }
+            }
+            Container::Run(runs) => {
+                let len = u16::try_from(runs.len())
+                    .expect("a container has at most 2^15 runs");
+                writer.write_all(&len.to_le_bytes())?;
+                for (start, length) in runs {
+                    writer.write_all(&start.to_le_bytes())?;
+                    writer.write_all(&length.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl VebTreeMap<u32, ()> {
+    /// Write the keys in the portable Roaring bitmap format, so other Roaring
+    /// implementations can read them.  Runs in O(n lg lg u) time.
+    ///
+    /// Writes are small, so use a buffered writer.
+    pub fn write_roaring<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let mut containers: Vec<(u16, usize, Container)> = Vec::new();
+        let mut high = None;
+        let mut lows = Vec::new();
+        let mut cursor = self.lower_bound(Bound::Unbounded);
+        loop {
+            let key = cursor.move_next().map(|(key, _)| key);
+            let key_high = key.map(|key| split(key).0);
+            if key_high != high {
+                if let Some(high) = high {
+                    let lows = core::mem::take(&mut lows);
+                    containers.push((high, lows.len(), Container::new(lows)));
+                }
+                high = key_high;
+            }
+            let Some(key) = key else { break };
+            lows.push(split(key).1);
+        }
+
+        let has_runs = containers
+            .iter()
+            .any(|(_, _, container)| matches!(container, Container::Run(_)));
+        let mut header_size = 4 + 4 * containers.len();
+        if has_runs {
+            // The size minus one fits, since there are at most 2^16.
+            let size = u16::try_from(containers.len().saturating_sub(1))
+                .expect("there are at most 2^16 containers");
+            writer.write_all(&COOKIE.to_le_bytes())?;
+            writer.write_all(&size.to_le_bytes())?;
+            let mut run_bits = vec![0u8; containers.len().div_ceil(8)];
+            for (i, (_, _, container)) in containers.iter().enumerate() {
+                if matches!(container, Container::Run(_)) {
+                    run_bits[i / 8] |= 1 << (i % 8);
+                }
+            }
+            writer.write_all(&run_bits)?;
+            header_size += run_bits.len();
+        } else {
+            let size = u32::try_from(containers.len())
+                .expect("there are at most 2^16 containers");
+            writer.write_all(&COOKIE_NO_RUNS.to_le_bytes())?;
+            writer.write_all(&size.to_le_bytes())?;
+            header_size += 4;
+        }
+        for (high, len, _) in &containers {
+            let cardinality = u16::try_from(len - 1)
+                .expect("a container has at most 2^16 keys");
+            writer.write_all(&high.to_le_bytes())?;
+            writer.write_all(&cardinality.to_le_bytes())?;
+        }
+        if !has_runs || containers.len() >= NO_OFFSET_THRESHOLD {
+            header_size += 4 * containers.len();
+            let mut offset = header_size;
+            for (_, _, container) in &containers {
+                let offset_bytes = u32::try_from(offset)
+                    .expect("offsets must fit in u32")
+                    .to_le_bytes();
+                writer.write_all(&offset_bytes)?;
+                offset += container.serialized_size();
+            }
+        }
+        for (_, _, container) in &containers {
+            container.write_to(&mut writer)?;
+        }
+        writer.flush()
+    }
+
+    /// Read keys in the portable Roaring bitmap format, as written by any
+    /// Roaring implementation.  Runs in O(n lg lg u) time.
+    ///
+    /// Reads are small, so use a buffered reader.
+    pub fn read_roaring<R>(mut reader: R) -> Result<Self, SnapshotError>
+    where
+        R: Read,
+    {
+        let cookie = read_u32(&mut reader)?;
+        let (size, run_bits) = if cookie == COOKIE_NO_RUNS {
+            let size = usize::try_from(read_u32(&mut reader)?)
+                .ok()
+                .filter(|size| *size <= 1 << 16)
+                .ok_or(SnapshotError::Corrupt("too many containers"))?;
+            (size, Vec::new())
+        } else if cookie & 0xffff == u32::from(COOKIE) {
+            let size =
+                usize::try_from(cookie >> 16).expect("u16 fits in usize") + 1;
+            let mut run_bits = vec![0u8; size.div_ceil(8)];
+            reader.read_exact(&mut run_bits)?;
+            (size, run_bits)
+        } else {
+            return Err(SnapshotError::BadMagic);
+        };
+        let is_run = |i: usize| {
+            run_bits.get(i / 8).is_some_and(|b| b >> (i % 8) & 1 != 0)
+        };
+
+        let has_runs = cookie != COOKIE_NO_RUNS;
+        let mut read_size = if has_runs { 4 + run_bits.len() } else { 8 };
+        let mut headers = Vec::with_capacity(size);
+        for _ in 0..size {
+            let high = read_u16(&mut reader)?;
+            let len = usize::from(read_u16(&mut reader)?) + 1;
+            if headers.last().is_some_and(|(last, _)| *last >= high) {
+                return Err(SnapshotError::Corrupt(
+                    "containers must be strictly increasing",
+                ));
+            }
+            headers.push((high, len));
+        }
+        read_size += 4 * size;
+        let offsets = if !has_runs || size >= NO_OFFSET_THRESHOLD {
+            read_size += 4 * size;
+            (0..size)
+                .map(|_| read_u32(&mut reader))
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            Vec::new()
+        };
+
+        let mut map = Self::new();
+        let mut finger = Finger::new();
+        #[cfg(feature = "safety_checks")]
+        map.shadow.begin_batch();
+        let mut lows = Vec::new();
+        for (i, (high, len)) in headers.into_iter().enumerate() {
+            if offsets
+                .get(i)
+                .is_some_and(|offset| u32::try_from(read_size) != Ok(*offset))
+            {
+                return Err(SnapshotError::Corrupt(
+                    "container offset is wrong",
+                ));
+            }
+            lows.clear();
+            read_size += if is_run(i) {
+                read_runs(&mut reader, &mut lows)?
+            } else if len <= MAX_ARRAY {
+                read_array(&mut reader, len, &mut lows)?
+            } else {
+                read_bitset(&mut reader, &mut lows)?
+            };
+            if lows.len() != len {
+                return Err(SnapshotError::Corrupt(
+                    "container has the wrong number of keys",
+                ));
+            }
+            for low in &lows {
+                let key = (u32::from(high) << 16) | u32::from(*low);
+                map.insert_near(&mut finger, key, ());
+            }
+        }
+        #[cfg(feature = "safety_checks")]
+        map.shadow_end_batch();
+        Ok(map)
+    }
+}
+
+/// Split a key into its high and low 16 bits.
+fn split(key: u32) -> (u16, u16) {
+    let high = u16::try_from(key >> 16).expect("shifted to 16 bits");
+    let low = u16::try_from(key & 0xffff).expect("masked to 16 bits");
+    (high, low)
+}
+
+/// Read an array container, returning its size.
+fn read_array(
+    reader: &mut impl Read,
+    len: usize,
+    lows: &mut Vec<u16>,
+) -> Result<usize, SnapshotError> {
+    for _ in 0..len {
+        let low = read_u16(reader)?;
+        if lows.last().is_some_and(|last| *last >= low) {
+            return Err(SnapshotError::Corrupt(
+                "keys must be strictly increasing",
+            ));
+        }
+        lows.push(low);
+    }
+    Ok(2 * len)
+}
+
+/// Read a bitset container, returning its size.
+fn read_bitset(
+    reader: &mut impl Read,
+    lows: &mut Vec<u16>,
+) -> Result<usize, SnapshotError> {
+    let mut low = 0u16;
+    for _ in 0..BITSET_WORDS {
+        let mut word = [0; 8];
+        reader.read_exact(&mut word)?;
+        let word = u64::from_le_bytes(word);
+        for bit in 0..64 {
+            if word >> bit & 1 != 0 {
+                lows.push(low);
+            }
+            low = low.wrapping_add(1);
+        }
+    }
+    Ok(8 * BITSET_WORDS)
+}
+
+/// Read a run container, returning its size.
+fn read_runs(
+    reader: &mut impl Read,
+    lows: &mut Vec<u16>,
+) -> Result<usize, SnapshotError> {
+    let runs = read_u16(reader)?;
+    let mut next = 0u32;
+    for _ in 0..runs {
+        let start = u32::from(read_u16(reader)?);
+        let end = start + u32::from(read_u16(reader)?);
+        if start < next || end > 0xffff {
+            return Err(SnapshotError::Corrupt(
+                "runs must be increasing and not overlap",
+            ));
+        }
+        for low in start..=end {
+            lows.push(u16::try_from(low).expect("checked to fit in u16"));
+        }
+        next = end + 1;
+    }
+    Ok(2 + 4 * usize::from(runs))
+}
+
+fn read_u16(reader: &mut impl Read) -> Result<u16, SnapshotError> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, SnapshotError> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
@@ -0,0 +1,146 @@
+//! Serialization of trees as sequences of entries in key order, rather than
+//! their internal layout.
+
+use core::fmt;
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::ops::Bound;
+
+use ::serde::de::{DeserializeSeed, Error, SeqAccess, Visitor};
+use ::serde::ser::SerializeSeq;
+use ::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Finger, VebKey, VebTreeMap};
+
+impl<K, V> Serialize for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug + Serialize,
+    V: Clone + Debug + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        let mut cursor = self.lower_bound(Bound::Unbounded);
+        while let Some(entry) = cursor.move_next() {
+            seq.serialize_element(&entry)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug + Deserialize<'de>,
+    V: Clone + Debug + Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        UniverseSeed {
+            map: VebTreeMap::new(),
+        }
+        .deserialize(deserializer)
+    }
+}
+
+/// Deserializes a tree with a universe smaller than its key type, which
+/// rejects keys that don't fit in the universe.
+///
+/// The entries must be in strictly increasing order of keys, like
+/// [`VebTreeMap`] serializes them, so that the tree can be built the same way
+/// as [`VebTreeMap::from_sorted_iter`].
+pub struct UniverseSeed<K, V>
+where
+    K: VebKey,
+{
+    /// The empty tree to deserialize into.
+    map: VebTreeMap<K, V>,
+}
+
+impl<K, V> Debug for UniverseSeed<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UniverseSeed")
+            .field("map", &self.map)
+            .finish()
+    }
+}
+
+impl<K, V> UniverseSeed<K, V>
+where
+    K: VebKey<Size = u8>,
+{
+    /// See [`VebTreeMap::with_universe_bits`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` isn't a power of two or is larger than the number of
+    /// bits in the key type.
+    pub fn new(bits: u8) -> UniverseSeed<K, V> {
+        UniverseSeed {
+            map: VebTreeMap::with_universe_bits(bits),
+        }
+    }
+}
+
+impl<'de, K, V> DeserializeSeed<'de> for UniverseSeed<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug + Deserialize<'de>,
+    V: Clone + Debug + Deserialize<'de>,
+{
+    type Value = VebTreeMap<K, V>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, K, V> Visitor<'de> for UniverseSeed<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug + Deserialize<'de>,
+    V: Clone + Debug + Deserialize<'de>,
+{
+    type Value = VebTreeMap<K, V>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of key-value pairs in key order")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut map = self.map;
+        let mut finger = Finger::new();
+        #[cfg(feature = "safety_checks")]
+        map.shadow.begin_batch();
+        while let Some((key, value)) = seq.next_element::<(K, V)>()? {
+            if !map.in_universe(&key) {
+                return Err(A::Error::custom(format_args!(
+                    "key {key:?} doesn't fit in a universe of {:?} bits",
+                    map.max_size
+                )));
+            }
+            if let Some((max, _)) = map.max_entry()
+                && key <= max
+            {
+                return Err(A::Error::custom(format_args!(
+                    "keys must be strictly increasing, but {key:?} came after {max:?}"
+                )));
+            }
+            map.insert_near(&mut finger, key, value);
+        }
+        #[cfg(feature = "safety_checks")]
+        map.shadow_end_batch();
+        Ok(map)
+    }
+}
@@ -0,0 +1,242 @@
+//! With the `safety_checks` feature, every tree keeps a copy of its entries
+//! in a [`BTreeMap`] on the side.  After each change, the change's result is
+//! checked against it, the whole tree is validated, and every entry is
+//! compared to it.  That takes O(n lg n) time per change, so the feature is
+//! meant for tests of small trees.  Bulk changes, like building a tree from
+//! sorted entries, check each entry as it's added and the whole tree once at
+//! the end.  A failure panics with every operation since the tree was
+//! created, with its arguments, so the bug can be replayed.  For long tests,
+//! [`VebTreeMap::set_safety_log_limit`] keeps only the last few.
+//!
+//! Values don't have to implement `PartialEq`, so the shadow keeps their
+//! `Debug` output and compares that.
+
+use core::fmt::{self, Debug, Write};
+use core::hash::Hash;
+use core::mem;
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::String;
+
+use crate::{VebKey, VebTreeMap};
+
+/// The entries that a tree should have, and the operations that changed them.
+#[derive(Debug, Clone)]
+pub(crate) struct Shadow<K> {
+    /// The entries, with the `Debug` output of their values.
+    entries: BTreeMap<K, String>,
+    /// The operations that a failure reports, oldest first.
+    log: VecDeque<String>,
+    /// The most operations that the log keeps, or `None` for all of them.
+    log_limit: Option<usize>,
+    /// The number of operations since the tree was created.
+    ops: u64,
+    /// Whether values were handed out to be changed since the last check, so
+    /// the shadow's values have to be taken from the tree.
+    lent: bool,
+    /// Whether a bulk change is in progress, which checks the whole tree
+    /// once at the end.
+    batch: bool,
+}
+
+impl<K> Shadow<K> {
+    pub(crate) fn new() -> Shadow<K> {
+        Shadow {
+            entries: BTreeMap::new(),
+            log: VecDeque::new(),
+            log_limit: None,
+            ops: 0,
+            lent: false,
+            batch: false,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.record(String::from("clear()"));
+        self.entries.clear();
+        self.lent = false;
+    }
+
+    /// Note that values were handed out to be changed, like by a
+    /// [`CursorMut`](crate::CursorMut).
+    pub(crate) fn lend(&mut self) {
+        self.lent = true;
+    }
+
+    /// Start a bulk change, like building a tree from sorted entries, after
+    /// which [`VebTreeMap::shadow_end_batch`] checks the whole tree.  Each
+    /// change is still checked on its own.
+    pub(crate) fn begin_batch(&mut self) {
+        self.batch = true;
+    }
+
+    fn record(&mut self, op: String) {
+        self.log.push_back(op);
+        self.ops += 1;
+        self.trim_log();
+    }
+
+    fn trim_log(&mut self) {
+        if let Some(limit) = self.log_limit {
+            let excess = self.log.len().saturating_sub(limit);
+            self.log.drain(..excess);
+        }
+    }
+}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey,
+{
+    /// Keep only the last `limit` operations in the log that a failed safety
+    /// check reports, or all of them with `None`, which is the default.
+    pub fn set_safety_log_limit(&mut self, limit: Option<usize>) {
+        self.shadow.log_limit = limit;
+        self.shadow.trim_log();
+    }
+}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Check an insert of `value` by `op`, which replaced `old_value`.
+    pub(crate) fn shadow_insert(
+        &mut self,
+        op: &str,
+        key: &K,
+        value: &V,
+        old_value: Option<&V>,
+    ) {
+        let value = format!("{value:?}");
+        self.shadow.record(format!("{op}({key:?}, {value})"));
+        let expected = self.shadow.entries.insert(key.clone(), value);
+        if !self.same_value(old_value, expected.as_ref()) {
+            self.diverged("insert returned the wrong value");
+        }
+        if !self.shadow.batch {
+            self.check_shadow();
+        }
+    }
+
+    /// Check a remove by `op`, which removed `value`.
+    pub(crate) fn shadow_remove(
+        &mut self,
+        op: &str,
+        key: &K,
+        value: Option<&V>,
+    ) {
+        self.shadow.record(format!("{op}({key:?})"));
+        let expected = self.shadow.entries.remove(key);
+        if !self.same_value(value, expected.as_ref()) {
+            self.diverged("remove returned the wrong value");
+        }
+        if !self.shadow.batch {
+            self.check_shadow();
+        }
+    }
+
+    /// Replace the shadow's entries with the tree's after building it all at
+    /// once by `op`, when there's nothing to compare it to.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn shadow_build(&mut self, op: &str) {
+        self.shadow.record(format!("{op}({} entries)", self.len));
+        self.shadow.entries = self
+            .entries()
+            .map(|(key, value)| (key, format!("{value:?}")))
+            .collect();
+        self.shadow.lent = false;
+        self.check_shadow();
+    }
+
+    /// Finish a bulk change, and check the whole tree.
+    pub(crate) fn shadow_end_batch(&mut self) {
+        self.shadow.batch = false;
+        self.check_shadow();
+    }
+
+    /// Whether a value that an operation returned is the shadow's.  After
+    /// values were lent out, the shadow's may be out of date, so only their
+    /// presence is compared.
+    fn same_value(&self, value: Option<&V>, expected: Option<&String>) -> bool {
+        match (value, expected) {
+            (Some(value), Some(expected)) => {
+                self.shadow.lent || debug_eq(value, expected)
+            }
+            (value, expected) => value.is_none() && expected.is_none(),
+        }
+    }
+
+    /// Validate the whole tree and compare every entry to the shadow's.
+    fn check_shadow(&mut self) {
+        if let Err(violation) = self.validate() {
+            self.diverged(&format!("an invariant is broken: {violation}"));
+        }
+        let mut entries = mem::take(&mut self.shadow.entries);
+        let lent = mem::replace(&mut self.shadow.lent, false);
+        let mismatch = self.compare_entries(&mut entries, lent);
+        self.shadow.entries = entries;
+        if let Some(reason) = mismatch {
+            self.diverged(reason);
+        }
+    }
+
+    /// Compare the tree's entries to the shadow's in order.  When values were
+    /// lent out, the shadow takes the tree's values instead of comparing
+    /// them.
+    fn compare_entries(
+        &self,
+        entries: &mut BTreeMap<K, String>,
+        lent: bool,
+    ) -> Option<&'static str> {
+        if self.len != entries.len() {
+            return Some("the length doesn't match");
+        }
+        for ((key, value), (k, expected)) in self.entries().zip(entries) {
+            if key != *k {
+                return Some("the keys don't match");
+            }
+            if lent {
+                *expected = format!("{value:?}");
+            } else if !debug_eq(value, expected) {
+                return Some("a value doesn't match");
+            }
+        }
+        None
+    }
+
+    fn diverged(&self, reason: &str) {
+        let Shadow { log, ops, .. } = &self.shadow;
+        if u64::try_from(log.len()) == Ok(*ops) {
+            panic!(
+                "tree diverged from a BTreeMap of its entries: {reason}; \
+                 after the operations {log:?}"
+            );
+        }
+        panic!(
+            "tree diverged from a BTreeMap of its entries: {reason}; \
+             after {ops} operations, the last {} were {log:?}",
+            log.len()
+        );
+    }
+}
+
+/// Whether a value's `Debug` output is the expected string, without
+/// allocating.
+fn debug_eq<V: Debug>(value: &V, expected: &str) -> bool {
+    let mut rest = DebugEq(expected);
+    write!(rest, "{value:?}").is_ok() && rest.0.is_empty()
+}
+
+/// A writer that checks that what's written is a prefix of the rest of a
+/// string.
+struct DebugEq<'a>(&'a str);
+
+impl Write for DebugEq<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 = self.0.strip_prefix(s).ok_or(fmt::Error)?;
+        Ok(())
+    }
+}
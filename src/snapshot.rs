@@ -0,0 +1,471 @@
+//! A compact binary format for saving and loading trees.
+//!
+//! A snapshot is laid out as:
+//!
+//! - The magic bytes `VEBT`.
+//! - The format version, as a little-endian u16.
+//! - The number of bits in the key type, then in the tree's universe, as a
+//!   byte each.
+//! - The number of entries, as a little-endian u64.
+//! - Each entry in key order: the difference from the previous key (or from
+//!   zero for the first), the length of the encoded value, and the encoded
+//!   value.  The difference and the length are LEB128 varints.
+//! - The CRC-32 of everything before it, as a little-endian u32.
+//!
+//! Since the keys are sorted, loading builds the tree the same way as
+//! [`VebTreeMap::from_sorted_iter`] instead of inserting from the root.
+//! Snapshots are read and written with `std::io`, so they need the `std`
+//! feature.
+//!
+//! [`VebTreeMap::from_sorted_iter`]: crate::VebTreeMap::from_sorted_iter
+
+use core::error::Error;
+use core::fmt;
+use core::fmt::Debug;
+use core::hash::Hash;
+#[cfg(feature = "std")]
+use core::ops::Bound;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::VebKey;
+#[cfg(feature = "std")]
+use crate::{Finger, VebTreeMap};
+
+#[cfg(feature = "std")]
+const MAGIC: [u8; 4] = *b"VEBT";
+#[cfg(feature = "std")]
+const VERSION: u16 = 1;
+
+/// A key type that can be written to a snapshot.
+pub trait SnapshotKey:
+    VebKey<Size = u8> + Ord + Clone + Hash + Eq + Debug
+{
+    /// Widen the key.
+    fn to_u128(&self) -> u128;
+    /// Narrow a key, or `None` if it doesn't fit.
+    fn from_u128(key: u128) -> Option<Self>;
+}
+
+macro_rules! impl_snapshot_key {
+    ($typ: ty) => {
+        impl SnapshotKey for $typ {
+            fn to_u128(&self) -> u128 {
+                u128::try_from(*self).expect("key must fit in u128")
+            }
+
+            fn from_u128(key: u128) -> Option<Self> {
+                Self::try_from(key).ok()
+            }
+        }
+    };
+}
+
+impl_snapshot_key!(u8);
+impl_snapshot_key!(u16);
+impl_snapshot_key!(u32);
+impl_snapshot_key!(u64);
+impl_snapshot_key!(u128);
+impl_snapshot_key!(usize);
+
+/// Converts values to and from the bytes stored in a snapshot.
+pub trait ValueCodec<V> {
+    /// Append the encoding of a value.
+    fn encode(&self, value: &V, out: &mut Vec<u8>);
+    /// Decode a value from exactly the bytes that [`ValueCodec::encode`]
+    /// appended.
+    fn decode(&self, bytes: &[u8]) -> Result<V, Box<dyn Error + Send + Sync>>;
+}
+
+/// Encodes unsigned integers as their little-endian bytes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LittleEndianCodec;
+
+macro_rules! impl_little_endian_codec {
+    ($typ: ty) => {
+        impl ValueCodec<$typ> for LittleEndianCodec {
+            fn encode(&self, value: &$typ, out: &mut Vec<u8>) {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+
+            fn decode(
+                &self,
+                bytes: &[u8],
+            ) -> Result<$typ, Box<dyn Error + Send + Sync>> {
+                Ok(<$typ>::from_le_bytes(bytes.try_into()?))
+            }
+        }
+    };
+}
+
+impl_little_endian_codec!(u8);
+impl_little_endian_codec!(u16);
+impl_little_endian_codec!(u32);
+impl_little_endian_codec!(u64);
+impl_little_endian_codec!(u128);
+
+/// Encodes `()` as no bytes, for trees used as sets.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnitCodec;
+
+impl ValueCodec<()> for UnitCodec {
+    fn encode(&self, _value: &(), _out: &mut Vec<u8>) {}
+
+    fn decode(&self, bytes: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if bytes.is_empty() {
+            Ok(())
+        } else {
+            Err("unit value must be empty".into())
+        }
+    }
+}
+
+/// The ways that reading a snapshot can fail.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    /// The reader failed.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// The data doesn't start with the magic bytes, so it isn't a snapshot.
+    BadMagic,
+    /// The snapshot was written by an unknown version of the format.
+    UnsupportedVersion(u16),
+    /// The snapshot's keys are a different width than the tree's.
+    KeyWidth { expected: u8, found: u8 },
+    /// The snapshot's values are a different size than the tree's.
+    ValueWidth { expected: usize, found: usize },
+    /// The snapshot's universe isn't valid for the key type.
+    Universe(u8),
+    /// The data ended before the snapshot did.
+    Truncated,
+    /// The checksum doesn't match the data.
+    Checksum { expected: u32, found: u32 },
+    /// An entry is invalid, so the data was corrupted.
+    Corrupt(&'static str),
+    /// The codec failed to decode a value.
+    Value(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            SnapshotError::Io(err) => {
+                write!(f, "failed to read snapshot: {err}")
+            }
+            SnapshotError::BadMagic => f.write_str("not a snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            SnapshotError::KeyWidth { expected, found } => write!(
+                f,
+                "snapshot has {found} bit keys, but the tree has {expected} bit keys"
+            ),
+            SnapshotError::ValueWidth { expected, found } => write!(
+                f,
+                "snapshot has {found} byte values, but the tree has {expected} byte values"
+            ),
+            SnapshotError::Universe(bits) => {
+                write!(f, "invalid universe of {bits} bits")
+            }
+            SnapshotError::Truncated => f.write_str("snapshot is truncated"),
+            SnapshotError::Checksum { expected, found } => write!(
+                f,
+                "snapshot checksum mismatch: expected {expected:#010x}, found {found:#010x}"
+            ),
+            SnapshotError::Corrupt(reason) => {
+                write!(f, "snapshot is corrupt: {reason}")
+            }
+            SnapshotError::Value(err) => {
+                write!(f, "failed to decode value: {err}")
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            SnapshotError::Io(err) => Some(err),
+            SnapshotError::Value(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            SnapshotError::Truncated
+        } else {
+            SnapshotError::Io(err)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> VebTreeMap<K, V>
+where
+    K: SnapshotKey,
+    V: Clone + Debug,
+{
+    /// Write a snapshot of the tree, encoding values with the codec.  Runs in
+    /// O(n lg lg u) time.
+    ///
+    /// Writes are small, so use a buffered writer.
+    pub fn write_to<W, C>(&self, writer: W, codec: &C) -> io::Result<()>
+    where
+        W: Write,
+        C: ValueCodec<V>,
+    {
+        let mut writer = Checksummed::new(writer);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[K::max_size(), self.max_size])?;
+        let len = u64::try_from(self.len()).expect("length must fit in u64");
+        writer.write_all(&len.to_le_bytes())?;
+
+        let mut previous = 0;
+        let mut value_bytes = Vec::new();
+        let mut cursor = self.lower_bound(Bound::Unbounded);
+        while let Some((key, value)) = cursor.move_next() {
+            let key = key.to_u128();
+            write_varint(&mut writer, key - previous)?;
+            previous = key;
+
+            value_bytes.clear();
+            codec.encode(value, &mut value_bytes);
+            let value_len = u128::try_from(value_bytes.len())
+                .expect("value length must fit in u128");
+            write_varint(&mut writer, value_len)?;
+            writer.write_all(&value_bytes)?;
+        }
+
+        let crc = writer.crc.finish();
+        writer.inner.write_all(&crc.to_le_bytes())?;
+        writer.inner.flush()
+    }
+
+    /// Read a snapshot written by [`VebTreeMap::write_to`], decoding values
+    /// with the codec.  Runs in O(n lg lg u) time, but faster than inserting
+    /// the entries one at a time.
+    ///
+    /// Reads are small, so use a buffered reader.
+    pub fn read_from<R, C>(reader: R, codec: &C) -> Result<Self, SnapshotError>
+    where
+        R: Read,
+        C: ValueCodec<V>,
+    {
+        let mut reader = Checksummed::new(reader);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let mut sizes = [0; 2];
+        reader.read_exact(&mut sizes)?;
+        let [key_bits, universe_bits] = sizes;
+        if key_bits != K::max_size() {
+            return Err(SnapshotError::KeyWidth {
+                expected: K::max_size(),
+                found: key_bits,
+            });
+        }
+        if !universe_bits.is_power_of_two() || universe_bits > key_bits {
+            return Err(SnapshotError::Universe(universe_bits));
+        }
+        let mut len = [0; 8];
+        reader.read_exact(&mut len)?;
+        let len = u64::from_le_bytes(len);
+
+        let mut map = Self::with_universe_bits(universe_bits);
+        let mut finger = Finger::new();
+        #[cfg(feature = "safety_checks")]
+        map.shadow.begin_batch();
+        let mut previous: Option<u128> = None;
+        let mut value_bytes = Vec::new();
+        for _ in 0..len {
+            let delta = read_varint(&mut reader)?;
+            let key = match previous {
+                None => delta,
+                Some(_) if delta == 0 => {
+                    return Err(SnapshotError::Corrupt(
+                        "keys must be strictly increasing",
+                    ));
+                }
+                Some(previous) => previous
+                    .checked_add(delta)
+                    .ok_or(SnapshotError::Corrupt("key overflows u128"))?,
+            };
+            previous = Some(key);
+            let key =
+                K::from_u128(key).filter(|key| map.in_universe(key)).ok_or(
+                    SnapshotError::Corrupt("key must fit in the universe"),
+                )?;
+
+            let value_len = u64::try_from(read_varint(&mut reader)?)
+                .map_err(|_| SnapshotError::Corrupt("value is too long"))?;
+            value_bytes.clear();
+            // Read without trusting the length to preallocate.
+            (&mut reader)
+                .take(value_len)
+                .read_to_end(&mut value_bytes)?;
+            if u64::try_from(value_bytes.len()) != Ok(value_len) {
+                return Err(SnapshotError::Truncated);
+            }
+            let value =
+                codec.decode(&value_bytes).map_err(SnapshotError::Value)?;
+            map.insert_near(&mut finger, key, value);
+        }
+
+        let expected = reader.crc.finish();
+        let mut found = [0; 4];
+        reader.inner.read_exact(&mut found)?;
+        let found = u32::from_le_bytes(found);
+        if found != expected {
+            return Err(SnapshotError::Checksum { expected, found });
+        }
+        #[cfg(feature = "safety_checks")]
+        map.shadow_end_batch();
+        Ok(map)
+    }
+}
+
+/// Write an unsigned LEB128 varint.
+#[cfg(feature = "std")]
+fn write_varint(writer: &mut impl Write, mut n: u128) -> io::Result<()> {
+    let mut bytes = [0; 19];
+    let mut len = 0;
+    loop {
+        let byte = u8::try_from(n & 0x7f).expect("masked to 7 bits");
+        n >>= 7;
+        if n == 0 {
+            bytes[len] = byte;
+            len += 1;
+            break;
+        }
+        bytes[len] = byte | 0x80;
+        len += 1;
+    }
+    writer.write_all(&bytes[..len])
+}
+
+/// Read an unsigned LEB128 varint.
+#[cfg(feature = "std")]
+fn read_varint(reader: &mut impl Read) -> Result<u128, SnapshotError> {
+    let mut n = 0u128;
+    for shift in (0..128).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        let [byte] = byte;
+        let bits = u128::from(byte & 0x7f);
+        if bits.checked_shl(shift).and_then(|b| b.checked_shr(shift))
+            != Some(bits)
+        {
+            return Err(SnapshotError::Corrupt("varint overflows u128"));
+        }
+        n |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(SnapshotError::Corrupt("varint is too long"))
+}
+
+#[cfg(feature = "std")]
+/// A reader or writer that computes the CRC-32 of the bytes passing through.
+struct Checksummed<T> {
+    inner: T,
+    crc: Crc32,
+}
+
+#[cfg(feature = "std")]
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Self {
+        Checksummed {
+            inner,
+            crc: Crc32::new(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.crc.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "std")]
+/// CRC-32 with the IEEE polynomial, as used by zlib and PNG.
+struct Crc32(u32);
+
+#[cfg(feature = "std")]
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    // The same as `i`, since `as` conversions are avoided.
+    let mut byte: u32 = 0;
+    while i < 256 {
+        let mut crc = byte;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+        byte += 1;
+    }
+    table
+};
+
+#[cfg(feature = "std")]
+impl Crc32 {
+    fn new() -> Self {
+        Crc32(!0)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            let index = (self.0 ^ u32::from(*byte)) & 0xff;
+            self.0 = CRC32_TABLE[usize::try_from(index).expect("u8 fits")]
+                ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
@@ -0,0 +1,73 @@
+//! Statistics about the shape and memory of a tree, for planning capacity.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+
+use alloc::vec::Vec;
+
+use crate::{VebKey, VebTreeMap};
+
+/// Statistics about a tree, from [`VebTreeMap::stats`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct TreeStats {
+    /// The number of nodes at each depth, starting with the root.
+    pub nodes_per_level: Vec<usize>,
+    /// The number of clusters, which are all the nodes but the root.
+    pub clusters: usize,
+    /// The arenas that hold the nodes: first the tree's, then the arena of
+    /// the summaries of the tree's nodes, then the arena of their summaries,
+    /// and so on.
+    pub arenas: Vec<ArenaStats>,
+    /// An estimate of the bytes that the tree allocates on the heap.  It
+    /// counts keys and values by their size, without any heap memory that
+    /// they own.
+    pub heap_bytes: usize,
+}
+
+/// Statistics about one of the arenas of a tree's nodes.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct ArenaStats {
+    /// The number of nodes in use.
+    pub nodes: usize,
+    /// The number of released nodes that are kept to be reused.
+    pub free_nodes: usize,
+    /// The number of nodes that fit without growing.
+    pub node_capacity: usize,
+    /// The number of entries in the nodes' mins and maxes.  In a summary
+    /// arena, this is the total size of the summaries.
+    pub entries: usize,
+    /// The number of clusters in the cluster table.
+    pub cluster_table_len: usize,
+    /// The number of clusters that fit in the cluster table without growing.
+    /// Without the `std` feature, the table is an ordered map, so this is
+    /// its length.
+    pub cluster_table_capacity: usize,
+    /// An estimate of the bytes that the arena allocates on the heap, without
+    /// the arenas below it.
+    pub heap_bytes: usize,
+}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Get statistics about the tree's structure and memory.  Runs in O(n lg
+    /// n) time.
+    pub fn stats(&self) -> TreeStats {
+        let nodes_per_level = match self.root {
+            Some(root) => self.arena.nodes_per_level(root),
+            None => Vec::new(),
+        };
+        let mut arenas = Vec::new();
+        self.arena.stats(&mut arenas);
+        TreeStats {
+            clusters: nodes_per_level.iter().sum::<usize>().saturating_sub(1),
+            nodes_per_level,
+            heap_bytes: arenas.iter().map(|arena| arena.heap_bytes).sum(),
+            arenas,
+        }
+    }
+}
@@ -1,3 +1,9 @@
+use std::ops::Bound;
+#[cfg(not(feature = "std"))]
+use std::prelude::rust_2024::*;
+#[cfg(not(feature = "std"))]
+use std::vec;
+
 use super::*;
 
 #[test]
@@ -122,3 +128,1530 @@ fn remove_after_two_inserts_decreasing_order() {
     t.remove(&1);
     assert_eq!(t.get(&1), None);
 }
+
+#[test]
+fn keys_with_same_cluster_and_low_bits_are_distinct() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    t.insert(0, 0);
+    t.insert(u32::MAX, 0);
+    // These differ only above the lowest bits of the cluster index.
+    t.insert(0x1_0005, 1);
+    assert_eq!(t.get(&0x1_0015), None);
+    assert_eq!(t.successor(&0x1_0005), Some((u32::MAX, 0)));
+}
+
+#[test]
+fn insert_below_single_element_returns_none() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    t.insert(5, 50);
+    assert_eq!(t.insert(3, 30), None);
+    assert_eq!(t.get(&3), Some(30));
+    assert_eq!(t.get(&5), Some(50));
+}
+
+#[test]
+fn clone_is_independent() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    for k in 0..100 {
+        t.insert(k * 1000, k);
+    }
+    let c = t.clone();
+    t.remove(&5000);
+    t.insert(5001, 1);
+    assert_eq!(c.get(&5000), Some(5));
+    assert_eq!(c.get(&5001), None);
+    assert_eq!(t.get(&5000), None);
+}
+
+#[test]
+fn remove_all_releases_nodes() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    for k in 0..1000 {
+        t.insert(k * 7919, k);
+    }
+    for k in 0..1000 {
+        t.remove(&(k * 7919));
+    }
+    assert!(t.is_empty());
+    assert_eq!(t.root, None);
+    // Nodes are reused after clearing.
+    t.insert(1, 10);
+    assert_eq!(t.get(&1), Some(10));
+}
+
+#[test]
+fn every_u8_key() {
+    let mut t = VebTreeMap::<u8, u8>::new();
+    for k in 0..=u8::MAX {
+        assert_eq!(t.insert(k, k), None);
+    }
+    for k in (0..=u8::MAX).step_by(2) {
+        t.remove(&k);
+    }
+    for k in 0..=u8::MAX {
+        let expected = if k % 2 == 1 { Some(k) } else { None };
+        assert_eq!(t.get(&k), expected);
+        let successor = (0..=u8::MAX).find(|s| *s > k && s % 2 == 1);
+        assert_eq!(t.successor(&k).map(|(s, _)| s), successor);
+        let predecessor = (0..k).rev().find(|p| p % 2 == 1);
+        assert_eq!(t.predecessor(&k).map(|(p, _)| p), predecessor);
+    }
+}
+
+fn assert_fixed_cluster_sizes<K: FixedDepthKey>() {
+    let mut universe_size = K::max_size();
+    for cluster_size in K::CLUSTER_SIZES {
+        assert_eq!(K::cluster_size(&universe_size), *cluster_size);
+        universe_size = *cluster_size;
+    }
+    assert_eq!(universe_size, 0);
+}
+
+#[test]
+fn fixed_depth_cluster_sizes() {
+    assert_fixed_cluster_sizes::<u32>();
+    assert_fixed_cluster_sizes::<u64>();
+}
+
+#[test]
+fn insert_fixed_get_fixed() {
+    let mut t = VebTreeMap::<u64, u64>::new();
+    assert_eq!(t.insert_fixed(1, 10), None);
+    assert_eq!(t.insert_fixed(u64::MAX, 20), None);
+    assert_eq!(t.insert_fixed(1 << 40, 30), None);
+    assert_eq!(t.insert_fixed(1 << 40, 40), Some(30));
+    assert_eq!(t.get_fixed(&(1 << 40)), Some(40));
+    assert_eq!(t.get(&(1 << 40)), Some(40));
+    assert_eq!(t.get_fixed(&2), None);
+    assert_eq!(t.successor_fixed(&1), Some((1 << 40, 40)));
+    assert_eq!(t.successor_fixed(&(1 << 40)), Some((u64::MAX, 20)));
+    assert_eq!(t.successor_fixed(&u64::MAX), None);
+}
+
+#[test]
+fn get_many_successor_many() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    assert_eq!(t.get_many(&[1, 2]), vec![None, None]);
+    assert_eq!(t.successor_many(&[1, 2]), vec![None, None]);
+    t.insert(1, 10);
+    t.insert(3, 30);
+    t.insert(1 << 20, 40);
+    t.insert(u32::MAX, 50);
+    assert_eq!(
+        t.get_many(&[3, 0, 1 << 20, 2, u32::MAX]),
+        vec![Some(30), None, Some(40), None, Some(50)]
+    );
+    assert_eq!(
+        t.successor_many(&[3, 0, 1 << 20, u32::MAX]),
+        vec![
+            Some((1 << 20, 40)),
+            Some((1, 10)),
+            Some((u32::MAX, 50)),
+            None
+        ]
+    );
+}
+
+#[test]
+fn finger_operations() {
+    let mut t = VebTreeMap::<u64, u64>::new();
+    let mut finger = Finger::new();
+    for k in (0..1000).map(|k| k * 1000 + (1 << 40)) {
+        assert_eq!(t.insert_near(&mut finger, k, k), None);
+    }
+    assert_eq!(t.insert_near(&mut finger, 1 << 40, 1), Some(1 << 40));
+    assert_eq!(t.get_near(&mut finger, &(1 << 40)), Some(1));
+    assert_eq!(
+        t.get_near(&mut finger, &((1 << 40) + 1000)),
+        Some((1 << 40) + 1000)
+    );
+    assert_eq!(t.get_near(&mut finger, &((1 << 40) + 1001)), None);
+    assert_eq!(
+        t.successor_near(&mut finger, &((1 << 40) + 1001)),
+        Some(((1 << 40) + 2000, (1 << 40) + 2000))
+    );
+    assert_eq!(
+        t.predecessor_near(&mut finger, &((1 << 40) + 1001)),
+        Some(((1 << 40) + 1000, (1 << 40) + 1000))
+    );
+
+    // Removing releases nodes that the finger may remember.
+    for k in (0..1000).map(|k| k * 1000 + (1 << 40)).skip(1) {
+        t.remove(&k);
+    }
+    assert_eq!(t.get_near(&mut finger, &((1 << 40) + 1000)), None);
+    assert_eq!(t.successor_near(&mut finger, &(1 << 40)), None);
+}
+
+#[test]
+fn finger_with_other_tree() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    let mut finger = Finger::new();
+    for k in 0..100 {
+        t.insert_near(&mut finger, k << 8, k);
+    }
+    let mut c = t.clone();
+    // The clone can allocate different nodes than the original.
+    c.insert(99 << 8 | 1, 0);
+    t.insert(99 << 8 | 2, 0);
+    assert_eq!(c.get_near(&mut finger, &(99 << 8 | 1)), Some(0));
+    assert_eq!(t.get_near(&mut finger, &(99 << 8 | 1)), None);
+    assert_eq!(t.get_near(&mut finger, &(99 << 8 | 2)), Some(0));
+    assert_eq!(c.get_near(&mut finger, &(99 << 8 | 2)), None);
+}
+
+#[test]
+fn cursor_moves() {
+    let mut t = VebTreeMap::<u16, u16>::new();
+    for k in [3, 10, 500, 40000] {
+        t.insert(k, k + 1);
+    }
+
+    let mut c = t.lower_bound(Bound::Included(&10));
+    assert_eq!(c.peek_prev(), Some((3, &4)));
+    assert_eq!(c.peek_next(), Some((10, &11)));
+    assert_eq!(c.move_next(), Some((10, &11)));
+    assert_eq!(c.move_next(), Some((500, &501)));
+    assert_eq!(c.move_next(), Some((40000, &40001)));
+    assert_eq!(c.peek_next(), None);
+    // There's no wrapping around at the ends.
+    assert_eq!(c.move_next(), None);
+    assert_eq!(c.peek_prev(), Some((40000, &40001)));
+    assert_eq!(c.move_prev(), Some((40000, &40001)));
+    assert_eq!(c.move_prev(), Some((500, &501)));
+    assert_eq!(c.move_prev(), Some((10, &11)));
+    assert_eq!(c.move_prev(), Some((3, &4)));
+    assert_eq!(c.move_prev(), None);
+    assert_eq!(c.peek_next(), Some((3, &4)));
+
+    let gap = |c: Cursor<'_, u16, u16>| {
+        (c.peek_prev().map(|(k, _)| k), c.peek_next().map(|(k, _)| k))
+    };
+    assert_eq!(
+        gap(t.lower_bound(Bound::Included(&11))),
+        (Some(10), Some(500))
+    );
+    assert_eq!(
+        gap(t.lower_bound(Bound::Excluded(&10))),
+        (Some(10), Some(500))
+    );
+    assert_eq!(gap(t.lower_bound(Bound::Unbounded)), (None, Some(3)));
+    assert_eq!(
+        gap(t.lower_bound(Bound::Excluded(&40000))),
+        (Some(40000), None)
+    );
+    assert_eq!(
+        gap(t.upper_bound(Bound::Included(&10))),
+        (Some(10), Some(500))
+    );
+    assert_eq!(
+        gap(t.upper_bound(Bound::Included(&499))),
+        (Some(10), Some(500))
+    );
+    assert_eq!(
+        gap(t.upper_bound(Bound::Excluded(&10))),
+        (Some(3), Some(10))
+    );
+    assert_eq!(gap(t.upper_bound(Bound::Unbounded)), (Some(40000), None));
+    assert_eq!(gap(t.upper_bound(Bound::Excluded(&3))), (None, Some(3)));
+
+    let empty = VebTreeMap::<u16, u16>::new();
+    assert_eq!(gap(empty.lower_bound(Bound::Included(&10))), (None, None));
+}
+
+#[test]
+fn cursor_mut_changes_tree() {
+    let mut t = VebTreeMap::<u16, u16>::new();
+    for k in [3, 10, 500] {
+        t.insert(k, k);
+    }
+
+    let mut c = t.lower_bound_mut(Bound::Included(&10));
+    *c.peek_next().unwrap().1 = 11;
+    c.insert_before(8, 8);
+    assert_eq!(c.peek_prev(), Some((8, &mut 8)));
+    c.insert_after(9, 9);
+    assert_eq!(c.peek_next(), Some((9, &mut 9)));
+    assert_eq!(c.remove_next(), Some((9, 9)));
+    assert_eq!(c.as_cursor().peek_next(), Some((10, &11)));
+    assert_eq!(c.remove_prev(), Some((8, 8)));
+    assert_eq!(c.as_cursor().peek_prev(), Some((3, &3)));
+    if let Some((_, v)) = c.move_next() {
+        *v += 1;
+    }
+    assert_eq!(c.remove_next(), Some((500, 500)));
+    assert_eq!(c.remove_next(), None);
+    assert_eq!(c.move_prev(), Some((10, &mut 12)));
+
+    // At the ends, entries go at the front or the back.
+    let mut c = t.upper_bound_mut(Bound::Excluded(&3));
+    assert_eq!(c.remove_prev(), None);
+    c.insert_after(0, 0);
+    assert_eq!(c.move_next(), Some((0, &mut 0)));
+    let mut c = t.upper_bound_mut(Bound::Unbounded);
+    c.insert_before(1000, 1000);
+    assert_eq!(c.peek_next(), None);
+    assert_eq!(VebTreeMap::min(&t), Some((0, 0)));
+    assert_eq!(VebTreeMap::max(&t), Some((1000, 1000)));
+}
+
+#[test]
+#[should_panic(expected = "must be in the gap")]
+fn cursor_mut_insert_outside_gap() {
+    let mut t = VebTreeMap::<u16, u16>::new();
+    t.insert(3, 3);
+    t.insert(10, 10);
+    t.lower_bound_mut(Bound::Unbounded).insert_after(11, 11);
+}
+
+#[test]
+fn floor_ceiling_nearest() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    assert_eq!(t.floor(&5), None);
+    assert_eq!(t.nearest(&5, Tie::Lower), None);
+    for k in [10, 20, 1 << 20, u32::MAX] {
+        t.insert(k, k / 2);
+    }
+
+    assert_eq!(t.ceiling(&10), Some((10, 5)));
+    assert_eq!(t.ceiling(&11), Some((20, 10)));
+    assert_eq!(t.ceiling(&0), Some((10, 5)));
+    assert_eq!(t.ceiling(&u32::MAX), Some((u32::MAX, u32::MAX / 2)));
+    assert_eq!(t.floor(&20), Some((20, 10)));
+    assert_eq!(t.floor(&19), Some((10, 5)));
+    assert_eq!(t.floor(&9), None);
+    assert_eq!(t.floor(&(u32::MAX - 1)), Some((1 << 20, 1 << 19)));
+
+    assert_eq!(t.nearest(&15, Tie::Lower), Some((10, 5)));
+    assert_eq!(t.nearest(&15, Tie::Higher), Some((20, 10)));
+    assert_eq!(t.nearest(&16, Tie::Lower), Some((20, 10)));
+    assert_eq!(t.nearest(&20, Tie::Higher), Some((20, 10)));
+    assert_eq!(t.nearest(&0, Tie::Lower), Some((10, 5)));
+    assert_eq!(
+        t.nearest(&(u32::MAX - 1), Tie::Lower),
+        Some((u32::MAX, u32::MAX / 2))
+    );
+}
+
+#[test]
+fn wrapping_operations() {
+    let mut t = VebTreeMap::<u8, u8>::new();
+    assert_eq!(t.successor_wrapping(&5), None);
+    assert_eq!(t.range_wrapping(10, 5).next(), None);
+    t.insert(7, 0);
+    assert_eq!(t.successor_wrapping(&7), Some((7, 0)));
+    assert_eq!(t.predecessor_wrapping(&7), Some((7, 0)));
+    for k in [2, 100, 250] {
+        t.insert(k, k);
+    }
+
+    assert_eq!(t.successor_wrapping(&7), Some((100, 100)));
+    assert_eq!(t.successor_wrapping(&250), Some((2, 2)));
+    assert_eq!(t.predecessor_wrapping(&2), Some((250, 250)));
+    assert_eq!(t.predecessor_wrapping(&0), Some((250, 250)));
+
+    let keys = |start, end| {
+        t.range_wrapping(start, end)
+            .map(|(k, _)| k)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(keys(2, 250), vec![2, 7, 100]);
+    assert_eq!(keys(100, 7), vec![100, 250, 2]);
+    assert_eq!(keys(251, 8), vec![2, 7]);
+    assert_eq!(keys(100, 100), Vec::<u8>::new());
+    assert_eq!(keys(0, 255), vec![2, 7, 100, 250]);
+    assert_eq!(keys(101, 100), vec![250, 2, 7]);
+}
+
+#[test]
+fn len_counts_entries() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    let mut finger = Finger::new();
+    assert_eq!(t.len(), 0);
+    t.insert(1, 1);
+    t.insert(1, 2);
+    t.insert_near(&mut finger, 2, 2);
+    t.insert_fixed(3, 3);
+    assert_eq!(t.len(), 3);
+    t.remove(&4);
+    t.remove(&1);
+    assert_eq!(t.len(), 2);
+    t.clear();
+    assert_eq!(t.len(), 0);
+}
+
+#[test]
+fn from_sorted_iter() {
+    let t = VebTreeMap::from_sorted_iter((0..1000u32).map(|k| (k * 7, k)));
+    assert_eq!(t.len(), 1000);
+    assert_eq!(t.get(&70), Some(10));
+    assert_eq!(t.successor(&70), Some((77, 11)));
+    assert_eq!(VebTreeMap::max(&t), Some((6993, 999)));
+}
+
+#[test]
+#[should_panic(expected = "keys must be strictly increasing")]
+fn from_sorted_iter_unsorted() {
+    VebTreeMap::from_sorted_iter([(1u8, ()), (1, ())]);
+}
+
+#[test]
+fn universe_bits() {
+    let mut t = VebTreeMap::<u64, u64>::with_universe_bits(16);
+    assert_eq!(t.universe_bits(), 16);
+    for k in (0..1 << 16).step_by(7) {
+        t.insert_fixed(k, k);
+    }
+    assert_eq!(t.get_fixed(&700), Some(700));
+    assert_eq!(t.get(&701), None);
+    assert_eq!(t.successor_fixed(&700), Some((707, 707)));
+    assert_eq!(t.successor(&65534), None);
+    assert_eq!(t.predecessor(&65535), Some((65534, 65534)));
+}
+
+#[test]
+fn fixed_keys_outside_universe() {
+    let mut t = VebTreeMap::<u32, u32>::with_universe_bits(8);
+    for k in [3, 100, 255] {
+        t.insert_fixed(k, k);
+    }
+    assert_eq!(t.get_fixed(&300), t.get(&300));
+    assert_eq!(t.get_fixed(&300), None);
+    assert_eq!(t.successor_fixed(&300), t.successor(&300));
+    assert_eq!(t.successor_fixed(&300), None);
+}
+
+/// A small tree whose universe ends at 255, for checking that queries for
+/// bigger keys don't look them up in the wrong cluster.
+fn small_universe() -> VebTreeMap<u32, u32> {
+    let mut t = VebTreeMap::with_universe_bits(8);
+    for k in [3, 100, 255] {
+        t.insert(k, k);
+    }
+    t
+}
+
+#[test]
+fn batch_keys_outside_universe() {
+    let t = small_universe();
+    assert_eq!(t.get_many(&[300, 100, 256]), vec![None, Some(100), None]);
+    assert_eq!(
+        t.successor_many(&[300, 99, 256]),
+        vec![None, Some((100, 100)), None]
+    );
+}
+
+#[test]
+fn finger_keys_outside_universe() {
+    let t = small_universe();
+    let mut finger = Finger::new();
+    assert_eq!(t.get_near(&mut finger, &300), None);
+    assert_eq!(t.successor_near(&mut finger, &300), None);
+    assert_eq!(t.predecessor_near(&mut finger, &300), Some((255, 255)));
+    assert_eq!(t.get_near(&mut finger, &100), Some(100));
+}
+
+#[test]
+fn cursor_bounds_outside_universe() {
+    let t = small_universe();
+    let gap = |c: Cursor<'_, u32, u32>| {
+        (c.peek_prev().map(|(k, _)| k), c.peek_next().map(|(k, _)| k))
+    };
+    assert_eq!(gap(t.lower_bound(Bound::Included(&300))), (Some(255), None));
+    assert_eq!(gap(t.lower_bound(Bound::Excluded(&300))), (Some(255), None));
+    assert_eq!(gap(t.upper_bound(Bound::Included(&300))), (Some(255), None));
+    assert_eq!(gap(t.upper_bound(Bound::Excluded(&300))), (Some(255), None));
+}
+
+#[test]
+#[should_panic(expected = "key must fit in the tree's universe")]
+fn cursor_insert_outside_universe() {
+    let mut t = small_universe();
+    t.upper_bound_mut(Bound::Unbounded).insert_before(256, 256);
+}
+
+#[test]
+fn range_wrapping_outside_universe() {
+    let t = small_universe();
+    let keys = |start, end| -> Vec<u32> {
+        t.range_wrapping(start, end).map(|(k, _)| k).collect()
+    };
+    assert_eq!(keys(300, 101), vec![3, 100]);
+    assert_eq!(keys(300, 1000), Vec::<u32>::new());
+    assert_eq!(keys(50, 300), vec![100, 255]);
+}
+
+#[test]
+#[should_panic(expected = "key must fit in the tree's universe")]
+fn universe_bits_rejects_large_keys() {
+    VebTreeMap::<u32, ()>::with_universe_bits(8).insert(256, ());
+}
+
+#[test]
+#[should_panic(expected = "universe must be a power of two bits")]
+fn universe_bits_must_be_power_of_two() {
+    VebTreeMap::<u32, ()>::with_universe_bits(20);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    let t =
+        VebTreeMap::from_sorted_iter([(3u32, 'a'), (10, 'b'), (1 << 20, 'c')]);
+    let json = serde_json::to_string(&t).unwrap();
+    assert_eq!(json, r#"[[3,"a"],[10,"b"],[1048576,"c"]]"#);
+    let u: VebTreeMap<u32, char> = serde_json::from_str(&json).unwrap();
+    assert_eq!(u.len(), 3);
+    assert_eq!(u.get(&10), Some('b'));
+    assert_eq!(u.successor(&10), Some((1 << 20, 'c')));
+
+    let err =
+        serde_json::from_str::<VebTreeMap<u32, char>>(r#"[[3,"a"],[3,"b"]]"#);
+    assert!(err.unwrap_err().to_string().contains("strictly increasing"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_rejects_keys_outside_universe() {
+    use ::serde::de::DeserializeSeed;
+
+    let mut de = serde_json::Deserializer::from_str("[[3,0],[255,1]]");
+    let t = UniverseSeed::<u32, u8>::new(8)
+        .deserialize(&mut de)
+        .unwrap();
+    assert_eq!(t.universe_bits(), 8);
+    assert_eq!(VebTreeMap::max(&t), Some((255, 1)));
+
+    let mut de = serde_json::Deserializer::from_str("[[3,0],[256,1]]");
+    let err = UniverseSeed::<u32, u8>::new(8).deserialize(&mut de);
+    assert!(
+        err.unwrap_err()
+            .to_string()
+            .contains("doesn't fit in a universe")
+    );
+}
+
+#[cfg(feature = "std")]
+fn snapshot(t: &VebTreeMap<u32, u64>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    t.write_to(&mut bytes, &LittleEndianCodec).unwrap();
+    bytes
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn snapshot_round_trip() {
+    let t = VebTreeMap::from_sorted_iter(
+        [0, 1, 2, 1000, 1 << 20, u32::MAX].map(|k| (k, u64::from(k) * 3)),
+    );
+    let bytes = snapshot(&t);
+    let u =
+        VebTreeMap::<u32, u64>::read_from(bytes.as_slice(), &LittleEndianCodec)
+            .unwrap();
+    assert_eq!(u.len(), t.len());
+    let mut next = VebTreeMap::min(&u);
+    while let Some((k, v)) = next {
+        assert_eq!(t.get(&k), Some(v));
+        next = u.successor(&k);
+    }
+
+    let empty = VebTreeMap::<u32, u64>::new();
+    let u = VebTreeMap::<u32, u64>::read_from(
+        snapshot(&empty).as_slice(),
+        &LittleEndianCodec,
+    )
+    .unwrap();
+    assert!(u.is_empty());
+
+    let mut set = VebTreeMap::<u16, ()>::with_universe_bits(8);
+    set.insert(200, ());
+    let mut bytes = Vec::new();
+    set.write_to(&mut bytes, &UnitCodec).unwrap();
+    let u =
+        VebTreeMap::<u16, ()>::read_from(bytes.as_slice(), &UnitCodec).unwrap();
+    assert_eq!(u.universe_bits(), 8);
+    assert_eq!(VebTreeMap::min(&u), Some((200, ())));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn snapshot_detects_damage() {
+    let t = VebTreeMap::from_sorted_iter((0..100u32).map(|k| (k * k, 7)));
+    let bytes = snapshot(&t);
+    let read = |bytes: &[u8]| {
+        VebTreeMap::<u32, u64>::read_from(bytes, &LittleEndianCodec)
+    };
+
+    for len in 0..bytes.len() {
+        assert!(
+            matches!(read(&bytes[..len]), Err(SnapshotError::Truncated)),
+            "len={len}"
+        );
+    }
+    for i in 8..bytes.len() {
+        let mut damaged = bytes.clone();
+        damaged[i] ^= 0x10;
+        assert!(read(&damaged).is_err(), "i={i}");
+    }
+    let mut damaged = bytes.clone();
+    *damaged.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        read(&damaged),
+        Err(SnapshotError::Checksum { .. })
+    ));
+    assert!(matches!(read(b"nope"), Err(SnapshotError::BadMagic)));
+    assert!(matches!(
+        VebTreeMap::<u64, u64>::read_from(bytes.as_slice(), &LittleEndianCodec),
+        Err(SnapshotError::KeyWidth {
+            expected: 64,
+            found: 32
+        })
+    ));
+}
+
+#[test]
+fn frozen_matches_map() {
+    let mut t = VebTreeMap::<u32, u64>::new();
+    for k in (0..2000u32).map(|k| k.wrapping_mul(2_654_435_761)) {
+        t.insert(k, u64::from(k) + 1);
+    }
+    let frozen = FrozenVebTree::from_map(&t);
+    assert_eq!(frozen.len(), t.len());
+    assert_eq!(frozen.min(), VebTreeMap::min(&t));
+    assert_eq!(frozen.max(), VebTreeMap::max(&t));
+    let borrowed =
+        FrozenVebTree::<u32, u64, &[u8]>::from_bytes(frozen.as_bytes())
+            .unwrap();
+    for k in (0..4000u32).map(|k| k.wrapping_mul(1_327_217_885)) {
+        assert_eq!(borrowed.get(&k), t.get(&k));
+        assert_eq!(borrowed.successor(&k), t.successor(&k));
+        assert_eq!(borrowed.predecessor(&k), t.predecessor(&k));
+    }
+
+    let (start, end) = (1 << 30, 1 << 31);
+    let range: Vec<_> = borrowed.range(start..end).collect();
+    let mut expected = Vec::new();
+    let mut next = t.ceiling(&start);
+    while let Some((k, v)) = next.filter(|(k, _)| *k < end) {
+        expected.push((k, v));
+        next = t.successor(&k);
+    }
+    assert_eq!(range, expected);
+    assert_eq!(borrowed.range(..).count(), t.len());
+}
+
+#[test]
+fn frozen_small_universe_and_empty() {
+    let mut t = VebTreeMap::<u64, ()>::with_universe_bits(8);
+    t.insert(3, ());
+    t.insert(200, ());
+    let frozen = FrozenVebTree::from_map(&t);
+    assert_eq!(frozen.universe_bits(), 8);
+    assert_eq!(frozen.get(&200), Some(()));
+    assert_eq!(frozen.get(&1000), None);
+    assert_eq!(frozen.successor(&3), Some((200, ())));
+
+    let empty = FrozenVebTree::<u16, u8>::from_sorted_iter([]);
+    assert!(empty.is_empty());
+    assert_eq!(empty.get(&1), None);
+    assert_eq!(empty.range(..).next(), None);
+}
+
+#[test]
+fn frozen_from_damaged_bytes() {
+    let frozen =
+        FrozenVebTree::from_sorted_iter((0..100u16).map(|k| (k * 613, k)));
+    let bytes = frozen.as_bytes();
+    assert!(matches!(
+        FrozenVebTree::<u16, u16, _>::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(SnapshotError::Truncated)
+    ));
+    assert!(matches!(
+        FrozenVebTree::<u32, u16, _>::from_bytes(bytes),
+        Err(SnapshotError::KeyWidth { .. })
+    ));
+    assert!(matches!(
+        FrozenVebTree::<u16, u32, _>::from_bytes(bytes),
+        Err(SnapshotError::ValueWidth { .. })
+    ));
+
+    // Damage can give wrong answers, but not panic.
+    for i in (40..bytes.len()).step_by(5) {
+        let mut damaged = bytes.to_vec();
+        damaged[i] ^= 0xa5;
+        let damaged =
+            FrozenVebTree::<u16, u16, _>::from_bytes(damaged.as_slice())
+                .unwrap();
+        for k in (0..u16::MAX).step_by(4099) {
+            damaged.get(&k);
+            damaged.successor(&k);
+            damaged.predecessor(&k);
+        }
+        damaged.range(..).take(200).count();
+    }
+}
+
+#[test]
+fn elias_fano_matches_map() {
+    let mut t = VebTreeMap::<u32, ()>::new();
+    for k in (0..3000u32).map(|k| k.wrapping_mul(2_654_435_761) >> 4) {
+        t.insert(k, ());
+    }
+    let set = EliasFanoSet::from_map(&t);
+    assert_eq!(set.len(), t.len());
+    assert!(set.size_in_bytes() < t.len() * 4);
+    let keys: Vec<u32> = set.iter().collect();
+    for (i, k) in keys.iter().enumerate() {
+        assert_eq!(set.select(i), Some(*k));
+        assert_eq!(set.rank(k), i);
+        assert!(set.contains(k));
+    }
+    assert_eq!(set.select(keys.len()), None);
+    for k in (0..4000u32).map(|k| k.wrapping_mul(1_327_217_885) >> 4) {
+        assert_eq!(set.contains(&k), t.get(&k).is_some());
+        assert_eq!(set.successor(&k), t.successor(&k).map(|(k, _)| k));
+        assert_eq!(set.predecessor(&k), t.predecessor(&k).map(|(k, _)| k));
+    }
+    let round_trip = VebTreeMap::from(&set);
+    assert_eq!(round_trip.len(), t.len());
+    assert_eq!(VebTreeMap::min(&round_trip), VebTreeMap::min(&t));
+    assert_eq!(VebTreeMap::max(&round_trip), VebTreeMap::max(&t));
+}
+
+#[test]
+fn elias_fano_edges() {
+    let empty = EliasFanoSet::<u64>::from_sorted_iter([]);
+    assert!(empty.is_empty());
+    assert_eq!(empty.successor(&0), None);
+    assert_eq!(empty.predecessor(&u64::MAX), None);
+    assert_eq!(empty.rank(&u64::MAX), 0);
+
+    let set = EliasFanoSet::from_sorted_iter([0, 1, u64::MAX - 1, u64::MAX]);
+    assert_eq!(set.successor(&1), Some(u64::MAX - 1));
+    assert_eq!(set.successor(&u64::MAX), None);
+    assert_eq!(set.predecessor(&(u64::MAX - 1)), Some(1));
+    assert_eq!(set.predecessor(&0), None);
+    assert_eq!(set.rank(&u64::MAX), 3);
+    assert!(set.contains(&u64::MAX));
+    assert!(!set.contains(&2));
+
+    let single = EliasFanoSet::from_sorted_iter([u64::MAX]);
+    assert_eq!(single.select(0), Some(u64::MAX));
+    assert_eq!(single.predecessor(&u64::MAX), None);
+    assert_eq!(single.successor(&5), Some(u64::MAX));
+}
+
+#[test]
+fn elias_fano_clustered() {
+    // A few keys spread over the universe, then a long run.  The spread keys
+    // leave long runs of zeros between their ones, and the run leaves a long
+    // run of ones, which the select indexes have to store one by one.
+    for spread in [300u64, 1000] {
+        let keys: Vec<u64> = (0..spread)
+            .map(|k| k * ((1 << 31) / spread))
+            .chain((1 << 31..).take(40_000))
+            .collect();
+        let set = EliasFanoSet::from_sorted_iter(keys.iter().copied());
+        assert!(set.iter().eq(keys.iter().copied()));
+        for (i, k) in keys.iter().enumerate().step_by(7) {
+            assert_eq!(set.select(i), Some(*k));
+            assert_eq!(set.rank(k), i);
+            assert_eq!(set.successor(k), keys.get(i + 1).copied());
+        }
+        assert_eq!(set.select(keys.len()), None);
+    }
+}
+
+#[test]
+#[should_panic(expected = "keys must be strictly increasing")]
+fn elias_fano_unsorted() {
+    EliasFanoSet::<u32>::from_sorted_iter([3, 3]);
+}
+
+#[cfg(feature = "std")]
+fn roaring(keys: impl IntoIterator<Item = u32>) -> Vec<u8> {
+    let t = VebTreeMap::from_sorted_iter(keys.into_iter().map(|k| (k, ())));
+    let mut bytes = Vec::new();
+    t.write_roaring(&mut bytes).unwrap();
+    let read = VebTreeMap::read_roaring(bytes.as_slice()).unwrap();
+    assert_eq!(read.len(), t.len());
+    let mut next = VebTreeMap::min(&t);
+    while let Some((k, _)) = next {
+        assert_eq!(read.get(&k), Some(()));
+        next = t.successor(&k);
+    }
+    bytes
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn roaring_fixtures() {
+    assert_eq!(roaring([]), [0x3a, 0x30, 0, 0, 0, 0, 0, 0]);
+    // One array container.
+    assert_eq!(
+        roaring([1, 2, 3]),
+        [
+            0x3a, 0x30, 0, 0, 1, 0, 0, 0, 0, 0, 2, 0, 16, 0, 0, 0, 1, 0, 2, 0,
+            3, 0
+        ]
+    );
+    // Array containers for different high bits.
+    assert_eq!(
+        roaring([5, (1 << 16) | 7, (3 << 16) | 0xffff]),
+        [
+            0x3a, 0x30, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0,
+            32, 0, 0, 0, 34, 0, 0, 0, 36, 0, 0, 0, 5, 0, 7, 0, 0xff, 0xff
+        ]
+    );
+    // One run container, without offsets.
+    assert_eq!(
+        roaring(0..1000),
+        [0x3b, 0x30, 0, 0, 1, 0, 0, 0xe7, 3, 1, 0, 0, 0, 0xe7, 3]
+    );
+    // Enough run containers for offsets.
+    let mut expected = vec![0x3b, 0x30, 3, 0, 0x0f];
+    for high in 0..4 {
+        expected.extend([high, 0, 9, 0]);
+    }
+    for offset in [37, 43, 49, 55] {
+        expected.extend([offset, 0, 0, 0]);
+    }
+    for _ in 0..4 {
+        expected.extend([1, 0, 0, 0, 9, 0]);
+    }
+    assert_eq!(
+        roaring((0..4).flat_map(|high| (0..10).map(move |k| (high << 16) | k))),
+        expected
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn roaring_bitset_and_mixed() {
+    // Too many keys for an array, with too many runs.
+    let bytes = roaring((0..20_000).step_by(2));
+    assert_eq!(bytes.len(), 16 + 8192);
+    assert_eq!(bytes[16..24], [0x55; 8]);
+
+    let keys = (0..20_000)
+        .step_by(2)
+        .chain(70_000..80_000)
+        .chain([1 << 20, u32::MAX]);
+    roaring(keys);
+    roaring([0, u32::MAX]);
+    roaring(0xffff_0000..=u32::MAX);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn roaring_rejects_bad_data() {
+    let bytes = roaring([5, (1 << 16) | 7, (3 << 16) | 0xffff]);
+    let read = |bytes: &[u8]| VebTreeMap::read_roaring(bytes);
+    assert!(matches!(
+        read(b"VEBT\0\0\0\0"),
+        Err(SnapshotError::BadMagic)
+    ));
+    assert!(matches!(
+        read(&bytes[..bytes.len() - 1]),
+        Err(SnapshotError::Truncated)
+    ));
+    let mut damaged = bytes.clone();
+    damaged[20] = 33;
+    assert!(matches!(read(&damaged), Err(SnapshotError::Corrupt(_))));
+    let mut damaged = bytes.clone();
+    damaged[12] = 0;
+    assert!(matches!(read(&damaged), Err(SnapshotError::Corrupt(_))));
+
+    // A run past the end of the container.
+    let runs = [0x3b, 0x30, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0xff, 0xff, 1, 0];
+    assert!(matches!(read(&runs), Err(SnapshotError::Corrupt(_))));
+}
+
+#[test]
+fn persistent_versions_are_independent() {
+    let mut t = PersistentVebTreeMap::<u32, u32>::new();
+    for k in (0..1000u32).map(|k| k.wrapping_mul(2_654_435_761)) {
+        t.insert(k, k);
+    }
+    let before = t.clone();
+    for k in (0..500u32).map(|k| k.wrapping_mul(2_654_435_761)) {
+        t.remove(&k);
+    }
+    t.insert(7, 1);
+    let updated = t.update(7, 2).without(&u32::MAX);
+
+    assert_eq!(before.len(), 1000);
+    assert_eq!(t.len(), 501);
+    assert_eq!(before.get(&7), None);
+    assert_eq!(t.get(&7), Some(1));
+    assert_eq!(updated.get(&7), Some(2));
+    for (i, k) in (0..1000u32)
+        .map(|k| k.wrapping_mul(2_654_435_761))
+        .enumerate()
+    {
+        assert_eq!(before.get(&k), Some(k));
+        assert_eq!(t.get(&k), (i >= 500).then_some(k));
+    }
+    let keys: Vec<u32> = before.iter().map(|(k, _)| k).collect();
+    assert!(keys.is_sorted());
+    assert_eq!(keys.len(), 1000);
+    assert_eq!(before.successor(&keys[10]), Some((keys[11], keys[11])));
+    assert_eq!(before.predecessor(&keys[10]), Some((keys[9], keys[9])));
+
+    // Removing a missing key leaves every version as it was.
+    let mut missing = before.clone();
+    missing.remove(&keys[10].wrapping_add(1));
+    assert_eq!(missing.len(), 1000);
+    assert!(missing.iter().eq(before.iter()));
+}
+
+/// A key whose hash only depends on its low bits, so that keys collide in
+/// the trie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CollidingKey(u32);
+
+impl core::hash::Hash for CollidingKey {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        state.write_u32(self.0 % 3);
+    }
+}
+
+#[test]
+fn hamt_versions_and_collisions() {
+    let mut t = hamt::Hamt::new();
+    for k in 0..1000u32 {
+        assert_eq!(t.insert(k, k), None);
+    }
+    let before = t.clone();
+    for k in (0..1000u32).step_by(2) {
+        assert_eq!(t.remove(&k), Some(k));
+    }
+    *t.get_mut(&1).unwrap() = 10;
+    assert_eq!(t.insert(3, 30), Some(3));
+    assert_eq!(t.remove(&0), None);
+    for k in 0..1000u32 {
+        assert_eq!(before.get(&k), Some(&k));
+    }
+    assert_eq!(t.get(&1), Some(&10));
+    assert_eq!(t.get(&3), Some(&30));
+    assert_eq!(t.get(&998), None);
+    for k in (0..1000u32).skip(5).step_by(2) {
+        assert_eq!(t.remove(&k), Some(k));
+    }
+    t.remove(&1);
+    t.remove(&3);
+    assert!(t.is_empty());
+
+    let mut t = hamt::Hamt::new();
+    for k in 0..10 {
+        t.insert(CollidingKey(k), k);
+    }
+    assert_eq!(t.insert(CollidingKey(4), 40), Some(4));
+    for k in 0..10 {
+        let expected = if k == 4 { 40 } else { k };
+        assert_eq!(t.get(&CollidingKey(k)), Some(&expected));
+    }
+    for k in 0..10 {
+        assert!(t.remove(&CollidingKey(k)).is_some());
+        assert_eq!(t.get(&CollidingKey(k)), None);
+        assert_eq!(t.get(&CollidingKey(9)).is_some(), k < 9);
+    }
+    assert!(t.is_empty());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn concurrent_threads_share_tree() {
+    let t = ConcurrentVebTreeMap::<u32, u32>::new();
+    std::thread::scope(|s| {
+        for thread in 0..4u32 {
+            let t = &t;
+            s.spawn(move || {
+                for k in (0..2000u32).map(|k| k.wrapping_mul(2_654_435_761)) {
+                    if k % 4 == thread {
+                        t.insert(k, k);
+                    }
+                }
+                for k in (0..2000u32).map(|k| k.wrapping_mul(2_654_435_761)) {
+                    if k % 4 == thread && k % 3 == 0 {
+                        t.remove(&k);
+                    }
+                }
+            });
+        }
+    });
+
+    let mut expected = VebTreeMap::<u32, u32>::new();
+    for k in (0..2000u32).map(|k| k.wrapping_mul(2_654_435_761)) {
+        if k % 3 != 0 {
+            expected.insert(k, k);
+        }
+    }
+    assert_eq!(t.len(), expected.len());
+    assert_eq!(t.min(), VebTreeMap::min(&expected));
+    assert_eq!(t.max(), VebTreeMap::max(&expected));
+    for k in (0..4000u32).map(|k| k.wrapping_mul(1_327_217_885)) {
+        assert_eq!(t.get(&k), expected.get(&k));
+        assert_eq!(t.successor(&k), expected.successor(&k));
+        assert_eq!(t.predecessor(&k), expected.predecessor(&k));
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn concurrent_skips_empty_shards() {
+    let t = ConcurrentVebTreeMap::<u32, ()>::with_shard_bits(12);
+    t.insert(5, ());
+    t.insert(u32::MAX, ());
+    t.insert(1 << 31, ());
+    assert_eq!(t.successor(&5), Some((1 << 31, ())));
+    assert_eq!(t.predecessor(&(1 << 31)), Some((5, ())));
+    t.remove(&(1 << 31));
+    assert_eq!(t.successor(&5), Some((u32::MAX, ())));
+    assert_eq!(t.predecessor(&u32::MAX), Some((5, ())));
+    assert_eq!(t.successor(&u32::MAX), None);
+    assert_eq!(t.predecessor(&5), None);
+
+    let single = ConcurrentVebTreeMap::<u8, ()>::with_shard_bits(0);
+    single.insert(200, ());
+    assert_eq!(single.min(), Some((200, ())));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn concurrent_successor_sees_earlier_inserts() {
+    // Whenever the key in the last shard is there, so is a smaller key in an
+    // earlier shard, so a search from the start must never return it.
+    let t = ConcurrentVebTreeMap::<u32, ()>::with_shard_bits(12);
+    let last = 4000 << 20;
+    let done = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..200_000u32 {
+                let earlier = (i % 3999 + 1) << 20;
+                t.insert(earlier, ());
+                t.insert(last, ());
+                t.remove(&last);
+                t.remove(&earlier);
+            }
+            done.store(true, std::sync::atomic::Ordering::Release);
+        });
+        s.spawn(|| {
+            while !done.load(std::sync::atomic::Ordering::Acquire) {
+                assert_ne!(t.successor(&0), Some((last, ())));
+                assert_ne!(t.min(), Some((last, ())));
+            }
+        });
+    });
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn shard_key_top_bits() {
+    assert_eq!(0xabcd_u16.top_bits(0), 0);
+    assert_eq!(0xabcd_u16.top_bits(4), 0xa);
+    assert_eq!(0xabcd_u16.top_bits(8), 0xab);
+    assert_eq!(u64::MAX.top_bits(12), 0xfff);
+    assert_eq!((1u128 << 120).top_bits(12), 1 << 4);
+}
+
+#[cfg(feature = "std")]
+#[test]
+#[should_panic(expected = "shards must split the root's clusters")]
+fn concurrent_too_many_shards() {
+    ConcurrentVebTreeMap::<u8, ()>::with_shard_bits(5);
+}
+
+#[cfg(feature = "rcu")]
+#[test]
+fn rcu_readers_see_published_versions() {
+    let cell = RcuCell::new(PersistentVebTreeMap::<u32, u32>::new());
+    std::thread::scope(|s| {
+        for _ in 0..3 {
+            let mut reader = cell.reader();
+            s.spawn(move || {
+                let mut last_len = 0;
+                while last_len < 1000 {
+                    // Each version has every key up to its length.
+                    let map = reader.get();
+                    assert!(map.len() >= last_len);
+                    last_len = map.len();
+                    if let Some((max, _)) = map.max() {
+                        assert_eq!(usize::try_from(max).unwrap() + 1, last_len);
+                    }
+                }
+            });
+        }
+        for batch in 0..100u32 {
+            cell.update_clone(|map| {
+                for k in batch * 10..(batch + 1) * 10 {
+                    map.insert(k, k);
+                }
+            });
+        }
+    });
+    assert_eq!(cell.version(), 100);
+    assert_eq!(cell.load().len(), 1000);
+
+    let mut reader = cell.reader();
+    let old = cell.load();
+    cell.publish(PersistentVebTreeMap::new());
+    assert_eq!(reader.cached().len(), 1000);
+    assert!(reader.get().is_empty());
+    assert_eq!(reader.version(), 101);
+    assert_eq!(old.len(), 1000);
+
+    let frozen = RcuCell::new(FrozenVebTree::<u32, u32>::from_sorted_iter([]));
+    frozen.update(|_| FrozenVebTree::from_sorted_iter([(1, 2)]));
+    assert_eq!(frozen.reader().get().get(&1), Some(2));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn rayon_build_and_iterate() {
+    use ::rayon::prelude::*;
+
+    let entries: Vec<(u32, u32)> = (0..5000u32)
+        .map(|k| k.wrapping_mul(2_654_435_761))
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .map(|k| (k, k / 2))
+        .collect();
+    let mut t = VebTreeMap::from_sorted_par_iter(entries.clone());
+    assert_eq!(t.len(), entries.len());
+    for (k, v) in &entries {
+        assert_eq!(t.get(k), Some(*v));
+        assert_eq!(t.successor(&(k + 1)), t.ceiling(&(k + 1)));
+    }
+    let expected = VebTreeMap::from_sorted_iter(entries.clone());
+    for k in (0..4000u32).map(|k| k.wrapping_mul(1_327_217_885)) {
+        assert_eq!(t.successor(&k), expected.successor(&k));
+        assert_eq!(t.predecessor(&k), expected.predecessor(&k));
+    }
+
+    let collected: Vec<(u32, u32)> =
+        t.par_iter().map(|(k, v)| (k, *v)).collect();
+    assert_eq!(collected, entries);
+
+    let keys: Vec<u32> = t.par_iter_mut().map(|(k, _)| k).collect();
+    assert!(keys.iter().eq(entries.iter().map(|(k, _)| k)));
+    t.par_iter_mut().for_each(|(k, v)| *v = k);
+    let owned: Vec<(u32, u32)> = t.into_par_iter().collect();
+    assert!(
+        owned
+            .iter()
+            .copied()
+            .eq(entries.iter().map(|(k, _)| (*k, *k)))
+    );
+
+    // Still a working tree after the clusters were stitched together.
+    let mut t = VebTreeMap::from_sorted_par_iter(entries.clone());
+    for (k, _) in entries.iter().step_by(2) {
+        t.remove(k);
+    }
+    t.insert(7, 7);
+    assert_eq!(t.len(), entries.len() / 2 + 1);
+    assert_eq!(t.par_iter().count(), t.len());
+
+    for entries in [vec![], vec![(3u8, ())], vec![(3, ()), (200, ())]] {
+        let t = VebTreeMap::from_sorted_par_iter(entries.clone());
+        assert_eq!(t.len(), entries.len());
+        assert_eq!(
+            t.par_iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>(),
+            entries
+        );
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+#[should_panic(expected = "keys must be strictly increasing")]
+fn rayon_build_unsorted() {
+    VebTreeMap::from_sorted_par_iter(vec![(1u16, ()), (5, ()), (5, ())]);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn rayon_build_with_universe_bits() {
+    use ::rayon::prelude::*;
+
+    let entries: Vec<(u64, u64)> = (0..1000).map(|k| (k * 7, k)).collect();
+    let t = VebTreeMap::from_sorted_par_iter_with_universe_bits(
+        16,
+        entries.clone(),
+    );
+    assert_eq!(t.universe_bits(), 16);
+    assert_eq!(t.validate(), Ok(()));
+    let collected: Vec<(u64, u64)> =
+        t.par_iter().map(|(k, v)| (k, *v)).collect();
+    assert_eq!(collected, entries);
+    assert_eq!(t.successor(&700), Some((707, 101)));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+#[should_panic(expected = "key must fit in the tree's universe")]
+fn rayon_build_outside_universe() {
+    VebTreeMap::from_sorted_par_iter_with_universe_bits(
+        8,
+        vec![(1u32, ()), (256, ())],
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn try_reserve_covers_inserts() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    assert_eq!(t.try_insert(5, 5), Ok(None));
+    assert_eq!(t.try_insert(5, 6), Ok(Some(5)));
+    t.try_reserve(1000).unwrap();
+    let capacities = t.arena.capacities();
+    for k in (0..1000u32).map(|k| k.wrapping_mul(2_654_435_761)) {
+        t.insert(k, k);
+    }
+    assert_eq!(t.arena.capacities(), capacities);
+
+    assert!(t.try_reserve(usize::MAX).is_err());
+    assert_eq!(t.len(), 1001);
+    assert_eq!(t.get(&5), Some(6));
+}
+
+#[test]
+fn keys_outside_universe() {
+    let mut t = VebTreeMap::<u32, u32>::with_universe_bits(8);
+    for k in [3, 100, 255] {
+        t.insert(k, k);
+    }
+    assert_eq!(t.get(&300), None);
+    assert_eq!(t.successor(&300), None);
+    assert_eq!(t.ceiling(&300), None);
+    assert_eq!(t.predecessor(&300), Some((255, 255)));
+    assert_eq!(t.floor(&u32::MAX), Some((255, 255)));
+    assert_eq!(t.nearest(&300, Tie::Lower), Some((255, 255)));
+    t.remove(&300);
+    assert_eq!(t.len(), 3);
+
+    let err = VebError::OutOfUniverse {
+        key: 256,
+        max_key: 255,
+    };
+    assert_eq!(t.checked_insert(256, 0), Err(err.clone()));
+    assert_eq!(t.checked_get(&256), Err(err.clone()));
+    assert_eq!(t.checked_remove(&256), Err(err.clone()));
+    assert_eq!(t.checked_successor(&256), Err(err.clone()));
+    assert_eq!(t.checked_predecessor(&256), Err(err));
+    assert_eq!(t.checked_insert(7, 7), Ok(None));
+    assert_eq!(t.checked_get(&7), Ok(Some(7)));
+    assert_eq!(t.checked_successor(&7), Ok(Some((100, 100))));
+    assert_eq!(t.checked_floor(&99), Ok(Some((7, 7))));
+    assert_eq!(t.checked_remove(&7), Ok(()));
+    assert_eq!(t.len(), 3);
+    assert_eq!(
+        t.check_key(&1000).unwrap_err().to_string(),
+        "key 1000 is outside the tree's universe, which ends at 255"
+    );
+}
+
+#[test]
+fn stats_count_nodes() {
+    let mut t = VebTreeMap::<u16, u16>::new();
+    assert_eq!(t.stats().nodes_per_level, Vec::<usize>::new());
+    assert_eq!(t.stats().clusters, 0);
+    for k in (0..2000u16).map(|k| k.wrapping_mul(40_503)) {
+        t.insert(k, k);
+    }
+    let stats = t.stats();
+    assert_eq!(stats.nodes_per_level[0], 1);
+    assert_eq!(stats.nodes_per_level.len(), 3);
+    assert_eq!(stats.clusters + 1, stats.arenas[0].nodes);
+    assert_eq!(stats.clusters, stats.arenas[0].cluster_table_len);
+    assert!(stats.arenas.len() > 1);
+    for arena in &stats.arenas {
+        assert!(arena.nodes + arena.free_nodes <= arena.node_capacity);
+        assert!(arena.cluster_table_len <= arena.cluster_table_capacity);
+    }
+    assert_eq!(
+        stats.heap_bytes,
+        stats
+            .arenas
+            .iter()
+            .map(|arena| arena.heap_bytes)
+            .sum::<usize>()
+    );
+    assert!(stats.heap_bytes >= 2000 * 2 * size_of::<u16>());
+
+    for k in 0..2000u16 {
+        t.remove(&k.wrapping_mul(40_503));
+    }
+    let emptied = t.stats();
+    assert_eq!(emptied.clusters, 0);
+    assert_eq!(emptied.arenas[0].nodes, 0);
+}
+
+#[test]
+fn dot_draws_nodes() {
+    let mut t = VebTreeMap::<u8, &str>::new();
+    assert_eq!(
+        t.dot().to_string(),
+        "digraph veb {\n    node [shape=box];\n}\n"
+    );
+    for k in [1, 17, 18, 33, 200] {
+        t.insert(k, "\"quoted\"");
+    }
+    let dot = t.dot().to_string();
+    assert!(dot.contains("a0n0 -> a0n2 [label=\"2\"];"));
+    assert!(dot.contains(
+        "a0n2 [label=\"a0n2\\ncluster bits: 2\\nmin: 1 => \
+         \\\"\\\\\\\"quoted\\\\\\\"\\\"\"];"
+    ));
+    assert_eq!(
+        t.dot().max_depth(0).to_string().lines().collect::<Vec<_>>()[3..],
+        [
+            "    a0n0 -> a1n0 [style=dashed, label=\"summary\"];",
+            "    a1n0 [label=\"a1n0\\ncluster bits: 2\\nmin: 1\\nmax: 2\"];",
+            "    a0n0_more [label=\"...\", shape=plaintext];",
+            "    a0n0 -> a0n0_more;",
+            "}",
+        ]
+    );
+}
+
+#[test]
+fn standard_traits_compare_entries() {
+    use alloc::collections::BTreeMap;
+    use core::hash::BuildHasher;
+
+    let a = VebTreeMap::from([(5u32, 50), (1, 10), (9, 90)]);
+    let mut b = VebTreeMap::with_universe_bits(8);
+    for k in [9, 200, 1, 5] {
+        b.insert(k, k * 10);
+    }
+    b.remove(&200);
+    assert_eq!(a, b);
+    let state = std::hash::RandomState::new();
+    assert_eq!(state.hash_one(&a), state.hash_one(&b));
+    assert_eq!(a[&5], 50);
+    assert_eq!(format!("{a:?}"), "{1: 10, 5: 50, 9: 90}");
+    assert!(format!("{:?}", a.debug_structure()).contains("arena"));
+
+    b.insert(9, 91);
+    assert_ne!(a, b);
+    assert!(a < b);
+    assert_eq!(
+        a.partial_cmp(&VebTreeMap::from([(1, 10), (5, 50)])),
+        Some(Ordering::Greater)
+    );
+    assert_eq!(a.cmp(&b), Ordering::Less);
+    assert_eq!(VebTreeMap::min(&a), Some((1, 10)));
+    let mut sorted = vec![b.clone(), a.clone()];
+    sorted.sort();
+    assert_eq!(sorted, [a.clone(), b.clone()]);
+
+    let btree: BTreeMap<u32, u32> = b.clone().into();
+    assert_eq!(btree, BTreeMap::from([(1, 10), (5, 50), (9, 91)]));
+    assert_eq!(VebTreeMap::from(btree), b);
+}
+
+#[test]
+#[should_panic(expected = "key must be in the tree")]
+fn index_missing_key() {
+    let t = VebTreeMap::from([(1u8, 1)]);
+    let _ = t[&2];
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn operation_stats_stay_within_lg_lg_u() {
+    let mut t = VebTreeMap::<u64, u64>::new();
+    let keys: Vec<u64> = (0..5000u64)
+        .map(|k| k.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .collect();
+    let mut finger = Finger::new();
+    for (i, &k) in keys.iter().enumerate() {
+        match i % 3 {
+            0 => t.insert(k, k),
+            1 => t.insert_fixed(k, k),
+            _ => t.insert_near(&mut finger, k, k),
+        };
+    }
+    for &k in &keys {
+        t.get(&k);
+        t.successor(&k);
+        t.predecessor(&k);
+        t.ceiling(&k);
+        t.floor(&k);
+        t.nearest(&k, Tie::Lower);
+        t.get_fixed(&k);
+        t.successor_fixed(&k);
+        t.get_near(&mut finger, &k);
+        t.successor_near(&mut finger, &k);
+        t.predecessor_near(&mut finger, &k);
+    }
+    t.get_many(&keys);
+    t.successor_many(&keys);
+    let mut cursor = t.lower_bound(Bound::Included(&0));
+    while cursor.move_next().is_some() {}
+    while cursor.move_prev().is_some() {}
+    for &k in &keys {
+        t.remove(&k);
+    }
+    let stats = t.operation_stats();
+    // lg lg u for 64-bit keys.
+    let levels = 6;
+    let n = keys.len();
+    let inserts = |i| (i..n).step_by(3).len();
+    for (counts, calls) in [
+        (stats.get, n),
+        (stats.insert, inserts(0)),
+        (stats.remove, n),
+        (stats.successor, n),
+        (stats.predecessor, n),
+        (stats.ceiling, n),
+        (stats.floor, n),
+        (stats.nearest, n),
+        (stats.get_fixed, n),
+        (stats.insert_fixed, inserts(1)),
+        (stats.successor_fixed, n),
+        (stats.get_near, n),
+        (stats.insert_near, inserts(2)),
+        (stats.successor_near, n),
+        (stats.predecessor_near, n),
+        (stats.cursor_seek, 1),
+        (stats.cursor_move, 2 * n),
+    ] {
+        assert_eq!(counts.calls, calls);
+        assert!(counts.node_visits >= counts.calls);
+        assert!(counts.max_depth <= levels);
+        // Each level recurses into either a cluster or a summary, except for
+        // calls that finish in constant time.
+        assert!(counts.node_visits <= 2 * levels * counts.calls);
+        assert!(counts.probes <= counts.node_visits);
+    }
+    // A batch counts once, with the work of all of its keys.
+    for counts in [stats.get_many, stats.successor_many] {
+        assert_eq!(counts.calls, 1);
+        assert!(counts.max_depth <= levels * n);
+        assert!(counts.node_visits <= 2 * levels * n);
+        assert!(counts.probes <= counts.node_visits);
+    }
+    assert_eq!(stats.get.summary_descents, 0);
+    assert_eq!(stats.get_fixed.summary_descents, 0);
+    assert_eq!(stats.get_near.summary_descents, 0);
+    assert_eq!(stats.get_many.summary_descents, 0);
+
+    t.reset_operation_stats();
+    assert_eq!(t.operation_stats(), OperationStats::default());
+}
+
+#[test]
+fn validate_finds_broken_invariants() {
+    let keys = (0..200u32).map(|k| k.wrapping_mul(2_654_435_761));
+    let t = VebTreeMap::from_sorted_iter({
+        let mut keys: Vec<_> = keys.collect();
+        keys.sort_unstable();
+        keys.into_iter().map(|k| (k, k))
+    });
+    assert_eq!(t.validate(), Ok(()));
+    assert_eq!(VebTreeMap::<u8, ()>::new().validate(), Ok(()));
+
+    let mut broken = t.clone();
+    broken.len += 1;
+    assert_eq!(
+        broken.validate(),
+        Err(InvariantViolation::Length {
+            expected: 201,
+            found: 200
+        })
+    );
+
+    let root = t.root.unwrap();
+    let mut broken = t.clone();
+    broken.arena.node_mut(root).max = None;
+    assert_eq!(
+        broken.validate(),
+        Err(InvariantViolation::ClustersWithoutMax)
+    );
+
+    let mut broken = t.clone();
+    broken.arena.node_mut(root).min = Some((u32::MAX, 0));
+    assert_eq!(broken.validate(), Err(InvariantViolation::MaxNotAboveMin));
+
+    let mut broken = t.clone();
+    broken.arena.node_mut(root).summary = None;
+    assert_eq!(broken.validate(), Err(InvariantViolation::Summary));
+
+    // The second smallest key is the min of the first cluster.
+    let (second, _) = t.successor(&VebTreeMap::min(&t).unwrap().0).unwrap();
+    let mut broken = t.clone();
+    broken.arena.node_mut(root).min = Some((second, second));
+    assert_eq!(
+        broken.validate(),
+        Err(InvariantViolation::ClusterKeyOutsideMinMax)
+    );
+
+    let mut broken = VebTreeMap::<u32, u32>::with_universe_bits(8);
+    broken.insert(1, 1);
+    broken.arena.node_mut(broken.root.unwrap()).min = Some((256, 256));
+    assert_eq!(
+        broken.validate(),
+        Err(InvariantViolation::KeyOutsideUniverse)
+    );
+}
+
+#[cfg(feature = "safety_checks")]
+#[test]
+#[should_panic(
+    expected = r#"after the operations ["insert(1, 1)", "insert(2, 2)", "insert(3, 3)", "insert(4, 4)"]"#
+)]
+fn safety_checks_report_operations() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    for k in 1..4 {
+        t.insert(k, k);
+    }
+    // Losing the max loses 3, which the next insert finds next to 4.
+    let root = t.root.unwrap();
+    t.arena.node_mut(root).max = None;
+    t.insert(4, 4);
+}
+
+#[cfg(feature = "safety_checks")]
+#[test]
+fn safety_checks_keep_recent_operations() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    t.set_safety_log_limit(Some(32));
+    for k in 0..100 {
+        t.insert(k, k);
+    }
+    // A value that changes behind the tree's back is caught by the next
+    // change, even to another key.
+    *t.arena.get_mut(t.root.unwrap(), &50).unwrap() = 0;
+    let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        t.remove(&99);
+    }))
+    .unwrap_err();
+    let message = err.downcast_ref::<String>().unwrap();
+    assert!(message.contains("a value doesn't match"), "{message}");
+    assert!(
+        message.contains("after 101 operations, the last 32 were"),
+        "{message}"
+    );
+    assert!(message.contains(r#"["insert(69, 69)", "#), "{message}");
+    assert!(
+        message.ends_with(r#""insert(99, 99)", "remove(99)"]"#),
+        "{message}"
+    );
+}
+
+#[cfg(feature = "safety_checks")]
+#[test]
+fn safety_checks_follow_cursor_changes() {
+    let mut t = VebTreeMap::<u32, u32>::new();
+    for k in 0..10 {
+        t.insert(k, k);
+    }
+    let mut cursor = t.lower_bound_mut(Bound::Included(&5));
+    *cursor.peek_next().unwrap().1 = 50;
+    cursor.remove_prev();
+    assert_eq!(t.get(&5), Some(50));
+    t.insert(20, 20);
+}
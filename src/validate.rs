@@ -0,0 +1,96 @@
+//! Checking that a tree's structure is consistent, for fuzzers and for trees
+//! built from untrusted data.
+
+use core::error::Error;
+use core::fmt::{self, Debug};
+use core::hash::Hash;
+
+use alloc::vec::Vec;
+
+use crate::{VebKey, VebTreeMap};
+
+/// The invariant of a van Emde Boas tree that [`VebTreeMap::validate`] found
+/// broken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvariantViolation {
+    /// A node has no entries, but empty clusters must be released.
+    EmptyNode,
+    /// A node's max isn't greater than its min.
+    MaxNotAboveMin,
+    /// A key doesn't fit in the universe of its node.
+    KeyOutsideUniverse,
+    /// A node's cluster size isn't the square root of its universe.
+    ClusterSize,
+    /// A node with a single entry has clusters, instead of only a min.
+    ClustersWithoutMax,
+    /// A key in a cluster isn't between the min and the max of the cluster's
+    /// parent, which must not be stored in clusters.
+    ClusterKeyOutsideMinMax,
+    /// A node's summary doesn't hold exactly its non-empty clusters.
+    Summary,
+    /// A node is linked from more than one place, is linked but free, or
+    /// isn't linked at all.
+    Links,
+    /// The tree's length isn't its number of entries.
+    Length { expected: usize, found: usize },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::EmptyNode => f.write_str("a node is empty"),
+            InvariantViolation::MaxNotAboveMin => {
+                f.write_str("a node's max isn't greater than its min")
+            }
+            InvariantViolation::KeyOutsideUniverse => {
+                f.write_str("a key doesn't fit in its node's universe")
+            }
+            InvariantViolation::ClusterSize => {
+                f.write_str("a node has the wrong cluster size")
+            }
+            InvariantViolation::ClustersWithoutMax => {
+                f.write_str("a node with a single entry has clusters")
+            }
+            InvariantViolation::ClusterKeyOutsideMinMax => f.write_str(
+                "a key in a cluster isn't between its parent's min and max",
+            ),
+            InvariantViolation::Summary => {
+                f.write_str("a summary doesn't match the non-empty clusters")
+            }
+            InvariantViolation::Links => {
+                f.write_str("a node is linked more than once or not at all")
+            }
+            InvariantViolation::Length { expected, found } => {
+                write!(f, "tree has {found} entries but a length of {expected}")
+            }
+        }
+    }
+}
+
+impl Error for InvariantViolation {}
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Check every invariant of the tree's structure: each node's min and
+    /// max, the universe of every key, the summaries of the non-empty
+    /// clusters, and the links between nodes.  Runs in O(n lg n) time.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        let roots: Vec<_> = self
+            .root
+            .iter()
+            .map(|root| (*root, self.max_size.clone()))
+            .collect();
+        let found = self.arena.validate(&roots)?.into_iter().sum();
+        if found != self.len {
+            return Err(InvariantViolation::Length {
+                expected: self.len,
+                found,
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Operations that treat the keys as a ring, where the minimum follows the
+//! maximum.
+
+use core::fmt::Debug;
+use core::hash::Hash;
+use core::iter::FusedIterator;
+
+use crate::{Finger, VebKey, VebTreeMap};
+
+impl<K, V> VebTreeMap<K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Get the successor of the given key, or the minimum when nothing is
+    /// greater than the key.  Runs in O(lg lg u) time.
+    pub fn successor_wrapping(&self, key: &K) -> Option<(K, V)> {
+        self.successor(key).or_else(|| self.min())
+    }
+
+    /// Get the predecessor of the given key, or the maximum when nothing is
+    /// less than the key.  Runs in O(lg lg u) time.
+    pub fn predecessor_wrapping(&self, key: &K) -> Option<(K, V)> {
+        self.predecessor(key).or_else(|| self.max())
+    }
+
+    /// Iterate over the entries from `start`, inclusive, to `end`, exclusive,
+    /// in order.  When `start` is greater than `end`, the range wraps around:
+    /// it goes up to the maximum and then continues from the minimum.  When
+    /// they're equal, the range is empty.
+    ///
+    /// Each step runs in O(lg lg u) time.
+    pub fn range_wrapping(&self, start: K, end: K) -> RangeWrapping<'_, K, V> {
+        let mut range = RangeWrapping {
+            map: self,
+            next: None,
+            wrapped: start < end,
+            end,
+            finger: Finger::new(),
+        };
+        if start != range.end {
+            let first = self
+                .root
+                .filter(|_| self.in_universe(&start))
+                .and_then(|root| self.arena.ceiling(root, &start));
+            range.next = range.check(first);
+        }
+        range
+    }
+}
+
+/// An iterator over a range of entries that can wrap around from the maximum
+/// to the minimum.  See [`VebTreeMap::range_wrapping`].
+#[derive(Clone)]
+pub struct RangeWrapping<'a, K, V>
+where
+    K: VebKey,
+{
+    map: &'a VebTreeMap<K, V>,
+    next: Option<(K, &'a V)>,
+    end: K,
+    /// Whether the range can't wrap anymore, so every key left in it is less
+    /// than `end`.
+    wrapped: bool,
+    finger: Finger<K>,
+}
+
+impl<K, V> Debug for RangeWrapping<'_, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RangeWrapping")
+            .field("map", &self.map)
+            .field("next", &self.next)
+            .field("end", &self.end)
+            .field("wrapped", &self.wrapped)
+            .field("finger", &self.finger)
+            .finish()
+    }
+}
+
+impl<'a, K, V> RangeWrapping<'a, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    /// Wrap around when running past the maximum, and stop at the end.
+    fn check(&mut self, entry: Option<(K, &'a V)>) -> Option<(K, &'a V)> {
+        let entry = match entry {
+            None if !self.wrapped => {
+                self.wrapped = true;
+                self.map.min_entry()
+            }
+            entry => entry,
+        };
+        entry.filter(|(k, _)| !self.wrapped || *k < self.end)
+    }
+}
+
+impl<'a, K, V> Iterator for RangeWrapping<'a, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.next.take()?;
+        let next = self.map.successor_entry_near(&mut self.finger, &key);
+        self.next = self.check(next);
+        Some((key, value))
+    }
+}
+
+impl<K, V> FusedIterator for RangeWrapping<'_, K, V>
+where
+    K: VebKey + Ord + Clone + Hash + Eq + Debug,
+    V: Clone + Debug,
+{
+}